@@ -1,10 +1,21 @@
 use crate::display::DownloadBar;
 use crate::utils;
+use lofty::picture::MimeType;
+use lofty::picture::Picture;
+use lofty::picture::PictureType;
 use std::fs;
 use std::io::{self, Read};
 use std::path::Path;
 use std::path::PathBuf;
 
+/// Size that cover art larger than `max_size` pixels is scaled down to, as a JPEG.
+#[derive(Debug, Clone, Copy, PartialEq)]
+pub struct ArtworkResize {
+    pub max_size: u32,
+    /// JPEG quality from 1 to 100.
+    pub quality: u8,
+}
+
 struct MimeMap;
 
 impl MimeMap {
@@ -33,7 +44,7 @@ fn read_file_to_vec(path: &Path) -> io::Result<Vec<u8>> {
     Ok(data)
 }
 
-fn hashed_url(url: &str) -> String {
+pub fn hashed_url(url: &str) -> String {
     use std::hash::Hasher;
     let mut hasher = fnv::FnvHasher::default();
     hasher.write(url.as_bytes());
@@ -41,6 +52,16 @@ fn hashed_url(url: &str) -> String {
     format!("{:x}", hash)
 }
 
+/// Hex-encoded SHA-1 of `data`, for keys that must be the same for every version of TaleCast.
+pub fn sha1_hex(data: &[u8]) -> String {
+    use sha1::Digest;
+
+    sha1::Sha1::digest(data)
+        .iter()
+        .map(|byte| format!("{:02x}", byte))
+        .collect()
+}
+
 fn cached_image(url: &str, ui: &DownloadBar) -> Option<Vec<u8>> {
     let hash = hashed_url(url);
     let path = utils::cache_dir().join(hash);
@@ -55,11 +76,11 @@ fn cached_image(url: &str, ui: &DownloadBar) -> Option<Vec<u8>> {
     image
 }
 
-async fn write_image(url: &str, ui: &DownloadBar) -> Option<()> {
+async fn write_image(client: &reqwest::Client, url: &str, ui: &DownloadBar) -> Option<()> {
     use std::io::Write;
 
     let hashed = hashed_url(url);
-    let response = match reqwest::get(url).await {
+    let response = match client.get(url).send().await {
         Ok(res) => {
             ui.log_info("connected to image url");
             res
@@ -89,15 +110,76 @@ async fn write_image(url: &str, ui: &DownloadBar) -> Option<()> {
     Some(())
 }
 
+/// The width and height of an image, read from its header.
+fn image_dimensions(data: &[u8]) -> Option<(u32, u32)> {
+    image::ImageReader::new(io::Cursor::new(data))
+        .with_guessed_format()
+        .ok()?
+        .into_dimensions()
+        .ok()
+}
+
+/// Scales the image down to fit in `resize.max_size` pixels, keeping the aspect ratio, and
+/// encodes it as a JPEG.
+fn scale_down(data: &[u8], resize: ArtworkResize) -> image::ImageResult<Vec<u8>> {
+    let image = image::load_from_memory(data)?.resize(
+        resize.max_size,
+        resize.max_size,
+        image::imageops::FilterType::Lanczos3,
+    );
+
+    let mut jpeg = vec![];
+    let encoder =
+        image::codecs::jpeg::JpegEncoder::new_with_quality(&mut jpeg, resize.quality.clamp(1, 100));
+    image.into_rgb8().write_with_encoder(encoder)?;
+    Ok(jpeg)
+}
+
+/// The cached image scaled down to fit `resize`, or `None` if it already fits or can't be resized.
+///
+/// The result is cached as well.
+async fn resized_image(url: &str, resize: ArtworkResize, ui: &DownloadBar) -> Option<Vec<u8>> {
+    let input = utils::cache_dir().join(hashed_url(url));
+    let data = read_file_to_vec(&input).ok()?;
+    let (width, height) = image_dimensions(&data)?;
+    if width.max(height) <= resize.max_size {
+        return None;
+    }
+
+    let key = format!("{}#{}q{}", url, resize.max_size, resize.quality);
+    let output = utils::cache_dir().join(format!("{}.jpg", hashed_url(&key)));
+    if let Ok(image) = read_file_to_vec(&output) {
+        return Some(image);
+    }
+
+    ui.log_debug(format!(
+        "resizing image from {}x{} to fit {} pixels",
+        width, height, resize.max_size
+    ));
+    match tokio::task::spawn_blocking(move || scale_down(&data, resize)).await {
+        Ok(Ok(image)) => {
+            let _ = fs::write(&output, &image);
+            Some(image)
+        }
+        Ok(Err(e)) => {
+            ui.log_warn(format!("failed to resize image: {}", e));
+            None
+        }
+        Err(_) => None,
+    }
+}
+
 pub async fn get_image(
+    client: &reqwest::Client,
     url: &str,
-    picture_type: id3::frame::PictureType,
+    picture_type: PictureType,
+    resize: Option<ArtworkResize>,
     ui: &DownloadBar,
-) -> Option<id3::frame::Frame> {
+) -> Option<Picture> {
     let data = match cached_image(url, ui) {
         Some(data) => data,
         None => {
-            write_image(url, ui).await?;
+            write_image(client, url, ui).await?;
             cached_image(url, ui)?
         }
     };
@@ -110,15 +192,72 @@ pub async fn get_image(
         }
     };
 
-    let pic = id3::frame::Picture {
-        data,
-        mime_type,
-        description: String::default(),
-        picture_type,
+    let resized = match resize {
+        Some(resize) => resized_image(url, resize, ui).await,
+        None => None,
+    };
+    let (data, mime_type) = match resized {
+        Some(resized) => (resized, "image/jpeg".to_string()),
+        None => (data, mime_type),
     };
 
-    Some(id3::frame::Frame::with_content(
-        "APIC",
-        id3::frame::Content::Picture(pic),
+    Some(Picture::new_unchecked(
+        picture_type,
+        Some(MimeType::from_str(&mime_type)),
+        None,
+        data,
     ))
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn sha1_hex_matches_fips_180_vectors() {
+        assert_eq!(sha1_hex(b""), "da39a3ee5e6b4b0d3255bfef95601890afd80709");
+        assert_eq!(sha1_hex(b"abc"), "a9993e364706816aba3e25717850c26c9cd0d89d");
+        assert_eq!(
+            sha1_hex(b"abcdbcdecdefdefgefghfghighijhijkijkljklmklmnlmnomnopnopq"),
+            "84983e441c3bd26ebaae4aa1f95129e5e54670f1"
+        );
+        assert_eq!(
+            sha1_hex(&[b'a'; 1_000_000]),
+            "34aa973cd4c4daa4f61eeb2bdbad27316534016f"
+        );
+    }
+
+    fn encoded(width: u32, height: u32, format: image::ImageFormat) -> Vec<u8> {
+        let image = image::RgbImage::from_pixel(width, height, image::Rgb([200, 40, 40]));
+        let mut data = io::Cursor::new(vec![]);
+        image.write_to(&mut data, format).unwrap();
+        data.into_inner()
+    }
+
+    #[test]
+    fn image_dimensions_of_png_and_jpeg() {
+        let png = encoded(400, 300, image::ImageFormat::Png);
+        assert_eq!(image_dimensions(&png), Some((400, 300)));
+
+        let jpeg = encoded(120, 640, image::ImageFormat::Jpeg);
+        assert_eq!(image_dimensions(&jpeg), Some((120, 640)));
+
+        assert_eq!(image_dimensions(b"not an image"), None);
+        assert_eq!(image_dimensions(&png[..12]), None);
+    }
+
+    #[test]
+    fn scale_down_keeps_aspect_ratio() {
+        let resize = ArtworkResize {
+            max_size: 100,
+            quality: 85,
+        };
+
+        let wide = scale_down(&encoded(400, 200, image::ImageFormat::Png), resize).unwrap();
+        assert!(wide.starts_with(&[0xFF, 0xD8]));
+        assert_eq!(image_dimensions(&wide), Some((100, 50)));
+
+        let tall = scale_down(&encoded(300, 900, image::ImageFormat::Jpeg), resize).unwrap();
+        assert_eq!(image_dimensions(&tall), Some((33, 100)));
+    }
+}
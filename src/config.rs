@@ -1,9 +1,20 @@
+use crate::cache::ArtworkResize;
 use crate::display::DownloadBar;
+use crate::display::PlainProgress;
+use crate::download_tracker::DownloadNumbers;
 use crate::episode;
+use crate::error::ConfigError;
+use crate::error::Error;
+use crate::observer::Observers;
+use crate::observer::SyncObserver;
 use crate::patterns::Evaluate;
 use crate::patterns::FullPattern;
 use crate::podcast::Podcast;
 use crate::podcast::RawPodcast;
+use crate::progress::JsonProgress;
+use crate::progress::ProgressSink;
+use crate::stats::PodcastStats;
+use crate::stats::RunStats;
 use crate::utils;
 use crate::utils::Unix;
 use futures::future;
@@ -11,6 +22,7 @@ use indicatif::MultiProgress;
 use regex::Regex;
 use serde::de::IntoDeserializer;
 use serde::{Deserialize, Serialize};
+use std::collections::BTreeMap;
 use std::collections::HashMap;
 use std::fs;
 use std::io::Write;
@@ -20,7 +32,9 @@ use std::process;
 use std::sync::atomic::AtomicBool;
 use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::sync::OnceLock;
 use std::time;
+use tokio::sync::Semaphore;
 
 /// Represents a [`PodcastConfig`] value that is either enabled, disabled,
 /// or deferring to the global config. Only valid for optional values.
@@ -94,6 +108,44 @@ fn default_download_path() -> String {
     "{home}/talecast/{podname}".to_string()
 }
 
+fn default_transcript_types() -> Vec<String> {
+    vec!["vtt".to_string(), "srt".to_string()]
+}
+
+fn default_preferred_extensions() -> Vec<String> {
+    vec!["mp3".to_string()]
+}
+
+fn default_extension_source() -> Vec<ExtensionSource> {
+    vec![
+        ExtensionSource::Url,
+        ExtensionSource::ContentType,
+        ExtensionSource::EnclosureType,
+    ]
+}
+
+fn default_soundbite_command() -> String {
+    "ffmpeg -loglevel error -y -ss {start} -t {duration} -i {input} -c copy {output}".to_string()
+}
+
+fn default_mp3_fix_command() -> String {
+    "ffmpeg -loglevel error -y -i {input} -c copy -write_xing 1 {output}".to_string()
+}
+
+/// Longest file name in bytes on most filesystems.
+const DEFAULT_MAX_FILENAME_LENGTH: usize = 255;
+
+/// Longest path in bytes the OS accepts.
+fn default_max_path_length() -> usize {
+    if cfg!(windows) {
+        260
+    } else if cfg!(target_os = "macos") {
+        1024
+    } else {
+        4096
+    }
+}
+
 fn default_id_pattern() -> String {
     "{guid}".to_string()
 }
@@ -127,6 +179,8 @@ impl<'a> EvalData<'a> {
 #[derive(Debug, Clone, Default)]
 pub struct Config {
     pub url: String,
+    /// The name of the podcast, its key in `podcasts.toml`.
+    pub podcast_name: String,
     pub name_pattern: String,
     pub id_pattern: String,
     pub download_path: PathBuf,
@@ -134,16 +188,83 @@ pub struct Config {
     pub tracker_path: PathBuf,
     pub symlink: Option<PathBuf>,
     pub id3_tags: HashMap<String, String>,
-    pub download_hook: Option<PathBuf>,
+    pub tags: HashMap<String, String>,
+    /// Tags from `--set-tag`, which take precedence over the others. Only written to files
+    /// downloaded in this run, never when retagging.
+    pub tag_overrides: HashMap<String, String>,
+    pub download_hook: Option<DownloadHook>,
+    pub filename_transform: FilenameTransform,
+    pub space_replacement: Option<char>,
+    pub request: RequestSettings,
+    pub tag_preset: Option<TagPreset>,
+    pub max_file_size: Option<u64>,
+    /// Downloads stop when the download path has less free space than this, in bytes.
+    pub min_free_space: Option<u64>,
+    /// Most bytes per second downloaded for the podcast.
+    pub download_rate: Option<u64>,
+    /// Most bytes per second downloaded for all podcasts together.
+    pub total_download_rate: Option<u64>,
+    /// Transcript types to download in order of preference. Empty if transcripts are disabled.
+    pub transcript_types: Vec<String>,
+    /// Extensions picked first among the ones guessed from a mime type, in order of preference.
+    pub preferred_extensions: Vec<String>,
+    /// Where the extension of a downloaded file is taken from, in order of precedence.
+    pub extension_source: Vec<ExtensionSource>,
+    pub write_description: bool,
+    pub write_nfo: bool,
+    /// Whether the episode's own artwork is embedded rather than the podcast's.
+    pub embed_episode_art: bool,
+    /// Whether the downloaded files are tagged with the metadata of the feed.
+    pub write_tags: bool,
+    /// Whether the tags of downloaded files are rewritten when the feed's metadata changes.
+    pub retag_on_change: bool,
+    /// Whether a file already at the episode's path is kept instead of downloaded again, if
+    /// its audio matches the enclosure.
+    pub reuse_intact_files: bool,
+    /// Command that cuts a soundbite out of an episode, if `extract_soundbites` is enabled.
+    pub soundbite_command: Option<String>,
+    /// Command that rewrites an mp3 with a correct Xing header, if `fix_mp3_headers` is enabled.
+    pub mp3_fix_command: Option<String>,
+    pub verify_size: bool,
+    /// How cover art is scaled down before it's embedded, if at all.
+    pub artwork_resize: Option<ArtworkResize>,
+    pub prefer_media: MediaKind,
+    pub prefer_bitrate: Option<BitratePreference>,
+    pub guid_fallback: GuidFallback,
+    /// Longest name of a downloaded file in bytes, including the extension.
+    pub max_filename_length: usize,
+    /// Longest full path of a downloaded file in bytes.
+    pub max_path_length: usize,
+    pub global_dedup: bool,
+    /// Most episodes downloaded from the same host at the same time, across all podcasts.
+    pub max_connections_per_host: Option<usize>,
+    /// `Referer` header sent when downloading enclosures.
+    pub referer: Option<String>,
+    pub tag_filters: TagFilters,
+    /// Lowercased substrings of titles to download, if any.
+    pub only_titles: Vec<String>,
+    /// Lowercased substrings of titles to skip, unless they're in `only_titles`.
+    pub skip_titles: Vec<String>,
+    /// Whether episodes marked with `itunes:explicit` are skipped.
+    pub skip_explicit: bool,
 }
 
 impl Config {
+    /// Fails if a directory of the episode can't be created.
     pub fn new(
         global_config: &GlobalConfig,
         podcast_config: &PodcastConfig,
+        tag_filters: &TagFilters,
         data: EvalData<'_>,
-    ) -> Self {
+    ) -> Result<Self, ConfigError> {
+        let config_error = |reason| ConfigError {
+            podcast: data.pod_name.to_string(),
+            reason,
+        };
         let podcast_config = podcast_config.to_owned();
+        let request = podcast_config.request_settings();
+        let (prefer_media, prefer_bitrate) = podcast_config.media_preference(global_config);
+        let guid_fallback = podcast_config.guid_fallback(global_config);
         let id3_tags = {
             let mut map = HashMap::with_capacity(
                 global_config.id3_tags.len() + podcast_config.id3_tags.len(),
@@ -159,32 +280,49 @@ impl Config {
             map
         };
 
+        let mut tags = global_config.tags.clone();
+        tags.extend(podcast_config.tags.clone());
+
         let download_hook = podcast_config
             .download_hook
-            .into_val(global_config.download_hook.as_ref());
+            .into_val(global_config.download_hook.as_ref())
+            .map(|hook| DownloadHook::new(&hook, data));
 
-        let download_path_str = podcast_config
-            .download_path
+        let download_path_str = global_config
+            .download_path_override
+            .clone()
+            .or(podcast_config.download_path)
             .unwrap_or_else(|| global_config.download_path.clone());
 
-        let download_path = FullPattern::direct_eval_dir(&download_path_str, data);
+        let download_path =
+            FullPattern::direct_eval_dir(&download_path_str, data).map_err(config_error)?;
+
+        // Kept in the same place for every episode, even if their download paths differ.
+        let default_tracker_path = {
+            let podcast_path = FullPattern::stable_prefix(&download_path_str);
+            if podcast_path.is_empty() || podcast_path.ends_with('/') {
+                podcast_path + ".downloaded"
+            } else {
+                podcast_path + "/.downloaded"
+            }
+        };
 
         let tracker_path = match podcast_config
             .tracker_path
             .into_val(global_config.tracker_path.as_ref())
         {
-            Some(tracker_path) => tracker_path,
+            Some(tracker_path) => {
+                let tracker_path =
+                    FullPattern::direct_eval_file(&tracker_path, data).map_err(config_error)?;
+                let default_tracker_path = FullPattern::direct_eval(&default_tracker_path, data);
+                migrate_tracker(Path::new(&default_tracker_path), &tracker_path);
+                tracker_path
+            }
             None => {
-                if download_path_str.ends_with('/') {
-                    download_path_str + ".downloaded"
-                } else {
-                    download_path_str + "/.downloaded"
-                }
+                FullPattern::direct_eval_file(&default_tracker_path, data).map_err(config_error)?
             }
         };
 
-        let tracker_path = FullPattern::direct_eval_file(&tracker_path, data);
-
         let name_pattern = FullPattern::from_str(
             &podcast_config
                 .name_pattern
@@ -201,15 +339,116 @@ impl Config {
         let symlink = podcast_config
             .symlink
             .or(global_config.symlink.clone())
-            .map(|str| FullPattern::direct_eval_dir(str.as_ref(), data));
+            .map(|str| FullPattern::direct_eval_dir(str.as_ref(), data))
+            .transpose()
+            .map_err(config_error)?;
 
+        // A relative partial path, like `.tmp`, is kept inside the download directory.
         let partial_path = podcast_config
             .partial_path
             .or(global_config.partial_path.clone())
-            .map(|str| FullPattern::direct_eval_dir(str.as_ref(), data));
+            .map(|str| {
+                let path = download_path.join(FullPattern::direct_eval(str.as_ref(), data));
+                utils::try_create_dir(&path).map(|_| path)
+            })
+            .transpose()
+            .map_err(config_error)?;
+
+        let filename_transform = podcast_config
+            .filename_transform
+            .or(global_config.filename_transform)
+            .unwrap_or_default();
+
+        let space_replacement = podcast_config
+            .space_replacement
+            .into_val(global_config.space_replacement.as_ref());
+
+        let max_file_size = podcast_config
+            .max_file_size
+            .clone()
+            .into_val(global_config.max_file_size.as_ref())
+            .map(|size| {
+                utils::parse_size(&size)
+                    .ok_or_else(|| config_error(format!("invalid max_file_size: {:?}", size)))
+            })
+            .transpose()?;
+
+        let min_free_space = podcast_config
+            .min_free_space
+            .clone()
+            .into_val(global_config.min_free_space.as_ref())
+            .map(|size| {
+                utils::parse_size(&size)
+                    .ok_or_else(|| config_error(format!("invalid min_free_space: {:?}", size)))
+            })
+            .transpose()?;
 
-        Config {
+        let download_rate = podcast_config
+            .download_rate
+            .clone()
+            .into_val(global_config.download_rate.as_ref())
+            .map(|rate| {
+                utils::parse_size(&rate)
+                    .ok_or_else(|| config_error(format!("invalid download_rate: {:?}", rate)))
+            })
+            .transpose()?;
+
+        let total_download_rate = global_config
+            .total_download_rate
+            .as_deref()
+            .map(|rate| {
+                utils::parse_size(rate)
+                    .ok_or_else(|| config_error(format!("invalid total_download_rate: {:?}", rate)))
+            })
+            .transpose()?;
+
+        let lowercase = |titles: &[String]| -> Vec<String> {
+            titles.iter().map(|title| title.to_lowercase()).collect()
+        };
+
+        let only_titles = podcast_config
+            .only_titles
+            .as_deref()
+            .or(global_config.only_titles.as_deref())
+            .map(lowercase)
+            .unwrap_or_default();
+
+        let skip_titles = podcast_config
+            .skip_titles
+            .as_deref()
+            .or(global_config.skip_titles.as_deref())
+            .map(lowercase)
+            .unwrap_or_default();
+
+        let transcript_types = if podcast_config
+            .download_transcripts
+            .or(global_config.download_transcripts)
+            .unwrap_or(false)
+        {
+            podcast_config
+                .transcript_types
+                .clone()
+                .or(global_config.transcript_types.clone())
+                .unwrap_or_else(default_transcript_types)
+        } else {
+            vec![]
+        };
+
+        let preferred_extensions = podcast_config
+            .preferred_extensions
+            .clone()
+            .or(global_config.preferred_extensions.clone())
+            .unwrap_or_else(default_preferred_extensions);
+
+        let extension_source = podcast_config
+            .extension_source
+            .clone()
+            .or(global_config.extension_source.clone())
+            .unwrap_or_else(default_extension_source);
+
+        Ok(Config {
             url: podcast_config.url.clone(),
+            podcast_name: data.pod_name.to_string(),
             name_pattern,
             id_pattern,
             download_path,
@@ -217,8 +456,506 @@ impl Config {
             tracker_path,
             symlink,
             id3_tags: id3_tags.clone(),
+            tags,
+            tag_overrides: global_config.tag_overrides.clone(),
             download_hook: download_hook.clone(),
+            filename_transform,
+            space_replacement,
+            request,
+            tag_preset: podcast_config.tag_preset,
+            max_file_size,
+            download_rate,
+            total_download_rate,
+            min_free_space,
+            transcript_types,
+            preferred_extensions,
+            extension_source,
+            write_description: podcast_config
+                .write_description
+                .or(global_config.write_description)
+                .unwrap_or(true),
+            write_nfo: podcast_config
+                .write_nfo
+                .or(global_config.write_nfo)
+                .unwrap_or(false),
+            embed_episode_art: podcast_config
+                .embed_episode_art
+                .or(global_config.embed_episode_art)
+                .unwrap_or(true),
+            write_tags: !global_config.no_tags
+                && podcast_config
+                    .write_tags
+                    .or(global_config.write_tags)
+                    .unwrap_or(true),
+            retag_on_change: podcast_config
+                .retag_on_change
+                .or(global_config.retag_on_change)
+                .unwrap_or(false),
+            reuse_intact_files: podcast_config
+                .reuse_intact_files
+                .or(global_config.reuse_intact_files)
+                .unwrap_or(false),
+            soundbite_command: podcast_config
+                .extract_soundbites
+                .or(global_config.extract_soundbites)
+                .unwrap_or(false)
+                .then(|| {
+                    podcast_config
+                        .soundbite_command
+                        .clone()
+                        .or(global_config.soundbite_command.clone())
+                        .unwrap_or_else(default_soundbite_command)
+                }),
+            mp3_fix_command: podcast_config
+                .fix_mp3_headers
+                .or(global_config.fix_mp3_headers)
+                .unwrap_or(false)
+                .then(|| {
+                    podcast_config
+                        .mp3_fix_command
+                        .clone()
+                        .or(global_config.mp3_fix_command.clone())
+                        .unwrap_or_else(default_mp3_fix_command)
+                }),
+            artwork_resize: podcast_config
+                .artwork_max_size
+                .or(global_config.artwork_max_size)
+                .map(|max_size| ArtworkResize {
+                    max_size,
+                    quality: podcast_config
+                        .artwork_quality
+                        .or(global_config.artwork_quality)
+                        .unwrap_or(85),
+                }),
+            prefer_media,
+            prefer_bitrate,
+            guid_fallback,
+            verify_size: podcast_config
+                .verify_size
+                .or(global_config.verify_size)
+                .unwrap_or(false),
+            max_filename_length: podcast_config
+                .max_filename_length
+                .or(global_config.max_filename_length)
+                .unwrap_or(DEFAULT_MAX_FILENAME_LENGTH),
+            max_path_length: podcast_config
+                .max_path_length
+                .or(global_config.max_path_length)
+                .unwrap_or_else(default_max_path_length),
+            global_dedup: global_config.global_dedup.unwrap_or(false),
+            max_connections_per_host: global_config.max_connections_per_host,
+            referer: podcast_config
+                .referer
+                .as_deref()
+                .map(|referer| match referer {
+                    "auto" => data
+                        .podcast
+                        .link()
+                        .unwrap_or(&podcast_config.url)
+                        .to_string(),
+                    referer => referer.to_string(),
+                }),
+            tag_filters: tag_filters.clone(),
+            only_titles,
+            skip_titles,
+            skip_explicit: podcast_config
+                .skip_explicit
+                .or(global_config.skip_explicit)
+                .unwrap_or(false),
+        })
+    }
+}
+
+/// The `require_tag` and `skip_tag` filters of a podcast, compiled once for all its episodes.
+#[derive(Debug, Clone, Default)]
+pub struct TagFilters {
+    /// Episodes are only downloaded if these episode keys match their regex.
+    pub require: Vec<(String, Regex)>,
+    /// Episodes are skipped if any of these episode keys match their regex.
+    pub skip: Vec<(String, Regex)>,
+}
+
+/// Compiles the regexes of a tag filter. The key may be written as a pattern like
+/// `rss::episode::itunes:episodeType`, and the regex must match the whole value.
+fn tag_filters(
+    setting: &str,
+    filters: HashMap<String, String>,
+) -> Result<Vec<(String, Regex)>, String> {
+    filters
+        .into_iter()
+        .map(|(key, regex)| {
+            let key = key
+                .strip_prefix("rss::episode::")
+                .unwrap_or(&key)
+                .to_string();
+            match Regex::new(&format!("^(?:{})$", regex)) {
+                Ok(regex) => Ok((key, regex)),
+                Err(e) => Err(format!("invalid regex in {} for {:?}: {}", setting, key, e)),
+            }
+        })
+        .collect()
+}
+
+/// A length of time, like the time between episodes in backlog mode.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(untagged)]
+pub enum Interval {
+    /// Plain numbers are days.
+    Days(i64),
+    /// Number with a unit, like `12h`, `2d` or `1w`.
+    Duration(String),
+}
+
+impl Interval {
+    fn duration(&self) -> Option<Unix> {
+        match self {
+            Self::Days(days) => Some(Unix::from_secs(u64::try_from(*days).ok()? * 86400)),
+            Self::Duration(s) => utils::parse_interval(s),
+        }
+    }
+}
+
+/// Time of day when episodes are allowed to be downloaded.
+///
+/// Windows where `end` is before `start` last overnight. If `days` is set, the window only
+/// opens on those days, e.g. a window from friday 23:00 to 06:00 lasts until saturday morning.
+#[derive(Serialize, Deserialize, Debug, Clone)]
+#[serde(deny_unknown_fields)]
+pub struct DownloadWindow {
+    start: String,
+    end: String,
+    #[serde(default, skip_serializing_if = "Vec::is_empty")]
+    days: Vec<String>,
+}
+
+impl DownloadWindow {
+    /// Fails if a time isn't written as HH:MM, or a day isn't a day of the week.
+    pub fn parse(&self) -> Result<OpenHours, String> {
+        let parse_time = |s: &str| {
+            chrono::NaiveTime::parse_from_str(s, "%H:%M")
+                .map_err(|_| format!("invalid time in download_window: {:?}, expected HH:MM", s))
+        };
+
+        let days = self
+            .days
+            .iter()
+            .map(|s| {
+                s.parse::<chrono::Weekday>()
+                    .map_err(|_| format!("invalid day in download_window: {:?}", s))
+            })
+            .collect::<Result<_, _>>()?;
+
+        Ok(OpenHours {
+            start: parse_time(&self.start)?,
+            end: parse_time(&self.end)?,
+            days,
+        })
+    }
+}
+
+/// A [`DownloadWindow`] with its times and days parsed.
+#[derive(Debug, Clone)]
+pub struct OpenHours {
+    start: chrono::NaiveTime,
+    end: chrono::NaiveTime,
+    /// Empty if the window opens every day.
+    days: Vec<chrono::Weekday>,
+}
+
+impl OpenHours {
+    fn opens_on(&self, day: chrono::Weekday) -> bool {
+        self.days.is_empty() || self.days.contains(&day)
+    }
+
+    /// Whether downloads are allowed at the given time.
+    pub fn is_open(&self, now: chrono::NaiveDateTime) -> bool {
+        use chrono::Datelike;
+
+        let time = now.time();
+        let today = now.weekday();
+
+        if self.start <= self.end {
+            self.start <= time && time < self.end && self.opens_on(today)
+        } else if time >= self.start {
+            self.opens_on(today)
+        } else {
+            time < self.end && self.opens_on(today.pred())
+        }
+    }
+
+    pub fn start(&self) -> String {
+        self.start.format("%H:%M").to_string()
+    }
+}
+
+/// Kind of media preferred when an episode has several media files.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum MediaKind {
+    #[default]
+    Audio,
+    Video,
+}
+
+/// Bitrate preferred when an episode has several media files of the preferred kind.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum BitratePreference {
+    Highest,
+    Lowest,
+}
+
+/// What to do with the episodes already in the feed when a podcast is synced for the first time.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum InitialSync {
+    /// Download them according to the other settings.
+    #[default]
+    Backlog,
+    /// Mark them as downloaded, so only episodes published afterwards are downloaded.
+    Now,
+}
+
+/// The timezone that publish dates are shown in, set with `pubdate_timezone`.
+#[derive(Debug, Clone, Copy, Default, PartialEq)]
+pub enum PubDateTimezone {
+    /// The offset of the episode's `pubDate`.
+    #[default]
+    Feed,
+    Utc,
+    /// The timezone of the system.
+    Local,
+    /// An offset like `+02:00`.
+    Fixed(chrono::FixedOffset),
+}
+
+impl PubDateTimezone {
+    fn from_str(s: &str) -> Option<Self> {
+        match s.trim().to_lowercase().as_str() {
+            "feed" => Some(Self::Feed),
+            "utc" => Some(Self::Utc),
+            "local" => Some(Self::Local),
+            offset => offset.parse().ok().map(Self::Fixed),
+        }
+    }
+
+    /// The offset to show the publish date in, given the one of the `pubDate`.
+    pub fn offset(&self, published: Unix, feed_offset: chrono::FixedOffset) -> chrono::FixedOffset {
+        use chrono::{Offset, TimeZone};
+
+        match self {
+            Self::Feed => feed_offset,
+            Self::Utc => chrono::Utc.fix(),
+            Self::Local => chrono::Local
+                .timestamp_opt(published.as_secs() as i64, 0)
+                .single()
+                .map(|date| date.offset().fix())
+                .unwrap_or(feed_offset),
+            Self::Fixed(offset) => *offset,
+        }
+    }
+}
+
+/// What `--prune` does with downloaded episodes that are no longer in the feed.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum RemovedEpisodePolicy {
+    /// Leave them as they are.
+    #[default]
+    Keep,
+    /// Delete the files and their download tracker lines.
+    Delete,
+    /// Move the files into a `removed` folder next to them.
+    Mark,
+}
+
+/// How the key of an episode without a `<guid>` is made up.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum GuidFallback {
+    /// The enclosure URL, as it appears in the feed.
+    #[default]
+    EnclosureUrl,
+    /// The title along with the publish date.
+    TitleDate,
+    /// SHA-1 of the title and the enclosure URL.
+    Hash,
+}
+
+/// Predefined tagging behavior for podcasts with special needs.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum TagPreset {
+    /// Tags every episode as part of the same album, with sequential track numbers,
+    /// and the season as disc number.
+    Audiobook,
+}
+
+/// Command run after an episode is downloaded.
+#[derive(Debug, Clone)]
+pub enum DownloadHook {
+    /// Path to a script, which gets the path of the episode as its only argument.
+    Script(PathBuf),
+    /// Program and arguments, where `{path}` is yet to be replaced with the episode's path.
+    Command(Vec<String>),
+}
+
+impl DownloadHook {
+    const PLACEHOLDERS: [&'static str; 5] = ["{path}", "{podcast}", "{title}", "{guid}", "{url}"];
+
+    /// Hooks with placeholders are split into arguments on whitespace, and the placeholders
+    /// known before downloading are replaced. Otherwise the hook is the path of a script.
+    fn new(hook: &Path, data: EvalData<'_>) -> Self {
+        let hook_str = hook.to_string_lossy();
+        if !Self::PLACEHOLDERS.iter().any(|p| hook_str.contains(p)) {
+            return Self::Script(hook.to_path_buf());
+        }
+
+        let args = hook_str
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{podcast}", data.pod_name)
+                    .replace("{title}", data.episode.title())
+                    .replace("{guid}", data.episode.guid())
+                    .replace("{url}", data.episode.url())
+            })
+            .collect();
+
+        Self::Command(args)
+    }
+
+    pub fn command(&self, path: &Path) -> std::process::Command {
+        match self {
+            Self::Script(script) => {
+                let mut cmd = std::process::Command::new(script);
+                cmd.arg(path);
+                cmd
+            }
+            Self::Command(args) => {
+                let path = path.to_string_lossy();
+                let mut args = args.iter().map(|arg| arg.replace("{path}", &path));
+                let mut cmd = std::process::Command::new(args.next().unwrap_or_default());
+                cmd.args(args);
+                cmd
+            }
+        }
+    }
+}
+
+/// Credentials and extra headers to send along with requests to a podcast's host.
+#[derive(Debug, Clone, Default)]
+pub struct RequestSettings {
+    host: Option<String>,
+    username: Option<String>,
+    password: Option<String>,
+    headers: HashMap<String, String>,
+}
+
+impl RequestSettings {
+    /// Adds the credentials and headers to the request.
+    ///
+    /// They're only added if `url` is on the same host as the feed, so that
+    /// credentials aren't leaked to third party hosts, like the CDN of an enclosure.
+    pub fn apply(
+        &self,
+        mut request: reqwest::RequestBuilder,
+        url: &str,
+    ) -> reqwest::RequestBuilder {
+        let host = url::Url::parse(url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+
+        if host.is_none() || host != self.host {
+            return request;
+        }
+
+        if let Some(username) = &self.username {
+            request = request.basic_auth(username, self.password.as_ref());
+        }
+
+        for (key, val) in &self.headers {
+            request = request.header(key.as_str(), val.as_str());
+        }
+
+        request
+    }
+}
+
+/// Where the extension of a downloaded episode can be taken from.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, PartialEq)]
+#[serde(rename_all = "snake_case")]
+pub enum ExtensionSource {
+    /// The extension at the end of the enclosure url's path.
+    Url,
+    /// The `Content-Type` header of the download.
+    ContentType,
+    /// The `type` attribute of the enclosure in the feed.
+    EnclosureType,
+}
+
+impl ExtensionSource {
+    pub fn describe(self) -> &'static str {
+        match self {
+            Self::Url => "the url",
+            Self::ContentType => "the content type",
+            Self::EnclosureType => "the enclosure type",
+        }
+    }
+}
+
+/// Transformation applied to the evaluated `name_pattern` before it's used as a filename.
+#[derive(Serialize, Deserialize, Debug, Clone, Copy, Default, PartialEq)]
+#[serde(rename_all = "lowercase")]
+pub enum FilenameTransform {
+    /// Keep the name as it is.
+    #[default]
+    None,
+    /// Lowercase, with separators replaced by underscores.
+    Snake,
+    /// Lowercase, with separators replaced by hyphens.
+    Kebab,
+    /// Lowercase only.
+    Lower,
+}
+
+impl FilenameTransform {
+    /// Applies the transform to the given name.
+    ///
+    /// If `space_replacement` is set, it overrides the separator used by `snake` and `kebab`,
+    /// and makes `none` and `lower` replace whitespace as well.
+    /// Repeated separators are collapsed into one.
+    pub fn apply(&self, name: &str, space_replacement: Option<char>) -> String {
+        let name = match self {
+            Self::None => name.to_string(),
+            Self::Snake | Self::Kebab | Self::Lower => name.to_lowercase(),
+        };
+
+        let default_separator = match self {
+            Self::Snake => Some('_'),
+            Self::Kebab => Some('-'),
+            Self::None | Self::Lower => None,
+        };
+
+        let Some(separator) = space_replacement.or(default_separator) else {
+            return name;
+        };
+
+        let is_separator = |c: char| {
+            c.is_whitespace()
+                || c == separator
+                || (default_separator.is_some() && (c == '_' || c == '-'))
+        };
+
+        let mut output = String::with_capacity(name.len());
+        for c in name.chars() {
+            if !is_separator(c) {
+                output.push(c);
+            } else if !output.is_empty() && !output.ends_with(separator) {
+                output.push(separator);
+            }
         }
+
+        output.trim_end_matches(separator).to_string()
     }
 }
 
@@ -339,16 +1076,101 @@ pub struct GlobalConfig {
     earliest_date: Option<String>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     id3_tags: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    tags: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    require_tag: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    skip_tag: HashMap<String, String>,
+    only_titles: Option<Vec<String>>,
+    skip_titles: Option<Vec<String>>,
+    skip_explicit: Option<bool>,
     download_hook: Option<PathBuf>,
     tracker_path: Option<String>,
     #[serde(default, skip_serializing_if = "IndicatifSettings::is_default")]
     style: Arc<IndicatifSettings>,
     user_agent: Option<String>,
+    opml_owner_name: Option<String>,
     #[serde(default, skip_serializing_if = "SearchSettings::is_default")]
     search: SearchSettings,
     symlink: Option<String>,
     #[serde(default, skip_serializing_if = "LogConfig::is_default")]
     log: Arc<LogConfig>,
+    filename_transform: Option<FilenameTransform>,
+    space_replacement: Option<char>,
+    keep: Option<i64>,
+    delete_after: Option<Interval>,
+    archive_after: Option<Interval>,
+    removed_episode_policy: Option<RemovedEpisodePolicy>,
+    guid_fallback: Option<GuidFallback>,
+    max_file_size: Option<String>,
+    min_free_space: Option<String>,
+    download_rate: Option<String>,
+    total_download_rate: Option<String>,
+    prefer_media: Option<MediaKind>,
+    prefer_bitrate: Option<BitratePreference>,
+    initial: Option<InitialSync>,
+    since_last_run: Option<bool>,
+    pubdate_timezone: Option<String>,
+    download_priority: Option<Vec<String>>,
+    strict_patterns: Option<bool>,
+    download_transcripts: Option<bool>,
+    transcript_types: Option<Vec<String>>,
+    preferred_extensions: Option<Vec<String>>,
+    extension_source: Option<Vec<ExtensionSource>>,
+    artwork_max_size: Option<u32>,
+    artwork_quality: Option<u8>,
+    download_window: Option<DownloadWindow>,
+    write_description: Option<bool>,
+    write_nfo: Option<bool>,
+    embed_episode_art: Option<bool>,
+    write_tags: Option<bool>,
+    retag_on_change: Option<bool>,
+    reuse_intact_files: Option<bool>,
+    extract_soundbites: Option<bool>,
+    soundbite_command: Option<String>,
+    fix_mp3_headers: Option<bool>,
+    mp3_fix_command: Option<String>,
+    verify_size: Option<bool>,
+    max_filename_length: Option<usize>,
+    max_path_length: Option<usize>,
+    global_dedup: Option<bool>,
+    danger_accept_invalid_certs: Option<bool>,
+    ca_cert: Option<PathBuf>,
+    stats_log: Option<PathBuf>,
+    cache_feeds: Option<bool>,
+    dead_feed_threshold: Option<u32>,
+    disable_dead_feeds: Option<bool>,
+    feed_fetch_concurrency: Option<usize>,
+    download_concurrency: Option<usize>,
+    episode_concurrency: Option<usize>,
+    request_delay: Option<f64>,
+    interleave_downloads: Option<bool>,
+    http2_prior_knowledge: Option<bool>,
+    pool_max_idle_per_host: Option<usize>,
+    max_connections_per_host: Option<usize>,
+    /// Named sets of settings that override the ones above, selected with `--profile`.
+    #[serde(default, skip_serializing_if = "BTreeMap::is_empty")]
+    profiles: BTreeMap<String, serde_json::Value>,
+    /// Set from the command line, takes precedence over every configured download path.
+    #[serde(skip)]
+    download_path_override: Option<String>,
+    /// Set from the command line, feeds are read from the cache and nothing is downloaded.
+    #[serde(skip)]
+    offline: bool,
+    /// Set from the command line, takes precedence over every `write_tags` setting.
+    #[serde(skip)]
+    no_tags: bool,
+    /// Set from the command line, podcasts and their episodes are synced one at a time
+    /// without progress bars.
+    #[serde(skip)]
+    sequential: bool,
+    /// Set from the command line, added to the tags of every file downloaded in this run.
+    #[serde(skip)]
+    tag_overrides: HashMap<String, String>,
+    /// Where the config was loaded from, and is saved to. The default path if not set.
+    #[serde(skip)]
+    path: Option<PathBuf>,
 }
 
 impl GlobalConfig {
@@ -376,10 +1198,10 @@ impl GlobalConfig {
             }
         };
 
-        let config: Self = match toml::from_str(&str) {
+        let config: Self = match ConfigFormat::of(&path).parse(&str) {
             Ok(config) => config,
             Err(e) => {
-                eprintln!("unable to parse config file: {:?}", e);
+                eprintln!("unable to parse config file: {}", e);
                 process::exit(1);
             }
         };
@@ -404,13 +1226,169 @@ impl GlobalConfig {
             }
         };
 
-        match toml::from_str(&str) {
+        let mut config: Self = match ConfigFormat::of(path).parse(&str) {
             Ok(config) => config,
             Err(e) => {
-                eprintln!("unable to parse given config file: {:?}\n{:?}", path, e);
+                eprintln!("unable to parse given config file: {:?}\n{}", path, e);
                 process::exit(1);
             }
-        }
+        };
+
+        config.path = Some(path.to_path_buf());
+        config
+    }
+
+    /// Checks that the config file at `path` parses, for validating edits before they're saved.
+    pub fn validate_file(path: &Path) -> Result<(), String> {
+        let str = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        ConfigFormat::of(path).parse::<Self>(&str).map(|_| ())
+    }
+
+    /// Overrides the settings with the ones of the profile `name`.
+    ///
+    /// Tables like `log` are merged, so a profile only needs the settings it changes.
+    pub fn apply_profile(&mut self, name: &str) -> Result<(), String> {
+        let Some(profile) = self.profiles.get(name) else {
+            let names: Vec<&str> = self.profiles.keys().map(String::as_str).collect();
+            return Err(format!(
+                "no profile named {:?}, available profiles: {:?}",
+                name, names
+            ));
+        };
+
+        let mut config = serde_json::to_value(&*self).map_err(|e| e.to_string())?;
+        merge_settings(&mut config, profile.clone());
+
+        let mut profiled: Self = serde_json::from_value(without_nulls(config))
+            .map_err(|e| format!("invalid profile {:?}: {}", name, e))?;
+        profiled.profiles = std::mem::take(&mut self.profiles);
+        profiled.path = self.path.take();
+        *self = profiled;
+        Ok(())
+    }
+
+    pub fn download_path(&self) -> &str {
+        &self.download_path
+    }
+
+    pub fn max_days(&self) -> Option<i64> {
+        self.max_days
+    }
+
+    pub fn max_episodes(&self) -> Option<i64> {
+        self.max_episodes
+    }
+
+    /// Sets the settings asked for by the setup wizard. Remember to save afterwards.
+    pub fn set_download_defaults(
+        &mut self,
+        download_path: String,
+        max_days: Option<i64>,
+        max_episodes: Option<i64>,
+    ) {
+        self.download_path = download_path;
+        self.max_days = max_days;
+        self.max_episodes = max_episodes;
+    }
+
+    pub fn override_download_path(&mut self, path: String) {
+        self.download_path_override = Some(path);
+    }
+
+    pub fn override_feed_fetch_concurrency(&mut self, qty: usize) {
+        self.feed_fetch_concurrency = Some(qty);
+    }
+
+    pub fn set_interleave_downloads(&mut self) {
+        self.interleave_downloads = Some(true);
+    }
+
+    /// Whether podcasts take turns downloading one episode each, rather than downloading all
+    /// their episodes at once.
+    fn interleave_downloads(&self) -> bool {
+        !self.sequential && self.interleave_downloads.unwrap_or(false)
+    }
+
+    pub fn set_tag_override(&mut self, field: String, value: String) {
+        self.tag_overrides.insert(field, value);
+    }
+
+    pub fn set_since_last_run(&mut self) {
+        self.since_last_run = Some(true);
+    }
+
+    /// Sets the default download rate of each podcast, like `download_rate` in the config.
+    /// Exits if it isn't a valid size.
+    pub fn set_download_rate(&mut self, rate: String) {
+        validate_rate(&rate);
+        self.download_rate = Some(rate);
+    }
+
+    /// Exits if `rate` isn't a valid size.
+    pub fn set_total_download_rate(&mut self, rate: String) {
+        validate_rate(&rate);
+        self.total_download_rate = Some(rate);
+    }
+
+    pub fn set_sequential(&mut self) {
+        self.sequential = true;
+    }
+
+    pub fn sequential(&self) -> bool {
+        self.sequential
+    }
+
+    /// Limits of how many podcasts fetch their feed, and download episodes, at the same time.
+    fn concurrency_limits(&self) -> (Arc<Semaphore>, Arc<Semaphore>) {
+        let semaphore = |limit: Option<usize>| {
+            let permits = limit.map_or(Semaphore::MAX_PERMITS, |limit| limit.max(1));
+            Arc::new(Semaphore::new(permits))
+        };
+
+        (
+            semaphore(self.feed_fetch_concurrency),
+            semaphore(self.download_concurrency),
+        )
+    }
+
+    pub fn set_offline(&mut self) {
+        self.offline = true;
+    }
+
+    pub fn offline(&self) -> bool {
+        self.offline
+    }
+
+    pub fn set_no_tags(&mut self) {
+        self.no_tags = true;
+    }
+
+    pub fn dead_feed_threshold(&self) -> u32 {
+        self.dead_feed_threshold.unwrap_or(5).max(1)
+    }
+
+    pub fn disable_dead_feeds(&self) -> bool {
+        self.disable_dead_feeds.unwrap_or(false)
+    }
+
+    pub fn cache_feeds(&self) -> bool {
+        self.cache_feeds.unwrap_or(false)
+    }
+
+    /// The pattern settings that are configured, along with their keys.
+    fn patterns(&self) -> Vec<(&'static str, &str)> {
+        [
+            ("download_path", Some(&self.download_path)),
+            ("partial_path", self.partial_path.as_ref()),
+            ("name_pattern", Some(&self.name_pattern)),
+            ("id_pattern", Some(&self.id_pattern)),
+            ("tracker_path", self.tracker_path.as_ref()),
+            ("symlink", self.symlink.as_ref()),
+            ("--path", self.download_path_override.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(key, pattern)| Some((key, pattern?.as_str())))
+        .collect()
     }
 
     pub fn style(&self) -> Arc<IndicatifSettings> {
@@ -423,22 +1401,53 @@ impl GlobalConfig {
 
     /// Serializes the config to the default path.
     pub fn save(&self) {
-        let path = Self::default_path();
-        let str = toml::to_string(self).unwrap();
+        let path = self.path();
+        let str = ConfigFormat::of(&path).serialize(self).unwrap();
         let mut f = std::fs::File::create(&path).expect("unable to create config file");
         f.write_all(str.as_bytes()).unwrap();
     }
 
+    fn tls_settings(&self) -> TlsSettings {
+        TlsSettings {
+            accept_invalid_certs: self.danger_accept_invalid_certs.unwrap_or(false),
+            ca_cert: self.ca_cert.clone(),
+        }
+    }
+
+    /// The client shared by all podcasts without their own TLS settings.
+    pub fn reqwest_client(&self) -> Arc<reqwest::Client> {
+        let tls = self.tls_settings();
+        if tls.accept_invalid_certs {
+            warn_insecure("all podcasts");
+        }
+
+        init_reqwest_client(self, &tls)
+    }
+
+    pub fn stats_log(&self) -> Option<PathBuf> {
+        self.stats_log.clone()
+    }
+
     pub fn user_agent(&self) -> String {
         self.user_agent.clone().unwrap_or_else(default_user_agent)
     }
 
+    /// The `ownerName` of exported OPML files.
+    pub fn opml_owner_name(&self) -> Option<String> {
+        self.opml_owner_name.clone()
+    }
+
     pub fn search_settings(&self) -> &SearchSettings {
         &self.search
     }
 
     pub fn default_path() -> PathBuf {
-        utils::config_dir().join("config.toml")
+        utils::config_file("config")
+    }
+
+    /// The path the config was loaded from, like the one given with `--config`.
+    pub fn path(&self) -> PathBuf {
+        self.path.clone().unwrap_or_else(Self::default_path)
     }
 
     pub fn max_search_results(&self) -> usize {
@@ -460,6 +1469,12 @@ impl Default for GlobalConfig {
             max_episodes: Some(10),
             earliest_date: None,
             id3_tags: Default::default(),
+            tags: Default::default(),
+            require_tag: Default::default(),
+            skip_tag: Default::default(),
+            only_titles: None,
+            skip_titles: None,
+            skip_explicit: None,
             download_hook: None,
             tracker_path: None,
             style: Default::default(),
@@ -467,7 +1482,68 @@ impl Default for GlobalConfig {
             log: Default::default(),
             symlink: None,
             user_agent: None,
+            opml_owner_name: None,
             partial_path: None,
+            filename_transform: None,
+            space_replacement: None,
+            keep: None,
+            delete_after: None,
+            archive_after: None,
+            removed_episode_policy: None,
+            guid_fallback: None,
+            max_file_size: None,
+            download_rate: None,
+            total_download_rate: None,
+            min_free_space: None,
+            prefer_media: None,
+            prefer_bitrate: None,
+            initial: None,
+            since_last_run: None,
+            pubdate_timezone: None,
+            download_priority: None,
+            strict_patterns: None,
+            download_transcripts: None,
+            transcript_types: None,
+            preferred_extensions: None,
+            extension_source: None,
+            artwork_max_size: None,
+            artwork_quality: None,
+            download_window: None,
+            write_description: None,
+            write_nfo: None,
+            embed_episode_art: None,
+            write_tags: None,
+            retag_on_change: None,
+            reuse_intact_files: None,
+            extract_soundbites: None,
+            soundbite_command: None,
+            fix_mp3_headers: None,
+            mp3_fix_command: None,
+            verify_size: None,
+            max_filename_length: None,
+            max_path_length: None,
+            global_dedup: None,
+            danger_accept_invalid_certs: None,
+            ca_cert: None,
+            stats_log: None,
+            cache_feeds: None,
+            dead_feed_threshold: None,
+            disable_dead_feeds: None,
+            feed_fetch_concurrency: None,
+            download_concurrency: None,
+            episode_concurrency: None,
+            request_delay: None,
+            http2_prior_knowledge: None,
+            pool_max_idle_per_host: None,
+            max_connections_per_host: None,
+            interleave_downloads: None,
+            profiles: BTreeMap::new(),
+            download_path_override: None,
+            offline: false,
+            no_tags: false,
+            sequential: false,
+            tag_overrides: HashMap::new(),
+            path: None,
         }
     }
 }
@@ -487,8 +1563,12 @@ pub enum DownloadMode {
 }
 
 impl DownloadMode {
-    pub fn new(global_config: &GlobalConfig, podcast_config: &PodcastConfig) -> Self {
-        match (
+    /// Fails with the conflicting settings if the podcast's mode is misconfigured.
+    pub fn new(
+        global_config: &GlobalConfig,
+        podcast_config: &PodcastConfig,
+    ) -> Result<Self, String> {
+        let mode = match (
             podcast_config.backlog_start.clone(),
             podcast_config.backlog_interval.clone(),
         ) {
@@ -500,45 +1580,63 @@ impl DownloadMode {
                 max_episodes: podcast_config
                     .max_episodes
                     .into_val(global_config.max_episodes.as_ref()),
-                earliest_date: {
-                    podcast_config
-                        .earliest_date
-                        .clone()
-                        .into_val(global_config.earliest_date.as_ref())
-                        .map(|date| {
-                            utils::date_str_to_unix(&date)
-                                .expect("failed to parse earliest_date string")
-                        })
+                earliest_date: match podcast_config
+                    .earliest_date
+                    .clone()
+                    .into_val(global_config.earliest_date.as_ref())
+                {
+                    Some(date) => Some(
+                        utils::date_str_to_unix(&date)
+                            .map_err(|_| format!("invalid earliest_date: {:?}", date))?,
+                    ),
+                    None => None,
                 },
             },
             (Some(_), None) => {
-                eprintln!("missing backlog_interval");
-                std::process::exit(1);
+                return Err("'backlog_start' is set without 'backlog_interval'".into());
             }
             (None, Some(_)) => {
-                eprintln!("missing backlog_start");
-                std::process::exit(1);
+                return Err("'backlog_interval' is set without 'backlog_start'".into());
             }
             (Some(start), Some(interval)) => {
-
                 if podcast_config.earliest_date.is_enabled() {
-                    eprintln!("'earliest_date' not compatible with backlog mode.");
-                    std::process::exit(1);
+                    return Err(
+                        "'earliest_date' can't be combined with 'backlog_start' and 'backlog_interval'"
+                            .into(),
+                    );
                 }
 
                 let Ok(start) = dateparser::parse(&start) else {
-                    eprintln!("invalid backlog_start format.");
-                    std::process::exit(1);
+                    return Err(format!("invalid backlog_start: {:?}", start));
+                };
+
+                let Some(interval) = interval.duration().filter(|interval| !interval.is_zero())
+                else {
+                    return Err(format!(
+                        "invalid backlog_interval: {:?}, use a number of days, or a duration like \"12h\", \"2d\" or \"1w\"",
+                        interval
+                    ));
                 };
 
                 DownloadMode::Backlog {
                     start: std::time::Duration::from_secs(start.timestamp() as u64),
-                    interval: Unix::from_secs(interval as u64 * 86400),
+                    interval,
                     max_episodes: podcast_config
                     .max_episodes
                     .into_val(global_config.max_episodes.as_ref()),
                 }
             }
+        };
+
+        Ok(mode)
+    }
+}
+
+impl DownloadMode {
+    /// Limits standard mode to episodes published after `time`, on top of `earliest_date`.
+    pub fn published_after(&mut self, time: Unix) {
+        if let Self::Standard { earliest_date, .. } = self {
+            *earliest_date = Some(earliest_date.map_or(time, |date| date.max(time)));
         }
     }
 }
@@ -553,61 +1651,271 @@ impl Default for DownloadMode {
     }
 }
 
-fn init_reqwest_client(config: &GlobalConfig) -> Arc<reqwest::Client> {
-    reqwest::Client::builder()
-        .user_agent(&config.user_agent())
+/// Format of a config file, determined by its extension.
+#[derive(Clone, Copy)]
+enum ConfigFormat {
+    Toml,
+    Yaml,
+}
+
+impl ConfigFormat {
+    fn of(path: &Path) -> Self {
+        match path.extension().and_then(|ext| ext.to_str()) {
+            Some("yaml" | "yml") => Self::Yaml,
+            _ => Self::Toml,
+        }
+    }
+
+    fn parse<T: serde::de::DeserializeOwned>(self, s: &str) -> Result<T, String> {
+        match self {
+            Self::Toml => toml::from_str(s).map_err(|e| e.to_string()),
+            Self::Yaml => serde_yaml::from_str(s).map_err(|e| e.to_string()),
+        }
+    }
+
+    fn serialize<T: Serialize>(self, val: &T) -> Result<String, String> {
+        match self {
+            Self::Toml => toml::to_string(val).map_err(|e| e.to_string()),
+            Self::Yaml => {
+                // Unlike TOML, YAML would write every unset setting as null.
+                let val = serde_json::to_value(val).map_err(|e| e.to_string())?;
+                serde_yaml::to_string(&without_nulls(val)).map_err(|e| e.to_string())
+            }
+        }
+    }
+}
+
+fn validate_rate(rate: &str) {
+    if utils::parse_size(rate).is_none() {
+        eprintln!("invalid rate {:?}, use a size like 500KB or 2MB", rate);
+        process::exit(1);
+    }
+}
+
+/// Copies the tracker at the default path to a newly configured `tracker_path`, like one in
+/// `{state}`, so that the episodes it tracks aren't downloaded again.
+///
+/// The old tracker is left in place, in case the setting is reverted.
+fn migrate_tracker(default_path: &Path, tracker_path: &Path) {
+    if tracker_path == default_path || tracker_path.exists() || !default_path.is_file() {
+        return;
+    }
+
+    if let Err(e) = fs::copy(default_path, tracker_path) {
+        eprintln!("failed to copy tracker to {:?}: {}", tracker_path, e);
+        return;
+    }
+
+    if let Err(e) = DownloadNumbers::migrate(default_path, tracker_path) {
+        eprintln!("{}", e);
+    }
+
+    eprintln!(
+        "copied the tracker {:?} to the configured tracker_path {:?}",
+        default_path, tracker_path
+    );
+}
+
+/// Sets the settings of `overrides` on `base`, merging the tables that are in both.
+fn merge_settings(base: &mut serde_json::Value, overrides: serde_json::Value) {
+    match (base, overrides) {
+        (serde_json::Value::Object(base), serde_json::Value::Object(overrides)) => {
+            for (key, val) in overrides {
+                match base.get_mut(&key) {
+                    Some(existing) => merge_settings(existing, val),
+                    None => {
+                        base.insert(key, val);
+                    }
+                }
+            }
+        }
+        (base, overrides) => *base = overrides,
+    }
+}
+
+fn without_nulls(val: serde_json::Value) -> serde_json::Value {
+    match val {
+        serde_json::Value::Object(map) => map
+            .into_iter()
+            .filter(|(_, val)| !val.is_null())
+            .map(|(key, val)| (key, without_nulls(val)))
+            .collect(),
+        val => val,
+    }
+}
+
+/// TLS settings for feeds served with self-signed certificates or a private CA.
+#[derive(Debug, Clone, Default, PartialEq)]
+pub struct TlsSettings {
+    accept_invalid_certs: bool,
+    ca_cert: Option<PathBuf>,
+}
+
+pub fn init_reqwest_client(config: &GlobalConfig, tls: &TlsSettings) -> Arc<reqwest::Client> {
+    let mut builder = reqwest::Client::builder().user_agent(&config.user_agent());
+
+    if let Some(path) = &tls.ca_cert {
+        let cert = fs::read(path)
+            .ok()
+            .and_then(|bytes| {
+                reqwest::Certificate::from_pem(&bytes)
+                    .or_else(|_| reqwest::Certificate::from_der(&bytes))
+                    .ok()
+            })
+            .unwrap_or_else(|| {
+                eprintln!("error: failed to load ca_cert: {:?}", path);
+                process::exit(1);
+            });
+
+        builder = builder.add_root_certificate(cert);
+    }
+
+    if tls.accept_invalid_certs {
+        builder = builder.danger_accept_invalid_certs(true);
+    }
+
+    // Only for hosts known to speak HTTP/2, as it fails with every other host.
+    if config.http2_prior_knowledge.unwrap_or(false) {
+        builder = builder.http2_prior_knowledge();
+    }
+
+    if let Some(max_idle) = config.pool_max_idle_per_host {
+        builder = builder.pool_max_idle_per_host(max_idle);
+    }
+
+    builder
         .build()
         .map(Arc::new)
         .expect("error: failed to instantiate reqwest client")
 }
 
+/// Warns that certificates aren't verified, which is printed on every run so it's not forgotten.
+fn warn_insecure(scope: &str) {
+    eprintln!(
+        "WARNING: TLS certificate verification is disabled for {}!",
+        scope
+    );
+    eprintln!("WARNING: connections can be intercepted. Prefer 'ca_cert' for self-hosted feeds.");
+}
+
+/// Set by `--podcasts`, for running with a podcasts file outside the config directory.
+static PODCASTS_PATH: OnceLock<PathBuf> = OnceLock::new();
+
 #[derive(Serialize, Deserialize, Debug, Clone)]
 pub struct PodcastConfigs(HashMap<String, PodcastConfig>);
 
 impl PodcastConfigs {
-    pub async fn sync(self, global_config: GlobalConfig, log_file: &Path) -> Vec<PathBuf> {
+    /// Syncs the podcasts, also reporting the progress to `progress` if set.
+    pub async fn sync(
+        self,
+        global_config: GlobalConfig,
+        log_file: &Path,
+        progress: Option<ProgressSink>,
+    ) -> RunStats {
+        if utils::clock_seems_unset() {
+            eprintln!("warning: the system clock seems to be unset, date filters like 'max_days' won't work as expected.");
+        }
+
         eprintln!("syncing {} podcasts", self.len());
         log::info!("syncing podcasts..");
+        let start = time::Instant::now();
 
-        let mp = MultiProgress::new();
         let global_config = Arc::new(global_config);
-        let client = init_reqwest_client(&global_config);
+        let client = global_config.reqwest_client();
 
         let Some(longest_name) = self.longest_name() else {
-            return vec![];
+            return RunStats::default();
         };
 
+        // Created before the progress bars, so that warnings aren't drawn over.
+        let podcasts: Vec<_> = self
+            .sorted()
+            .into_iter()
+            .map(|(name, config)| {
+                let client = config.reqwest_client(&name, &global_config, &client);
+                (name, config, client)
+            })
+            .collect();
+
+        let mp = MultiProgress::new();
         let error_occured = Arc::new(AtomicBool::new(false));
+        let (fetch_limit, download_limit) = global_config.concurrency_limits();
 
-        let futures = self
-            .into_inner()
+        let sequential = global_config.sequential();
+        let tasks = podcasts
             .into_iter()
-            .map(|(name, config)| {
-                let client = Arc::clone(&client);
+            .map(|(name, config, client)| {
                 let settings = global_config.style();
-                let mut ui = DownloadBar::new(name.clone(), settings, &mp, longest_name);
+                let ui = match sequential {
+                    true => DownloadBar::hidden(name.clone()),
+                    false => DownloadBar::new(name.clone(), settings, &mp, longest_name),
+                };
+                let plain = sequential.then(|| PlainProgress::new(name.clone()));
                 let global_config = Arc::clone(&global_config);
                 let val = error_occured.clone();
-
-                tokio::task::spawn(async move {
-                    match Podcast::new(name, config, &global_config, client, &ui).await {
-                        Ok(podcast) => podcast.sync(&mut ui).await,
+                let fetch_limit = Arc::clone(&fetch_limit);
+                let download_limit = Arc::clone(&download_limit);
+                let json = progress
+                    .clone()
+                    .map(|sink| JsonProgress::new(name.clone(), sink));
+
+                async move {
+                    let mut observers: Vec<&dyn SyncObserver> = vec![&ui];
+                    observers.extend(plain.as_ref().map(|plain| plain as &dyn SyncObserver));
+                    observers.extend(json.as_ref().map(|json| json as &dyn SyncObserver));
+                    let observer = Observers(observers);
+
+                    let start = time::Instant::now();
+                    let fetch_permit = fetch_limit.acquire_owned().await.unwrap();
+                    let podcast =
+                        Podcast::new(name.clone(), config, &global_config, client, &ui).await;
+                    drop(fetch_permit);
+
+                    // A broken config isn't a problem with the feed.
+                    let fetch_failed =
+                        matches!(podcast, Err(Error::FeedFetch(_) | Error::Parse(_)));
+                    let synced = match podcast {
+                        // The semaphore is fair, so a podcast waits for the others to download
+                        // an episode before it gets to download its next one.
+                        Ok(podcast) if global_config.interleave_downloads() => {
+                            podcast.sync(&ui, &observer, Some(&download_limit)).await
+                        }
+                        Ok(podcast) => {
+                            let _permit = download_limit.acquire_owned().await.unwrap();
+                            podcast.sync(&ui, &observer, None).await
+                        }
                         Err(e) => {
-                            ui.error(&e);
+                            observer.on_error(&e.to_string());
                             val.store(true, Ordering::SeqCst);
-                            vec![]
+                            Default::default()
                         }
+                    };
+
+                    PodcastStats {
+                        name,
+                        files: synced.files,
+                        bytes: synced.bytes,
+                        low_disk_space: synced.low_disk_space,
+                        fetch_failed,
+                        elapsed: start.elapsed(),
                     }
-                })
+                }
             })
             .collect::<Vec<_>>();
 
-        let paths: Vec<PathBuf> = future::join_all(futures)
-            .await
-            .into_iter()
-            .filter_map(Result::ok)
-            .flatten()
-            .collect();
+        let podcasts: Vec<PodcastStats> = if sequential {
+            let mut podcasts = vec![];
+            for task in tasks {
+                podcasts.extend(tokio::task::spawn(task).await.ok());
+            }
+            podcasts
+        } else {
+            future::join_all(tasks.into_iter().map(tokio::task::spawn))
+                .await
+                .into_iter()
+                .filter_map(Result::ok)
+                .collect()
+        };
 
         if let Some(p) = global_config.log().path() {
             if true || error_occured.load(Ordering::SeqCst) {
@@ -618,26 +1926,58 @@ impl PodcastConfigs {
             }
         }
 
-        paths
+        RunStats {
+            podcasts,
+            elapsed: start.elapsed(),
+        }
     }
 
     pub fn load() -> Self {
-        let Ok(config_str) = fs::read_to_string(&Self::path()) else {
-            eprintln!("error: failed to read podcasts.toml file");
+        let path = Self::path();
+        let Ok(config_str) = fs::read_to_string(&path) else {
+            eprintln!("error: failed to read {:?}", &path);
             process::exit(1);
         };
 
-        match toml::from_str(&config_str) {
-            Ok(s) => Self(s),
-            Err(e) => {
-                eprintln!("failed to deserialize podcasts.toml file\n{:?}", e);
+        // An empty YAML file is null rather than an empty map.
+        if config_str.trim().is_empty() {
+            return Self(HashMap::new());
+        }
+
+        let podcasts: HashMap<String, PodcastConfig> =
+            match ConfigFormat::of(&path).parse(&config_str) {
+                Ok(s) => s,
+                Err(e) => {
+                    eprintln!("failed to deserialize {:?}\n{}", &path, e);
+                    process::exit(1);
+                }
+            };
+
+        for (name, podcast) in &podcasts {
+            if podcast.url.is_empty() && podcast.url_command.is_none() {
+                eprintln!("{}: missing 'url' or 'url_command' in {:?}", name, &path);
                 process::exit(1);
             }
         }
+
+        Self(podcasts)
     }
 
-    fn into_inner(self) -> HashMap<String, PodcastConfig> {
-        self.0
+    /// Checks that the podcasts file at `path` loads, for validating edits before they're saved.
+    pub fn validate_file(path: &Path) -> Result<(), String> {
+        let config_str = fs::read_to_string(path).map_err(|e| e.to_string())?;
+        if config_str.trim().is_empty() {
+            return Ok(());
+        }
+
+        let podcasts: HashMap<String, PodcastConfig> = ConfigFormat::of(path).parse(&config_str)?;
+        for (name, podcast) in &podcasts {
+            if podcast.url.is_empty() && podcast.url_command.is_none() {
+                return Err(format!("{}: missing 'url' or 'url_command'", name));
+            }
+        }
+
+        Ok(())
     }
 
     pub fn filter(mut self, filter: Option<Regex>) -> Self {
@@ -649,6 +1989,24 @@ impl PodcastConfigs {
         self
     }
 
+    /// Leaves out the podcasts with `enabled = false`.
+    pub fn without_disabled(mut self) -> Self {
+        self.0.retain(|_, config| config.enabled.unwrap_or(true));
+        self
+    }
+
+    /// Sets `enabled = false` on the given podcasts.
+    pub fn disable(names: &[String]) {
+        let mut podcasts = Self::load();
+        for name in names {
+            if let Some(config) = podcasts.0.get_mut(name) {
+                config.enabled = Some(false);
+            }
+        }
+
+        podcasts.save_to_file();
+    }
+
     pub fn assert_not_empty(self) -> Self {
         if self.is_empty() {
             eprintln!("No podcasts configured!");
@@ -668,6 +2026,65 @@ impl PodcastConfigs {
         self
     }
 
+    /// Exits with an error if any pattern contains an unknown token, when `strict_patterns` is set.
+    ///
+    /// Otherwise, unknown tokens are kept as is, with a warning when they're first evaluated.
+    pub fn validate_patterns(self, global_config: &GlobalConfig) -> Self {
+        if !global_config.strict_patterns.unwrap_or(false) {
+            return self;
+        }
+
+        let mut patterns: Vec<(&str, &str, &str)> = global_config
+            .patterns()
+            .into_iter()
+            .map(|(key, pattern)| ("config.toml", key, pattern))
+            .collect();
+
+        for (name, config) in &self.0 {
+            for (key, pattern) in config.patterns() {
+                patterns.push((name, key, pattern));
+            }
+        }
+
+        let mut valid = true;
+        for (source, key, pattern) in patterns {
+            for token in FullPattern::from_str(pattern).unknown_tokens() {
+                eprintln!("{}: unknown pattern \"{{{}}}\" in {}", source, token, key);
+                valid = false;
+            }
+        }
+
+        if !valid {
+            process::exit(1);
+        }
+
+        self
+    }
+
+    /// Leaves out the podcasts with conflicting download mode settings, reporting each of them,
+    /// so that the others can still sync.
+    pub fn without_invalid_modes(mut self, global_config: &GlobalConfig) -> Self {
+        self.0.retain(|name, config| {
+            let valid = DownloadMode::new(global_config, config);
+            if let Err(e) = &valid {
+                eprintln!("{}: skipping, {}", name, e);
+            }
+            valid.is_ok()
+        });
+        self
+    }
+
+    pub fn contains(&self, name: &str) -> bool {
+        self.0.contains_key(name)
+    }
+
+    /// Whether the podcast exists and doesn't have `enabled = false`.
+    pub fn is_enabled(&self, name: &str) -> bool {
+        self.0
+            .get(name)
+            .is_some_and(|config| config.enabled.unwrap_or(true))
+    }
+
     pub fn longest_name(&self) -> Option<usize> {
         self.0.iter().map(|(name, _)| name.chars().count()).max()
     }
@@ -706,8 +2123,10 @@ impl PodcastConfigs {
     pub fn save_to_file(self) {
         use std::fs::File;
 
-        let str = toml::to_string(&self).expect("failed to serialize podcastconfigs");
         let path = Self::path();
+        let str = ConfigFormat::of(&path)
+            .serialize(&self)
+            .expect("failed to serialize podcastconfigs");
 
         if let Err(e) = File::create(&path).map(|mut file| file.write_all(str.as_bytes())) {
             eprintln!("failed to save podcast configs to file: {:?}", e);
@@ -742,18 +2161,96 @@ impl PodcastConfigs {
         }
     }
 
+    /// Podcasts for the feed urls read from stdin, one per line, named after their channel titles.
+    ///
+    /// Blank lines and lines starting with `#` are skipped, as are feeds that fail to download.
+    pub async fn from_stdin(global_config: &GlobalConfig) -> Self {
+        use std::io::BufRead;
+
+        let urls: Vec<String> = std::io::stdin()
+            .lock()
+            .lines()
+            .map_while(Result::ok)
+            .map(|line| line.trim().to_string())
+            .filter(|line| !line.is_empty() && !line.starts_with('#'))
+            .collect();
+
+        let client = global_config.reqwest_client();
+        let mut podcasts = HashMap::new();
+
+        for url in urls {
+            let ui = DownloadBar::hidden(url.clone());
+            let podcast = PodcastConfig::new(url.clone());
+            let request = podcast.request_settings();
+            let title = match utils::download_text(&client, &url, &request, &ui).await {
+                Ok(xml) => crate::podcast::feed_title(&xml, &ui),
+                Err(e) => {
+                    eprintln!("{}: {}", &url, e);
+                    continue;
+                }
+            };
+
+            let name = title
+                .map(|title| sanitize_filename::sanitize(title.trim()))
+                .filter(|name| !name.is_empty())
+                .or_else(|| {
+                    let url = url::Url::parse(&url).ok()?;
+                    url.host_str().map(str::to_string)
+                })
+                .unwrap_or_else(|| "podcast".to_string());
+
+            // Feeds can share a title.
+            let mut unique_name = name.clone();
+            let mut suffix = 2;
+            while podcasts.contains_key(&unique_name) {
+                unique_name = format!("{} ({})", name, suffix);
+                suffix += 1;
+            }
+
+            podcasts.insert(unique_name, podcast);
+        }
+
+        if podcasts.is_empty() {
+            eprintln!("error: no feeds read from stdin");
+            process::exit(1);
+        }
+
+        Self(podcasts)
+    }
+
+    /// Uses the given podcasts file instead of the one in the config directory.
+    pub fn override_path(path: PathBuf) {
+        if !path.is_file() {
+            eprintln!("error: podcasts file not found: {:?}", &path);
+            process::exit(1);
+        }
+
+        PODCASTS_PATH.set(path).expect("podcasts path already set");
+    }
+
     pub fn path() -> PathBuf {
-        let path = utils::config_dir().join("podcasts.toml");
+        if let Some(path) = PODCASTS_PATH.get() {
+            return path.clone();
+        }
+
+        let path = utils::config_file("podcasts");
 
         if !path.exists() {
-            std::fs::File::create(&path).expect("failed to create podcasts.toml file");
+            std::fs::File::create(&path).expect("failed to create podcasts file");
         }
 
         path
     }
 
+    /// The podcasts sorted by name, which is also the order of the exported outlines.
+    pub fn sorted(self) -> Vec<(String, PodcastConfig)> {
+        let mut podcasts: Vec<_> = self.0.into_iter().collect();
+        podcasts.sort_by(|a, b| a.0.cmp(&b.0));
+        podcasts
+    }
+
     pub fn into_outlines(self) -> Vec<opml::Outline> {
-        self.0
+        self.sorted()
             .into_iter()
             .map(|(name, pod)| opml::Outline {
                 text: name.clone(),
@@ -770,10 +2267,14 @@ impl From<PodcastConfigs> for opml::OPML {
     fn from(podcasts: PodcastConfigs) -> opml::OPML {
         use opml::{Body, Head, OPML};
 
+        let now = chrono::Utc::now().to_rfc2822();
+
         let mut opml = OPML {
             head: Some(Head {
                 title: Some("TaleCast Podcast Feeds".to_string()),
-                date_created: Some(chrono::Utc::now().to_rfc2822()),
+                date_created: Some(now.clone()),
+                date_modified: Some(now),
+                docs: Some("http://opml.org/spec2.opml".to_string()),
                 ..Head::default()
             }),
             ..Default::default()
@@ -809,34 +2310,101 @@ impl<'a> IntoIterator for &'a PodcastConfigs {
 #[derive(Serialize, Deserialize, Debug, Clone)]
 #[serde(deny_unknown_fields)]
 pub struct PodcastConfig {
+    #[serde(default, skip_serializing_if = "String::is_empty")]
     pub url: String,
+    /// Command printing the feed url, replacing `url` for feeds with expiring urls.
+    url_command: Option<String>,
+    enabled: Option<bool>,
     name_pattern: Option<String>,
     id_pattern: Option<String>,
     #[serde(alias = "path")]
     download_path: Option<String>,
     partial_path: Option<String>,
     backlog_start: Option<String>,
-    backlog_interval: Option<i64>,
+    backlog_interval: Option<Interval>,
     #[serde(default, skip_serializing_if = "HashMap::is_empty")]
     id3_tags: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    tags: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    require_tag: HashMap<String, String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    skip_tag: HashMap<String, String>,
+    only_titles: Option<Vec<String>>,
+    skip_titles: Option<Vec<String>>,
+    skip_explicit: Option<bool>,
     max_days: ConfigOption<i64>,
     max_episodes: ConfigOption<i64>,
     earliest_date: ConfigOption<String>,
     download_hook: ConfigOption<PathBuf>,
     tracker_path: ConfigOption<String>,
     symlink: Option<String>,
+    filename_transform: Option<FilenameTransform>,
+    space_replacement: ConfigOption<char>,
+    username: Option<String>,
+    password: Option<String>,
+    #[serde(default, skip_serializing_if = "HashMap::is_empty")]
+    headers: HashMap<String, String>,
+    keep: ConfigOption<i64>,
+    delete_after: ConfigOption<Interval>,
+    archive_after: ConfigOption<Interval>,
+    removed_episode_policy: Option<RemovedEpisodePolicy>,
+    guid_fallback: Option<GuidFallback>,
+    tag_preset: Option<TagPreset>,
+    max_file_size: ConfigOption<String>,
+    min_free_space: ConfigOption<String>,
+    download_rate: ConfigOption<String>,
+    prefer_media: Option<MediaKind>,
+    prefer_bitrate: Option<BitratePreference>,
+    initial: Option<InitialSync>,
+    since_last_run: Option<bool>,
+    pubdate_timezone: Option<String>,
+    download_priority: Option<Vec<String>>,
+    episode_concurrency: Option<usize>,
+    request_delay: Option<f64>,
+    download_transcripts: Option<bool>,
+    transcript_types: Option<Vec<String>>,
+    preferred_extensions: Option<Vec<String>>,
+    extension_source: Option<Vec<ExtensionSource>>,
+    artwork_max_size: Option<u32>,
+    artwork_quality: Option<u8>,
+    download_window: ConfigOption<DownloadWindow>,
+    write_description: Option<bool>,
+    write_nfo: Option<bool>,
+    embed_episode_art: Option<bool>,
+    write_tags: Option<bool>,
+    retag_on_change: Option<bool>,
+    reuse_intact_files: Option<bool>,
+    extract_soundbites: Option<bool>,
+    soundbite_command: Option<String>,
+    fix_mp3_headers: Option<bool>,
+    mp3_fix_command: Option<String>,
+    verify_size: Option<bool>,
+    max_filename_length: Option<usize>,
+    max_path_length: Option<usize>,
+    danger_accept_invalid_certs: Option<bool>,
+    ca_cert: Option<PathBuf>,
+    referer: Option<String>,
 }
 
 impl PodcastConfig {
     pub fn new(url: String) -> Self {
         Self {
             url,
+            url_command: Default::default(),
+            enabled: Default::default(),
             name_pattern: Default::default(),
             id_pattern: Default::default(),
             download_path: Default::default(),
             backlog_start: Default::default(),
             backlog_interval: Default::default(),
             id3_tags: Default::default(),
+            require_tag: Default::default(),
+            skip_tag: Default::default(),
+            only_titles: Default::default(),
+            skip_titles: Default::default(),
+            skip_explicit: Default::default(),
+            tags: Default::default(),
             max_days: Default::default(),
             max_episodes: Default::default(),
             earliest_date: Default::default(),
@@ -844,6 +2412,307 @@ impl PodcastConfig {
             tracker_path: Default::default(),
             symlink: Default::default(),
             partial_path: Default::default(),
+            filename_transform: Default::default(),
+            space_replacement: Default::default(),
+            username: Default::default(),
+            password: Default::default(),
+            headers: Default::default(),
+            keep: Default::default(),
+            delete_after: Default::default(),
+            archive_after: Default::default(),
+            removed_episode_policy: Default::default(),
+            guid_fallback: Default::default(),
+            tag_preset: Default::default(),
+            max_file_size: Default::default(),
+            download_rate: Default::default(),
+            min_free_space: Default::default(),
+            prefer_media: Default::default(),
+            prefer_bitrate: Default::default(),
+            initial: Default::default(),
+            since_last_run: Default::default(),
+            pubdate_timezone: Default::default(),
+            download_priority: Default::default(),
+            episode_concurrency: Default::default(),
+            request_delay: Default::default(),
+            download_transcripts: Default::default(),
+            transcript_types: Default::default(),
+            preferred_extensions: Default::default(),
+            extension_source: Default::default(),
+            artwork_max_size: Default::default(),
+            artwork_quality: Default::default(),
+            download_window: Default::default(),
+            write_description: Default::default(),
+            write_nfo: Default::default(),
+            embed_episode_art: Default::default(),
+            write_tags: Default::default(),
+            retag_on_change: Default::default(),
+            reuse_intact_files: Default::default(),
+            extract_soundbites: Default::default(),
+            soundbite_command: Default::default(),
+            fix_mp3_headers: Default::default(),
+            mp3_fix_command: Default::default(),
+            verify_size: Default::default(),
+            max_filename_length: Default::default(),
+            max_path_length: Default::default(),
+            danger_accept_invalid_certs: Default::default(),
+            ca_cert: Default::default(),
+            referer: Default::default(),
+        }
+    }
+
+    /// The pattern settings that are configured, along with their keys.
+    fn patterns(&self) -> Vec<(&'static str, &str)> {
+        let tracker_path = match &self.tracker_path {
+            ConfigOption::Enabled(path) => Some(path),
+            _ => None,
+        };
+
+        [
+            ("name_pattern", self.name_pattern.as_ref()),
+            ("id_pattern", self.id_pattern.as_ref()),
+            ("download_path", self.download_path.as_ref()),
+            ("partial_path", self.partial_path.as_ref()),
+            ("tracker_path", tracker_path),
+            ("symlink", self.symlink.as_ref()),
+        ]
+        .into_iter()
+        .filter_map(|(key, pattern)| Some((key, pattern?.as_str())))
+        .collect()
+    }
+
+    /// How many of the most recent downloaded episodes to keep when pruning.
+    pub fn keep(&self, global_config: &GlobalConfig) -> Option<i64> {
+        self.keep.into_val(global_config.keep.as_ref())
+    }
+
+    /// How old downloaded episodes can get before they're pruned.
+    pub fn delete_after(&self, global_config: &GlobalConfig) -> Option<Unix> {
+        let interval = self
+            .delete_after
+            .clone()
+            .into_val(global_config.delete_after.as_ref())?;
+
+        Some(Self::retention_duration("delete_after", interval))
+    }
+
+    /// How old downloaded episodes can get before they're moved into the archive.
+    pub fn archive_after(&self, global_config: &GlobalConfig) -> Option<Unix> {
+        let interval = self
+            .archive_after
+            .clone()
+            .into_val(global_config.archive_after.as_ref())?;
+
+        Some(Self::retention_duration("archive_after", interval))
+    }
+
+    fn retention_duration(setting: &str, interval: Interval) -> Unix {
+        match interval.duration() {
+            Some(duration) => duration,
+            None => {
+                eprintln!("invalid {}: {:?}", setting, interval);
+                eprintln!("use a number of days, or a duration like \"12h\", \"2d\" or \"1w\".");
+                process::exit(1);
+            }
+        }
+    }
+
+    /// The client to use for this podcast.
+    ///
+    /// Podcasts with their own TLS settings get a dedicated client, the rest use `shared`.
+    pub fn reqwest_client(
+        &self,
+        name: &str,
+        global_config: &GlobalConfig,
+        shared: &Arc<reqwest::Client>,
+    ) -> Arc<reqwest::Client> {
+        let global_tls = global_config.tls_settings();
+        let tls = TlsSettings {
+            accept_invalid_certs: self
+                .danger_accept_invalid_certs
+                .unwrap_or(global_tls.accept_invalid_certs),
+            ca_cert: self.ca_cert.clone().or(global_tls.ca_cert.clone()),
+        };
+
+        if tls == global_tls {
+            return Arc::clone(shared);
+        }
+
+        if tls.accept_invalid_certs && !global_tls.accept_invalid_certs {
+            warn_insecure(&format!("'{}'", name));
+        }
+
+        init_reqwest_client(global_config, &tls)
+    }
+
+    /// Lowercased episode types, in the order their episodes are downloaded.
+    pub fn download_priority(&self, global_config: &GlobalConfig) -> Vec<String> {
+        self.download_priority
+            .as_ref()
+            .or(global_config.download_priority.as_ref())
+            .map(|types| types.iter().map(|ty| ty.trim().to_lowercase()).collect())
+            .unwrap_or_default()
+    }
+
+    /// How many episodes of the podcast are downloaded at the same time.
+    pub fn episode_concurrency(&self, global_config: &GlobalConfig) -> usize {
+        if global_config.sequential() {
+            return 1;
+        }
+
+        self.episode_concurrency
+            .or(global_config.episode_concurrency)
+            .unwrap_or(1)
+            .max(1)
+    }
+
+    /// The minimum time between starting the episode downloads of the podcast.
+    pub fn request_delay(&self, global_config: &GlobalConfig) -> Option<time::Duration> {
+        self.request_delay
+            .or(global_config.request_delay)
+            .filter(|secs| *secs > 0.)
+            .map(time::Duration::from_secs_f64)
+    }
+
+    pub fn removed_episode_policy(&self, global_config: &GlobalConfig) -> RemovedEpisodePolicy {
+        self.removed_episode_policy
+            .or(global_config.removed_episode_policy)
+            .unwrap_or_default()
+    }
+
+    pub fn guid_fallback(&self, global_config: &GlobalConfig) -> GuidFallback {
+        self.guid_fallback
+            .or(global_config.guid_fallback)
+            .unwrap_or_default()
+    }
+
+    /// Fails if the timezone isn't valid.
+    pub fn pubdate_timezone(
+        &self,
+        global_config: &GlobalConfig,
+    ) -> Result<PubDateTimezone, String> {
+        let Some(timezone) = self
+            .pubdate_timezone
+            .as_ref()
+            .or(global_config.pubdate_timezone.as_ref())
+        else {
+            return Ok(PubDateTimezone::default());
+        };
+
+        PubDateTimezone::from_str(timezone).ok_or_else(|| {
+            format!(
+                "invalid pubdate_timezone: {:?}, use feed, utc, local or an offset like +02:00",
+                timezone
+            )
+        })
+    }
+
+    /// Fails if a regex of the filters is invalid.
+    pub fn tag_filters(&self, global_config: &GlobalConfig) -> Result<TagFilters, String> {
+        let mut require_tag = global_config.require_tag.clone();
+        require_tag.extend(self.require_tag.clone());
+        let mut skip_tag = global_config.skip_tag.clone();
+        skip_tag.extend(self.skip_tag.clone());
+
+        Ok(TagFilters {
+            require: tag_filters("require_tag", require_tag)?,
+            skip: tag_filters("skip_tag", skip_tag)?,
+        })
+    }
+
+    pub fn initial_sync(&self, global_config: &GlobalConfig) -> InitialSync {
+        self.initial.or(global_config.initial).unwrap_or_default()
+    }
+
+    pub fn since_last_run(&self, global_config: &GlobalConfig) -> bool {
+        self.since_last_run
+            .or(global_config.since_last_run)
+            .unwrap_or(false)
+    }
+
+    /// The preferred kind and bitrate of media, for episodes with several media files.
+    pub fn media_preference(
+        &self,
+        global_config: &GlobalConfig,
+    ) -> (MediaKind, Option<BitratePreference>) {
+        (
+            self.prefer_media
+                .or(global_config.prefer_media)
+                .unwrap_or_default(),
+            self.prefer_bitrate.or(global_config.prefer_bitrate),
+        )
+    }
+
+    /// Fails if the window isn't valid.
+    pub fn download_window(
+        &self,
+        global_config: &GlobalConfig,
+    ) -> Result<Option<OpenHours>, String> {
+        self.download_window
+            .clone()
+            .into_val(global_config.download_window.as_ref())
+            .map(|window| window.parse())
+            .transpose()
+    }
+
+    /// Sets the credentials and headers used when fetching the feed and its enclosures.
+    pub fn set_credentials(
+        &mut self,
+        username: Option<String>,
+        password: Option<String>,
+        headers: HashMap<String, String>,
+    ) {
+        self.username = username;
+        self.password = password;
+        self.headers = headers;
+    }
+
+    /// Runs the `url_command` and uses its output as the url of the feed.
+    pub async fn resolve_url(&mut self) -> Result<(), String> {
+        let Some(command) = &self.url_command else {
+            return Ok(());
+        };
+
+        let mut args = command.split_whitespace();
+        let program = args.next().ok_or("empty url_command")?;
+        let output = tokio::process::Command::new(program)
+            .args(args)
+            .output()
+            .await
+            .map_err(|e| format!("failed to run url_command: {}", e))?;
+
+        if !output.status.success() {
+            let stderr = String::from_utf8_lossy(&output.stderr);
+            let mut error = format!("url_command failed with {}", output.status);
+            if !stderr.trim().is_empty() {
+                error.push_str(&format!(": {}", stderr.trim()));
+            }
+            return Err(error);
+        }
+
+        let url = String::from_utf8_lossy(&output.stdout).trim().to_string();
+        if url.is_empty() {
+            return Err("url_command printed no url".to_string());
+        }
+
+        self.url = url;
+        Ok(())
+    }
+
+    /// Identifies the feed in caches, which the changing url of a `url_command` can't.
+    pub fn feed_key(&self) -> &str {
+        self.url_command.as_deref().unwrap_or(&self.url)
+    }
+
+    pub fn request_settings(&self) -> RequestSettings {
+        let host = url::Url::parse(&self.url)
+            .ok()
+            .and_then(|url| url.host_str().map(str::to_string));
+
+        RequestSettings {
+            host,
+            username: self.username.clone(),
+            password: self.password.clone(),
+            headers: self.headers.clone(),
         }
     }
 
@@ -894,3 +2763,138 @@ impl LogConfig {
         self == &Self::default()
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn filename_transform_none_keeps_name() {
+        let transform = FilenameTransform::None;
+        assert_eq!(transform.apply("My  Episode-1", None), "My  Episode-1");
+        assert_eq!(transform.apply("My  Episode", Some('_')), "My_Episode");
+    }
+
+    #[test]
+    fn filename_transform_snake() {
+        let transform = FilenameTransform::Snake;
+        assert_eq!(
+            transform.apply("My Great Episode", None),
+            "my_great_episode"
+        );
+        assert_eq!(
+            transform.apply("Part 1 - The  Start ", None),
+            "part_1_the_start"
+        );
+        assert_eq!(transform.apply("a__b--c", None), "a_b_c");
+    }
+
+    #[test]
+    fn filename_transform_kebab() {
+        let transform = FilenameTransform::Kebab;
+        assert_eq!(
+            transform.apply("My Great Episode", None),
+            "my-great-episode"
+        );
+        assert_eq!(
+            transform.apply(" Part 1 _ The  Start", None),
+            "part-1-the-start"
+        );
+    }
+
+    #[test]
+    fn filename_transform_lower() {
+        let transform = FilenameTransform::Lower;
+        assert_eq!(
+            transform.apply("My Great-Episode", None),
+            "my great-episode"
+        );
+        assert_eq!(
+            transform.apply("My  Great Episode", Some('.')),
+            "my.great.episode"
+        );
+    }
+
+    #[test]
+    fn space_replacement_overrides_separator() {
+        assert_eq!(
+            FilenameTransform::Snake.apply("My Great Episode", Some('.')),
+            "my.great.episode"
+        );
+        assert_eq!(
+            FilenameTransform::Kebab.apply("a_b - c", Some('+')),
+            "a+b+c"
+        );
+    }
+
+    #[test]
+    fn tag_filters_match_whole_values() {
+        let mut config = PodcastConfig::new("http://example.com/feed.xml".to_string());
+        config.require_tag.insert(
+            "rss::episode::itunes:episodeType".to_string(),
+            "full|bonus".to_string(),
+        );
+
+        let filters = config.tag_filters(&GlobalConfig::default()).unwrap();
+        let (key, regex) = &filters.require[0];
+        assert_eq!(key, "itunes:episodeType");
+        assert!(regex.is_match("bonus"));
+        assert!(!regex.is_match("trailer-bonus"));
+    }
+
+    #[test]
+    fn tag_filters_reject_invalid_regex() {
+        let mut config = PodcastConfig::new("http://example.com/feed.xml".to_string());
+        config
+            .skip_tag
+            .insert("itunes:episodeType".to_string(), "(".to_string());
+
+        let error = config.tag_filters(&GlobalConfig::default()).unwrap_err();
+        assert!(error.contains("skip_tag"));
+    }
+
+    #[test]
+    fn invalid_pubdate_timezone_is_an_error() {
+        let mut config = PodcastConfig::new("http://example.com/feed.xml".to_string());
+        config.pubdate_timezone = Some("+02:00".to_string());
+        assert!(config.pubdate_timezone(&GlobalConfig::default()).is_ok());
+
+        config.pubdate_timezone = Some("mars".to_string());
+        let error = config
+            .pubdate_timezone(&GlobalConfig::default())
+            .unwrap_err();
+        assert!(error.contains("mars"));
+    }
+
+    #[test]
+    fn download_window_opens_overnight() {
+        let window = DownloadWindow {
+            start: "23:00".to_string(),
+            end: "06:00".to_string(),
+            days: vec!["fri".to_string()],
+        };
+        let hours = window.parse().unwrap();
+        let at =
+            |date: &str| chrono::NaiveDateTime::parse_from_str(date, "%Y-%m-%d %H:%M").unwrap();
+
+        // 2024-01-05 is a friday.
+        assert!(hours.is_open(at("2024-01-05 23:30")));
+        assert!(hours.is_open(at("2024-01-06 05:59")));
+        assert!(!hours.is_open(at("2024-01-06 23:30")));
+        assert!(!hours.is_open(at("2024-01-05 12:00")));
+        assert_eq!(hours.start(), "23:00");
+    }
+
+    #[test]
+    fn invalid_download_window_is_an_error() {
+        let window = |start: &str, day: &str| DownloadWindow {
+            start: start.to_string(),
+            end: "06:00".to_string(),
+            days: vec![day.to_string()],
+        };
+
+        assert!(window("23:00", "fri").parse().is_ok());
+        assert!(window("11pm", "fri").parse().is_err());
+        assert!(window("23:00", "someday").parse().is_err());
+    }
+}
@@ -0,0 +1,54 @@
+use crate::cache;
+use crate::utils;
+use std::fs;
+use std::io::{BufRead, BufReader};
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Index of downloaded media across all podcasts, keyed by the enclosure URL.
+///
+/// Used by the `global_dedup` setting to link episodes that are published in several feeds
+/// instead of downloading them again.
+pub struct MediaIndex;
+
+impl MediaIndex {
+    /// The most recently indexed file of the URL that still exists.
+    pub fn lookup(url: &str) -> Option<PathBuf> {
+        let hashed = cache::hashed_url(url);
+        let file = fs::File::open(Self::path()).ok()?;
+
+        BufReader::new(file)
+            .lines()
+            .map_while(Result::ok)
+            .filter_map(|line| utils::parse_quoted_words(&line))
+            .filter(|(key, _)| key == &hashed)
+            .map(|(_, path)| PathBuf::from(path))
+            .filter(|path| path.is_file())
+            .last()
+    }
+
+    pub fn append(url: &str, path: &Path) -> Result<(), String> {
+        let path = path
+            .canonicalize()
+            .map_err(|_| "failed to resolve path of downloaded file".to_string())?;
+        let hashed = cache::hashed_url(url);
+        let value = format!("\"{}\"", path.display());
+
+        utils::append_to_config(&Self::path(), &hashed, &value)
+            .map_err(|_| "failed to write to media index".to_string())
+    }
+
+    /// Links `path` to the existing file, preferring a hardlink.
+    pub fn link(existing: &Path, path: &Path) -> Result<(), String> {
+        if fs::hard_link(existing, path).is_ok() {
+            return Ok(());
+        }
+
+        std::os::unix::fs::symlink(existing, path)
+            .map_err(|_| "failed to link to existing file".to_string())
+    }
+
+    fn path() -> PathBuf {
+        utils::state_dir().join("media_index")
+    }
+}
@@ -0,0 +1,140 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfigs;
+use crate::display::DownloadBar;
+use crate::download_tracker::DownloadedEpisodes;
+use crate::podcast::Podcast;
+use regex::Regex;
+use std::collections::HashSet;
+use std::process;
+
+/// Exit code of `--check-new` when a podcast has episodes to download.
+const NEW_EPISODES_EXIT_CODE: i32 = 10;
+
+/// Prints how the feed of each podcast differs from its download tracker, ignoring the
+/// download filters.
+///
+/// Lists the episodes in the feed that aren't downloaded, and the downloaded episodes that
+/// are no longer in the feed.
+pub async fn diff(global_config: GlobalConfig, filter: Option<Regex>) {
+    let podcasts = PodcastConfigs::load()
+        .assert_not_empty()
+        .filter(filter)
+        .validate_patterns(&global_config);
+    let client = global_config.reqwest_client();
+
+    let mut podcasts: Vec<_> = podcasts.into_iter().collect();
+    podcasts.sort_by(|(a, _), (b, _)| a.cmp(b));
+
+    for (name, podcast_config) in podcasts {
+        let podcast = match Podcast::load_quietly(
+            name.clone(),
+            podcast_config,
+            &global_config,
+            &client,
+        )
+        .await
+        {
+            Ok(podcast) => podcast,
+            Err(e) => {
+                eprintln!("{}: {}", &name, e);
+                continue;
+            }
+        };
+
+        let mut not_downloaded = vec![];
+        let mut aged_out = vec![];
+        let mut in_sync = 0;
+
+        for (tracker_path, episodes) in podcast.episodes_by_tracker() {
+            let tracked = DownloadedEpisodes::load(&tracker_path);
+            let ids: HashSet<String> = episodes.iter().map(|episode| episode.get_id()).collect();
+
+            for episode in episodes {
+                if tracked.contains_episode(&episode.get_id()) {
+                    in_sync += 1;
+                } else {
+                    not_downloaded.push(episode.attrs.title().to_string());
+                }
+            }
+
+            for episode in tracked
+                .episodes()
+                .filter(|episode| !ids.contains(&episode.id))
+            {
+                let path = episode.path.as_ref().map(|path| path.display().to_string());
+                let description = match (&episode.title, path) {
+                    (Some(title), Some(path)) => format!("{} ({})", title, path),
+                    (Some(title), None) => title.clone(),
+                    (None, path) => path.unwrap_or_else(|| episode.id.clone()),
+                };
+                aged_out.push(description);
+            }
+        }
+
+        println!("{}:", &name);
+        println!("  {} episodes in sync", in_sync);
+
+        if !not_downloaded.is_empty() {
+            println!("  {} in the feed but not downloaded:", not_downloaded.len());
+            for title in &not_downloaded {
+                println!("    + {}", title);
+            }
+        }
+
+        if !aged_out.is_empty() {
+            aged_out.sort();
+            println!("  {} downloaded but no longer in the feed:", aged_out.len());
+            for episode in &aged_out {
+                println!("    - {}", episode);
+            }
+        }
+    }
+}
+
+/// Prints how many episodes a sync would download for each podcast as JSON, without downloading
+/// anything.
+///
+/// Exits with [`NEW_EPISODES_EXIT_CODE`] if there's something to download, otherwise with 1 if a
+/// feed failed to load, or 0.
+pub async fn check_new(global_config: GlobalConfig, filter: Option<Regex>) {
+    let podcasts = PodcastConfigs::load()
+        .assert_not_empty()
+        .without_disabled()
+        .filter(filter)
+        .validate_patterns(&global_config)
+        .without_invalid_modes(&global_config);
+    let client = global_config.reqwest_client();
+
+    let mut counts = serde_json::Map::new();
+    let mut failed = vec![];
+    let mut total = 0;
+
+    for (name, podcast_config) in podcasts.sorted() {
+        let ui = DownloadBar::hidden(name.clone());
+        let client = podcast_config.reqwest_client(&name, &global_config, &client);
+        match Podcast::new(name.clone(), podcast_config, &global_config, client, &ui).await {
+            Ok(podcast) => {
+                let pending = podcast.queued_and_pending(&ui).len();
+                total += pending;
+                counts.insert(name, pending.into());
+            }
+            Err(e) => {
+                eprintln!("{}: {}", &name, e);
+                failed.push(name);
+            }
+        }
+    }
+
+    let report = serde_json::json!({
+        "new": total,
+        "podcasts": counts,
+        "failed": failed,
+    });
+    println!("{}", report);
+
+    if total > 0 {
+        process::exit(NEW_EPISODES_EXIT_CODE);
+    } else if !failed.is_empty() {
+        process::exit(1);
+    }
+}
@@ -1,18 +1,25 @@
 use crate::config::IndicatifSettings;
 use crate::episode::Episode;
+use crate::observer::SyncObserver;
 use crate::utils;
 use indicatif::MultiProgress;
 use indicatif::ProgressBar;
 use indicatif::ProgressStyle;
+use std::path::Path;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
 
 #[derive(Debug)]
 pub struct DownloadBar {
     bar: Option<ProgressBar>,
+    mp: Option<MultiProgress>,
+    /// An extra row for one of several episodes downloaded at the same time.
+    is_row: bool,
     podcast_name: String,
     longest_podcast_name: usize,
     settings: Arc<IndicatifSettings>,
-    completed: bool,
+    completed: AtomicBool,
 }
 
 impl DownloadBar {
@@ -30,10 +37,44 @@ impl DownloadBar {
 
         Self {
             bar,
+            mp: Some(mp.clone()),
+            is_row: false,
             settings,
             podcast_name,
             longest_podcast_name,
-            completed: false,
+            completed: AtomicBool::new(false),
+        }
+    }
+
+    /// A bar below this one, for an episode downloaded at the same time as others.
+    pub fn row(&self) -> Option<Self> {
+        let (Some(bar), Some(mp)) = (&self.bar, &self.mp) else {
+            return None;
+        };
+
+        let row = Self {
+            bar: Some(mp.insert_after(bar, ProgressBar::new_spinner())),
+            mp: None,
+            is_row: true,
+            settings: Arc::clone(&self.settings),
+            podcast_name: self.podcast_name.clone(),
+            longest_podcast_name: self.longest_podcast_name,
+            completed: AtomicBool::new(false),
+        };
+        row.init();
+        Some(row)
+    }
+
+    /// A bar that only logs, for when there's no progress to display.
+    pub fn hidden(podcast_name: String) -> Self {
+        Self {
+            bar: None,
+            mp: None,
+            is_row: false,
+            settings: Default::default(),
+            podcast_name,
+            longest_podcast_name: 0,
+            completed: AtomicBool::new(false),
         }
     }
 
@@ -132,8 +173,18 @@ impl DownloadBar {
         }
     }
 
-    pub fn error(&mut self, msg: &str) {
-        if self.completed {
+    /// Logs the warning and prints it above the progress bars.
+    pub fn warn(&self, msg: &str) {
+        self.log_warn(msg);
+        let msg = format!("warning: {}: {}", &self.podcast_name, msg);
+        match &self.bar {
+            Some(pb) => pb.println(msg),
+            None => eprintln!("{}", msg),
+        }
+    }
+
+    pub fn error(&self, msg: &str) {
+        if self.completed.load(Ordering::SeqCst) {
             return;
         }
 
@@ -143,12 +194,43 @@ impl DownloadBar {
             self.set_template(&template);
             let msg = self.msg_with_prefix(msg);
             pb.finish_with_message(msg);
-            self.completed = true;
+            self.completed.store(true, Ordering::SeqCst);
         }
     }
 
-    pub fn complete(&mut self) {
-        if self.completed {
+    /// Finishes the bar with a note about episodes waiting for the download window.
+    pub fn deferred(&self, episode_qty: usize, window_start: &str) {
+        self.complete_with_note(&format!("{} deferred until {}", episode_qty, window_start));
+    }
+
+    /// Finishes the bar with the pending episodes of a cached feed.
+    pub fn offline(&self, episode_qty: usize, fetched: &str) {
+        self.complete_with_note(&format!(
+            "{} pending, offline with feed from {}",
+            episode_qty, fetched
+        ));
+    }
+
+    /// Finishes the bar with the episodes marked as downloaded on the first sync.
+    pub fn marked_seen(&self, episode_qty: usize) {
+        self.complete_with_note(&format!("{} marked as seen", episode_qty));
+    }
+
+    fn complete_with_note(&self, note: &str) {
+        if self.completed.load(Ordering::SeqCst) {
+            return;
+        }
+
+        if let Some(pb) = &self.bar {
+            let template = self.settings.completion_template();
+            self.set_template(&template);
+            pb.finish_with_message(format!("{} ({})", &self.podcast_name, note));
+            self.completed.store(true, Ordering::SeqCst);
+        }
+    }
+
+    pub fn complete(&self) {
+        if self.completed.load(Ordering::SeqCst) {
             return;
         }
 
@@ -156,7 +238,127 @@ impl DownloadBar {
             let template = self.settings.completion_template();
             self.set_template(&template);
             pb.finish_with_message(self.podcast_name.clone());
-            self.completed = true;
+            self.completed.store(true, Ordering::SeqCst);
+        }
+    }
+}
+
+impl SyncObserver for DownloadBar {
+    fn on_feed_loaded(&self, _episode_qty: usize) {
+        self.init();
+    }
+
+    fn on_downloads_deferred(&self, episode_qty: usize, window_start: &str) {
+        self.deferred(episode_qty, window_start);
+    }
+
+    fn on_offline(&self, episode_qty: usize, fetched: &str) {
+        self.offline(episode_qty, fetched);
+    }
+
+    fn on_marked_seen(&self, episode_qty: usize) {
+        self.marked_seen(episode_qty);
+    }
+
+    fn on_episode_start(&self, episode: &Episode, index: usize, episode_qty: usize) {
+        self.begin_download(episode, index, episode_qty);
+    }
+
+    fn on_episode_progress(&self, downloaded: u64, total: u64) {
+        if let Some(pb) = &self.bar {
+            if pb.length() != Some(total) {
+                pb.set_length(total);
+            }
+        }
+
+        self.set_progress(downloaded);
+    }
+
+    fn on_episode_done(&self, episode: &Episode, path: &Path) {
+        self.log_debug(format!(
+            "downloaded {:?} to {:?}",
+            episode.attrs.title(),
+            path
+        ));
+        if let Some(pb) = self.bar.as_ref().filter(|_| self.is_row) {
+            pb.finish_and_clear();
         }
     }
+
+    fn on_hooks_start(&self) {
+        self.hook_status();
+    }
+
+    fn on_sync_done(&self) {
+        self.complete();
+    }
+
+    fn on_error(&self, msg: &str) {
+        self.error(msg);
+    }
+
+    fn episode_observer(&self) -> Option<Box<dyn SyncObserver>> {
+        self.row().map(|row| Box::new(row) as Box<dyn SyncObserver>)
+    }
+}
+
+/// Prints the sync of a podcast as plain lines instead of progress bars, for `--sequential`.
+pub struct PlainProgress {
+    podcast_name: String,
+}
+
+impl PlainProgress {
+    pub fn new(podcast_name: String) -> Self {
+        Self { podcast_name }
+    }
+
+    fn print(&self, msg: &str) {
+        eprintln!("{}: {}", &self.podcast_name, msg);
+    }
+}
+
+impl SyncObserver for PlainProgress {
+    fn on_feed_loaded(&self, episode_qty: usize) {
+        self.print(&format!("{} episodes to download", episode_qty));
+    }
+
+    fn on_downloads_deferred(&self, episode_qty: usize, window_start: &str) {
+        self.print(&format!("{} deferred until {}", episode_qty, window_start));
+    }
+
+    fn on_offline(&self, episode_qty: usize, fetched: &str) {
+        self.print(&format!(
+            "{} pending, offline with feed from {}",
+            episode_qty, fetched
+        ));
+    }
+
+    fn on_marked_seen(&self, episode_qty: usize) {
+        self.print(&format!("{} marked as seen", episode_qty));
+    }
+
+    fn on_episode_start(&self, episode: &Episode, index: usize, episode_qty: usize) {
+        self.print(&format!(
+            "downloading {:?} ({}/{})",
+            episode.attrs.title(),
+            index + 1,
+            episode_qty
+        ));
+    }
+
+    fn on_episode_done(&self, _episode: &Episode, path: &Path) {
+        self.print(&format!("downloaded to {:?}", path));
+    }
+
+    fn on_hooks_start(&self) {
+        self.print("waiting for download hooks");
+    }
+
+    fn on_sync_done(&self) {
+        self.print("done");
+    }
+
+    fn on_error(&self, msg: &str) {
+        self.print(&format!("error: {}", msg));
+    }
 }
@@ -1,16 +1,283 @@
-use crate::episode::DownloadedEpisode;
+use crate::cache;
 use crate::utils;
+use crate::utils::Unix;
+use std::collections::HashMap;
 use std::collections::HashSet;
 use std::fs;
 use std::path::Path;
+use std::path::PathBuf;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+
+/// A single line in the download tracker.
+///
+/// Lines are in the form of `id timestamp "title" "path"`, where the path is relative to the
+/// tracker's directory if possible. Lines written by older versions don't have the path.
+#[derive(Debug, Clone)]
+pub struct TrackedEpisode {
+    pub id: String,
+    /// The title when the episode was downloaded.
+    pub title: Option<String>,
+    pub path: Option<PathBuf>,
+}
+
+impl TrackedEpisode {
+    fn from_line(line: &str, tracker_dir: &Path) -> Option<Self> {
+        let mut parts = line.splitn(3, ' ');
+        let id = parts.next().filter(|id| !id.is_empty())?.to_string();
+        let _timestamp = parts.next();
+        let (title, path) = Self::split_fields(parts.next().unwrap_or_default());
+
+        let title = Some(title.to_string()).filter(|title| !title.is_empty());
+        let path = path
+            .filter(|path| !path.is_empty())
+            .map(|path| tracker_dir.join(path));
+
+        Some(Self { id, title, path })
+    }
+
+    /// Splits the quoted fields after the timestamp into the title and the path, if any.
+    ///
+    /// Titles aren't escaped, so the title of an older line can contain `" "` as well. The last
+    /// field is only the path if it could have been written as one: it's empty, or it has an
+    /// extension, like every downloaded file, and no quotes, which filenames are sanitized of.
+    fn split_fields(fields: &str) -> (&str, Option<&str>) {
+        let quoted = fields.strip_prefix('"').unwrap_or(fields);
+        let quoted = quoted.strip_suffix('"').unwrap_or(quoted);
+
+        let is_path = |path: &str| {
+            path.is_empty() || (!path.contains('"') && Path::new(path).extension().is_some())
+        };
+
+        match quoted
+            .rsplit_once("\" \"")
+            .filter(|(_, path)| is_path(path))
+        {
+            Some((title, path)) => (title, Some(path)),
+            None => (quoted, None),
+        }
+    }
+}
+
+/// Episodes a sync set out to download, kept until they're downloaded.
+///
+/// An interrupted sync resumes with them, even if the feed no longer makes them pending.
+pub struct DownloadQueue;
+
+impl DownloadQueue {
+    /// The ids of the queued episodes of the podcast, in the order they were queued.
+    pub fn load(podcast: &str) -> Vec<String> {
+        fs::read_to_string(Self::path(podcast))
+            .map(|s| s.lines().map(ToString::to_string).collect())
+            .unwrap_or_default()
+    }
+
+    /// Replaces the queue of the podcast, removing it if there are no ids.
+    pub fn save(podcast: &str, ids: &[String]) -> Result<(), String> {
+        let path = Self::path(podcast);
+        if ids.is_empty() {
+            return match fs::remove_file(&path) {
+                Err(e) if e.kind() != std::io::ErrorKind::NotFound => {
+                    Err("failed to remove download queue".to_string())
+                }
+                _ => Ok(()),
+            };
+        }
+
+        if let Some(parent) = path.parent() {
+            utils::try_create_dir(parent)?;
+        }
+
+        fs::write(&path, ids.join("\n")).map_err(|_| "failed to save download queue".to_string())
+    }
+
+    fn path(podcast: &str) -> PathBuf {
+        utils::state_dir()
+            .join("queue")
+            .join(cache::hashed_url(podcast))
+    }
+}
+
+/// When each podcast last synced without failing, for `since_last_run`.
+pub struct LastRun;
+
+impl LastRun {
+    pub fn load(podcast: &str) -> Option<Unix> {
+        let secs = fs::read_to_string(Self::path(podcast)).ok()?;
+        secs.trim().parse().ok().map(Unix::from_secs)
+    }
+
+    pub fn save(podcast: &str, time: Unix) -> Result<(), String> {
+        let path = Self::path(podcast);
+        if let Some(parent) = path.parent() {
+            utils::try_create_dir(parent)?;
+        }
+
+        fs::write(&path, time.as_secs().to_string())
+            .map_err(|_| "failed to save time of last run".to_string())
+    }
+
+    fn path(podcast: &str) -> PathBuf {
+        utils::state_dir()
+            .join("last_run")
+            .join(cache::hashed_url(podcast))
+    }
+}
+
+/// Counts the episodes downloaded for each download tracker, for the `{download_number}` pattern.
+///
+/// Unlike the position of an episode in the feed, the number doesn't shift as the feed grows.
+pub struct DownloadNumbers;
+
+/// Numbers of the downloads in progress by tracker key, so concurrent downloads get different ones.
+static IN_PROGRESS: OnceLock<Mutex<HashMap<String, HashSet<u64>>>> = OnceLock::new();
+
+/// A download number that's taken until it's committed or dropped.
+pub struct ReservedNumber {
+    key: String,
+    number: u64,
+}
+
+impl ReservedNumber {
+    pub fn number(&self) -> u64 {
+        self.number
+    }
+
+    /// Saves the number once the episode is downloaded, so the next download continues from it.
+    pub fn commit(self) -> Result<(), String> {
+        DownloadNumbers::save(&self.key, self.number)
+    }
+}
+
+impl Drop for ReservedNumber {
+    fn drop(&mut self) {
+        if let Some(in_progress) = IN_PROGRESS.get() {
+            if let Some(numbers) = in_progress.lock().unwrap().get_mut(&self.key) {
+                numbers.remove(&self.number);
+            }
+        }
+    }
+}
+
+impl DownloadNumbers {
+    /// Reserves the next number of the podcast with the given tracker.
+    ///
+    /// The number is only saved when committed, so failed downloads don't use up numbers.
+    pub fn reserve(tracker_path: &Path) -> ReservedNumber {
+        let key = cache::hashed_url(&tracker_path.to_string_lossy());
+        let mut in_progress = IN_PROGRESS.get_or_init(Default::default).lock().unwrap();
+        let numbers = in_progress.entry(key.clone()).or_default();
+
+        let mut number = Self::last(&key) + 1;
+        while numbers.contains(&number) {
+            number += 1;
+        }
+        numbers.insert(number);
+
+        ReservedNumber { key, number }
+    }
+
+    /// Continues the numbers of the podcast with the tracker `from` for the tracker `to`.
+    pub fn migrate(from: &Path, to: &Path) -> Result<(), String> {
+        let last = Self::last(&cache::hashed_url(&from.to_string_lossy()));
+        if last == 0 {
+            return Ok(());
+        }
+
+        Self::save(&cache::hashed_url(&to.to_string_lossy()), last)
+    }
+
+    /// Saves `number` as the last number of `key`, unless a higher one is saved already.
+    ///
+    /// The file keeps one line per tracker.
+    fn save(key: &str, number: u64) -> Result<(), String> {
+        let path = Self::path();
+        let mut lines: Vec<(String, String)> = fs::read_to_string(&path)
+            .unwrap_or_default()
+            .lines()
+            .filter_map(utils::parse_quoted_words)
+            .collect();
+
+        let last = lines
+            .iter()
+            .filter(|(k, _)| k == key)
+            .filter_map(|(_, number)| number.parse::<u64>().ok())
+            .next_back()
+            .unwrap_or(0);
+        lines.retain(|(k, _)| k != key);
+        lines.push((key.to_string(), last.max(number).to_string()));
+
+        let contents: String = lines
+            .iter()
+            .map(|(key, number)| format!("{} {}\n", key, number))
+            .collect();
+        fs::write(&path, contents).map_err(|_| "failed to save download number".to_string())
+    }
+
+    fn last(key: &str) -> u64 {
+        fs::read_to_string(Self::path())
+            .unwrap_or_default()
+            .lines()
+            .filter_map(utils::parse_quoted_words)
+            .filter(|(k, _)| k == key)
+            .filter_map(|(_, number)| number.parse::<u64>().ok())
+            .next_back()
+            .unwrap_or(0)
+    }
+
+    fn path() -> PathBuf {
+        utils::state_dir().join("download_numbers")
+    }
+}
+
+/// Hashes of the values the tags of downloaded episodes were written from, for `retag_on_change`.
+pub struct TagHashes(HashMap<String, String>);
+
+impl TagHashes {
+    pub fn load() -> Self {
+        let hashes = fs::read_to_string(Self::path())
+            .unwrap_or_default()
+            .lines()
+            .filter_map(utils::parse_quoted_words)
+            .collect();
+
+        Self(hashes)
+    }
+
+    /// The hash saved when the episode was last tagged.
+    pub fn get(&self, tracker_path: &Path, id: &str) -> Option<&str> {
+        self.0.get(&Self::key(tracker_path, id)).map(String::as_str)
+    }
+
+    pub fn save(tracker_path: &Path, id: &str, hash: &str) -> Result<(), String> {
+        utils::append_to_config(&Self::path(), &Self::key(tracker_path, id), hash)
+            .map_err(|_| "failed to save tag hash".to_string())
+    }
+
+    fn key(tracker_path: &Path, id: &str) -> String {
+        cache::hashed_url(&format!("{} {}", tracker_path.display(), id))
+    }
+
+    fn path() -> PathBuf {
+        utils::state_dir().join("tag_hashes")
+    }
+}
 
 /// Keeps track of which episodes have already been downloaded.
 #[derive(Debug, Default)]
-pub struct DownloadedEpisodes(HashSet<String>);
+pub struct DownloadedEpisodes(HashMap<String, TrackedEpisode>);
 
 impl DownloadedEpisodes {
     pub fn contains_episode(&self, episode_id: &str) -> bool {
-        self.0.contains(episode_id)
+        self.0.contains_key(episode_id)
+    }
+
+    pub fn get(&self, episode_id: &str) -> Option<&TrackedEpisode> {
+        self.0.get(episode_id)
+    }
+
+    pub fn episodes(&self) -> impl Iterator<Item = &TrackedEpisode> {
+        self.0.values()
     }
 
     pub fn load(path: &Path) -> Self {
@@ -22,19 +289,24 @@ impl DownloadedEpisodes {
             e @ Err(_) => e.unwrap(),
         };
 
-        let mut hashmap: HashSet<String> = HashSet::new();
+        let tracker_dir = path.parent().unwrap_or(Path::new(""));
+        let mut hashmap: HashMap<String, TrackedEpisode> = HashMap::new();
 
         for line in s.trim().lines() {
-            let mut parts = line.split_whitespace();
-            if let Some(id) = parts.next() {
-                hashmap.insert(id.to_string());
+            if let Some(episode) = TrackedEpisode::from_line(line, tracker_dir) {
+                hashmap.insert(episode.id.clone(), episode);
             }
         }
 
         Self(hashmap)
     }
 
-    pub fn append(path: &Path, id: &str, episode: &DownloadedEpisode) -> Result<(), String> {
+    pub fn append(
+        path: &Path,
+        id: &str,
+        title: &str,
+        episode_path: Option<&Path>,
+    ) -> Result<(), String> {
         use std::io::Write;
 
         if path.is_dir() {
@@ -47,6 +319,8 @@ impl DownloadedEpisodes {
             utils::create_dir(&parent)
         }
 
+        let episode_path = episode_path.map(|episode_path| Self::relative_path(path, episode_path));
+
         let mut file = fs::OpenOptions::new()
             .append(true)
             .create(true)
@@ -55,13 +329,143 @@ impl DownloadedEpisodes {
 
         writeln!(
             file,
-            "{} {} \"{}\"",
+            "{} {} \"{}\" \"{}\"",
             id,
             utils::current_unix().as_secs(),
-            episode.inner().attrs.title()
+            title,
+            episode_path
+                .map(|path| path.display().to_string())
+                .unwrap_or_default()
         )
         .unwrap();
 
         Ok(())
     }
+
+    /// The episode's path relative to the tracker's directory, if it's inside of it.
+    fn relative_path<'a>(path: &Path, episode_path: &'a Path) -> &'a Path {
+        path.parent()
+            .and_then(|parent| episode_path.strip_prefix(parent).ok())
+            .unwrap_or(episode_path)
+    }
+
+    /// Changes the path of an episode in the tracker file, keeping the rest of its line.
+    pub fn set_path(path: &Path, id: &str, episode_path: &Path) -> Result<(), String> {
+        let s = fs::read_to_string(path).map_err(|_| "failed to read tracker file".to_string())?;
+        let episode_path = Self::relative_path(path, episode_path);
+
+        let mut output = String::new();
+        for line in s.lines() {
+            let mut parts = line.splitn(3, ' ');
+            match (parts.next(), parts.next(), parts.next()) {
+                (Some(line_id), Some(timestamp), Some(fields)) if line_id == id => {
+                    let (title, _) = TrackedEpisode::split_fields(fields);
+                    output.push_str(&format!(
+                        "{} {} \"{}\" \"{}\"",
+                        line_id,
+                        timestamp,
+                        title,
+                        episode_path.display()
+                    ));
+                }
+                _ => output.push_str(line),
+            }
+            output.push('\n');
+        }
+
+        fs::write(path, output).map_err(|_| "failed to write tracker file".to_string())
+    }
+
+    /// Removes the lines of the given episode IDs from the tracker file.
+    pub fn remove(path: &Path, ids: &HashSet<String>) -> Result<(), String> {
+        let s = fs::read_to_string(path).map_err(|_| "failed to read tracker file".to_string())?;
+
+        let mut output = String::new();
+        for line in s.lines() {
+            let id = line.split(' ').next().unwrap_or_default();
+            if !ids.contains(id) {
+                output.push_str(line);
+                output.push('\n');
+            }
+        }
+
+        fs::write(path, output).map_err(|_| "failed to write tracker file".to_string())
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    fn parse(line: &str) -> TrackedEpisode {
+        TrackedEpisode::from_line(line, Path::new("/podcasts/show")).unwrap()
+    }
+
+    #[test]
+    fn parses_legacy_lines() {
+        let episode = parse("guid-1 1700000000 \"Episode 1\"");
+        assert_eq!(episode.id, "guid-1");
+        assert_eq!(episode.title.as_deref(), Some("Episode 1"));
+        assert_eq!(episode.path, None);
+
+        let episode = parse("guid-1 1700000000 \"Part \"A\" \"B\"\"");
+        assert_eq!(episode.title.as_deref(), Some("Part \"A\" \"B\""));
+        assert_eq!(episode.path, None);
+    }
+
+    #[test]
+    fn parses_lines_with_paths() {
+        let episode = parse("guid-1 1700000000 \"Episode 1\" \"Episode 1.mp3\"");
+        assert_eq!(episode.title.as_deref(), Some("Episode 1"));
+        assert_eq!(
+            episode.path,
+            Some(PathBuf::from("/podcasts/show/Episode 1.mp3"))
+        );
+
+        let episode = parse("guid-1 1700000000 \"He said \"hi\" \"there\"\" \"hi.mp3\"");
+        assert_eq!(episode.title.as_deref(), Some("He said \"hi\" \"there\""));
+        assert_eq!(episode.path, Some(PathBuf::from("/podcasts/show/hi.mp3")));
+
+        let episode = parse("guid-1 1700000000 \"Part \"A\"\" \"\"");
+        assert_eq!(episode.title.as_deref(), Some("Part \"A\""));
+        assert_eq!(episode.path, None);
+    }
+
+    #[test]
+    fn tracker_round_trip() {
+        let dir = testing::temp_dir("tracker");
+        let tracker = dir.join(".downloaded");
+        let titles = ["Episode 1", "Part \"A\" \"B\"", "\"Quoted\""];
+
+        for (i, title) in titles.iter().enumerate() {
+            let path = dir.join(format!("{}.mp3", i));
+            DownloadedEpisodes::append(&tracker, &i.to_string(), title, Some(&path)).unwrap();
+        }
+        DownloadedEpisodes::append(&tracker, "no-path", "Part \"A\" \"B\"", None).unwrap();
+
+        let episodes = DownloadedEpisodes::load(&tracker);
+        for (i, title) in titles.iter().enumerate() {
+            let episode = episodes.0.get(&i.to_string()).unwrap();
+            assert_eq!(episode.title.as_deref(), Some(*title));
+            assert_eq!(episode.path, Some(dir.join(format!("{}.mp3", i))));
+        }
+        let episode = episodes.0.get("no-path").unwrap();
+        assert_eq!(episode.title.as_deref(), Some("Part \"A\" \"B\""));
+        assert_eq!(episode.path, None);
+    }
+
+    #[test]
+    fn set_path_keeps_legacy_titles() {
+        let dir = testing::temp_dir("tracker-set-path");
+        let tracker = dir.join(".downloaded");
+        fs::write(&tracker, "guid-1 1700000000 \"Part \"A\" \"B\"\"\n").unwrap();
+
+        DownloadedEpisodes::set_path(&tracker, "guid-1", &dir.join("new.mp3")).unwrap();
+
+        let episodes = DownloadedEpisodes::load(&tracker);
+        let episode = episodes.0.get("guid-1").unwrap();
+        assert_eq!(episode.title.as_deref(), Some("Part \"A\" \"B\""));
+        assert_eq!(episode.path, Some(dir.join("new.mp3")));
+    }
 }
@@ -1,19 +1,51 @@
 use crate::cache;
+use crate::config::BitratePreference;
 use crate::config::Config;
 use crate::config::DownloadMode;
+use crate::config::GuidFallback;
+use crate::config::MediaKind;
+use crate::dedup::MediaIndex;
 use crate::display::DownloadBar;
+use crate::download_tracker::DownloadNumbers;
 use crate::download_tracker::DownloadedEpisodes;
+use crate::download_tracker::TagHashes;
+use crate::error::DownloadError;
+use crate::error::Error;
+use crate::error::TagError;
+use crate::observer::SyncObserver;
+use crate::patterns;
+use crate::podcast;
+use crate::ratelimit;
+use crate::tags;
+use crate::tags::Tags;
 use crate::utils;
 use futures_util::StreamExt;
+use indicatif::HumanBytes;
+use lofty::file::TaggedFileExt;
+use lofty::picture::PictureType;
+use lofty::tag::TagType;
 use std::cmp;
+use std::collections::BTreeMap;
+use std::collections::HashMap;
 use std::fs;
 use std::io::Seek;
 use std::io::Write as IOWrite;
 use std::path::Path;
 use std::path::PathBuf;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
 use std::time;
+use tokio::sync::OwnedSemaphorePermit;
+use tokio::sync::Semaphore;
 use tokio::task::JoinHandle;
 
+/// Longest guid that's used as is for the name of a partial download.
+const MAX_PARTIAL_NAME_LEN: usize = 200;
+
+/// Bytes at the end of an existing file compared with the enclosure, for `reuse_intact_files`.
+const INTACT_CHECK_SIZE: u64 = 64 * 1024;
+
 pub trait XmlWrapper {
     fn inner(&self) -> &serde_json::Map<String, serde_json::Value>;
 
@@ -57,6 +89,16 @@ impl RawEpisode {
     }
 }
 
+/// A media file of an episode.
+struct MediaCandidate<'a> {
+    url: &'a str,
+    mime: Option<&'a str>,
+    kind: Option<MediaKind>,
+    bitrate: Option<f64>,
+    /// The `length` of an enclosure or the `fileSize` of a `media:content`, in bytes.
+    size: Option<u64>,
+}
+
 #[derive(Debug, Clone)]
 pub struct Attributes {
     pub title: String,
@@ -64,13 +106,22 @@ pub struct Attributes {
     pub mime: Option<String>,
     pub guid: String,
     pub published: time::Duration,
+    /// Offset that dates of the episode are shown in, the one of its `pubDate` by default.
+    pub offset: chrono::FixedOffset,
+    /// The `itunes:explicit` of the episode, or of the channel if the episode has none.
+    pub explicit: Option<bool>,
+    /// Size of the selected media in bytes, as reported by the feed.
+    pub size: Option<u64>,
     pub raw: RawEpisode,
 }
 
 impl Attributes {
-    pub fn new(raw: RawEpisode) -> Result<Self, String> {
+    pub fn new(raw: RawEpisode, guid_fallback: GuidFallback) -> Result<Self, String> {
         let title = raw.get_string("title")?;
-        let enclosure = raw.get_val("enclosure")?;
+        let enclosure = match raw.get_val("enclosure")? {
+            serde_json::Value::Array(vals) => vals.first().ok_or("empty enclosure")?,
+            enclosure => enclosure,
+        };
 
         let url = enclosure
             .get("@url")
@@ -82,24 +133,69 @@ impl Attributes {
             .get("@type")
             .and_then(|x| Some(x.as_str()?.to_string()));
 
-        let published = raw.get_str("pubDate")?;
-        let published = utils::date_str_to_unix(published)?;
-        let guid = raw.get_string("guid")?;
+        let pubdate = raw.get_str("pubDate")?;
+        let published = utils::date_str_to_unix(pubdate)?;
+        let offset = utils::date_str_offset(pubdate).unwrap_or_else(|| {
+            use chrono::Offset;
+            chrono::Utc.fix()
+        });
+        let guid = match raw.get_string("guid") {
+            Ok(guid) => guid,
+            Err(_) => Self::fallback_guid(guid_fallback, &title, &url, published),
+        };
+
+        let explicit = raw
+            .get_str("itunes:explicit")
+            .ok()
+            .and_then(utils::parse_explicit);
 
-        Ok(Self {
+        let mut attrs = Self {
             title,
             url,
             mime,
             guid,
             published,
+            offset,
+            explicit,
+            size: None,
             raw,
-        })
+        };
+        attrs.size = Self::media_size(&attrs.media_candidates(), &attrs.url);
+
+        Ok(attrs)
+    }
+
+    /// Key for an episode without a `<guid>`. The `talecast:` prefix marks it as synthesized in
+    /// the download tracker, and it only depends on the feed so it's the same on every run.
+    fn fallback_guid(
+        fallback: GuidFallback,
+        title: &str,
+        url: &str,
+        published: time::Duration,
+    ) -> String {
+        match fallback {
+            GuidFallback::EnclosureUrl => format!("talecast:url:{}", url),
+            GuidFallback::TitleDate => {
+                format!("talecast:title-date:{}@{}", title, published.as_secs())
+            }
+            GuidFallback::Hash => {
+                let hash = cache::sha1_hex(format!("{}{}", title, url).as_bytes());
+                format!("talecast:sha1:{}", hash)
+            }
+        }
     }
 
     pub fn published(&self) -> time::Duration {
         self.published
     }
 
+    /// The publish date in the episode's `offset`.
+    pub fn published_at(&self) -> chrono::DateTime<chrono::FixedOffset> {
+        chrono::DateTime::from_timestamp(self.published.as_secs() as i64, 0)
+            .unwrap_or_default()
+            .with_timezone(&self.offset)
+    }
+
     pub fn _mime(&self) -> Option<&str> {
         self.mime.as_deref()
     }
@@ -116,13 +212,36 @@ impl Attributes {
         &self.url
     }
 
+    /// The `<link>` of the episode, i.e. its web page rather than the enclosure.
+    pub fn link(&self) -> Option<&str> {
+        match self.raw.get_val("link").ok()? {
+            serde_json::Value::Array(vals) => vals.iter().find_map(|val| val.as_str()),
+            val => val.as_str(),
+        }
+    }
+
     pub fn get_str(&self, key: &str) -> Result<&str, String> {
         self.raw.get_str(key)
     }
 
+    /// The text of an element of the item, for `{rss::episode::key}`. See [`utils::xml_lookup`].
+    pub fn rss_value(&self, key: &str) -> Option<String> {
+        utils::xml_lookup(self.raw.inner(), key)
+    }
+
+    /// The episode's own artwork, from `itunes:image` or else `media:thumbnail`.
     pub fn image(&self) -> Result<&str, String> {
         let key = "itunes:image";
-        self.raw.get_url(key)
+        self.raw.get_url(key).or_else(|e| {
+            self.media_groups()
+                .into_iter()
+                .filter_map(|group| match group.get("media:thumbnail")? {
+                    serde_json::Value::Array(vals) => vals.iter().find_map(utils::val_to_url),
+                    val => utils::val_to_url(val),
+                })
+                .next()
+                .ok_or(e)
+        })
     }
     pub fn author(&self) -> Result<&str, String> {
         self.get_str("author")
@@ -132,24 +251,244 @@ impl Attributes {
         self.get_str("description")
     }
 
+    /// The `content:encoded` show notes. The namespace isn't preserved, so it's under `encoded`.
+    pub fn content_encoded(&self) -> Result<&str, String> {
+        self.get_str("encoded")
+    }
+
     pub fn itunes_episode(&self) -> Result<&str, String> {
         let key = "itunes:episode";
         self.get_str(&key)
     }
 
-    pub fn itunes_duration(&self) -> Result<&str, String> {
-        let key = "itunes:duration";
-        self.get_str(&key)
+    pub fn itunes_season(&self) -> Result<&str, String> {
+        let key = "itunes:season";
+        self.get_str(key)
+    }
+
+    /// The lowercased `itunes:episodeType`, which is `full` if missing.
+    pub fn episode_type(&self) -> String {
+        self.get_str("itunes:episodeType")
+            .map(|ty| ty.trim().to_lowercase())
+            .unwrap_or_else(|_| "full".to_string())
+    }
+
+    /// The episode itself followed by its `media:group` elements, which can both hold media.
+    fn media_groups(&self) -> Vec<&serde_json::Map<String, serde_json::Value>> {
+        let mut groups = vec![self.raw.inner()];
+
+        if let Ok(group) = self.raw.get_val("media:group") {
+            match group {
+                serde_json::Value::Array(vals) => {
+                    groups.extend(vals.iter().filter_map(|val| val.as_object()))
+                }
+                val => groups.extend(val.as_object()),
+            }
+        }
+
+        groups
+    }
+
+    /// The `media:content` elements of the episode, including those inside a `media:group`.
+    pub fn media_contents(&self) -> Vec<&serde_json::Map<String, serde_json::Value>> {
+        let mut contents = vec![];
+
+        for group in self.media_groups() {
+            match group.get("media:content") {
+                Some(serde_json::Value::Array(vals)) => {
+                    contents.extend(vals.iter().filter_map(|val| val.as_object()))
+                }
+                Some(val) => contents.extend(val.as_object()),
+                None => {}
+            }
+        }
+
+        contents
+    }
+
+    /// The media files of the episode, from its enclosures and `media:content` elements.
+    fn media_candidates(&self) -> Vec<MediaCandidate<'_>> {
+        let enclosures = match self.raw.get_val("enclosure") {
+            Ok(serde_json::Value::Array(vals)) => {
+                vals.iter().filter_map(|val| val.as_object()).collect()
+            }
+            Ok(val) => val.as_object().into_iter().collect(),
+            Err(_) => vec![],
+        };
+
+        enclosures
+            .into_iter()
+            .chain(self.media_contents())
+            .filter_map(|media| {
+                let url = media.get("@url").and_then(utils::val_to_str)?;
+                let mime = media.get("@type").and_then(utils::val_to_str);
+                let medium = media.get("@medium").and_then(utils::val_to_str);
+                let kind = match (medium, mime) {
+                    (Some("audio"), _) => Some(MediaKind::Audio),
+                    (Some("video"), _) => Some(MediaKind::Video),
+                    (_, Some(mime)) if mime.starts_with("audio/") => Some(MediaKind::Audio),
+                    (_, Some(mime)) if mime.starts_with("video/") => Some(MediaKind::Video),
+                    _ => None,
+                };
+                let bitrate = media.get("@bitrate").and_then(|val| {
+                    val.as_f64()
+                        .or_else(|| utils::val_to_str(val)?.trim().parse().ok())
+                });
+                let size = media
+                    .get("@length")
+                    .or_else(|| media.get("@fileSize"))
+                    .and_then(utils::val_to_u64)
+                    .filter(|size| *size > 0);
+
+                Some(MediaCandidate {
+                    url,
+                    mime,
+                    kind,
+                    bitrate,
+                    size,
+                })
+            })
+            .collect()
+    }
+
+    /// The size of the media at `url`, from the first candidate with that url that has one.
+    ///
+    /// A `media:content` often repeats the enclosure with the `fileSize` the enclosure lacks.
+    fn media_size(candidates: &[MediaCandidate], url: &str) -> Option<u64> {
+        candidates
+            .iter()
+            .filter(|candidate| candidate.url == url)
+            .find_map(|candidate| candidate.size)
+    }
+
+    /// Switches to the best media file of the episode, if it has several.
+    ///
+    /// Media of the preferred kind ranks first, then those of unknown kind. Among those, the
+    /// bitrate preference decides, and otherwise the enclosure wins. Returns the chosen URL
+    /// if it's not the enclosure.
+    pub fn select_media(
+        &mut self,
+        kind: MediaKind,
+        bitrate: Option<BitratePreference>,
+    ) -> Option<String> {
+        let candidates = self.media_candidates();
+        if candidates.len() < 2 {
+            return None;
+        }
+
+        let rank = |candidate: &MediaCandidate| {
+            let kind_rank = match candidate.kind {
+                Some(candidate_kind) if candidate_kind == kind => 0,
+                None => 1,
+                Some(_) => 2,
+            };
+
+            // kbps, since f64 isn't Ord.
+            let kbps = candidate.bitrate.map(|bitrate| bitrate as u64);
+            let bitrate_rank = match bitrate {
+                Some(BitratePreference::Highest) => u64::MAX - kbps.unwrap_or(0),
+                Some(BitratePreference::Lowest) => kbps.unwrap_or(u64::MAX),
+                None => 0,
+            };
+
+            (kind_rank, bitrate_rank)
+        };
+
+        // min_by_key returns the first of equal elements, so the enclosure wins ties.
+        let best = candidates.iter().min_by_key(|candidate| rank(candidate))?;
+        if best.url == self.url {
+            return None;
+        }
+
+        let url = best.url.to_string();
+        let mime = best.mime.map(ToString::to_string);
+        let size = Self::media_size(&candidates, best.url);
+        self.url = url.clone();
+        self.mime = mime;
+        self.size = size;
+        Some(url)
+    }
+
+    /// The `podcast:transcript` links of the episode, as `(url, type)` pairs.
+    pub fn transcripts(&self) -> Vec<(&str, &str)> {
+        let transcripts = match self.raw.get_val("podcast:transcript") {
+            Ok(serde_json::Value::Array(vals)) => vals.iter().collect(),
+            Ok(val) => vec![val],
+            Err(_) => vec![],
+        };
+
+        transcripts
+            .into_iter()
+            .filter_map(|val| {
+                let url = val.get("@url").and_then(utils::val_to_str)?;
+                let ty = val.get("@type").and_then(utils::val_to_str).unwrap_or("");
+                Some((url, ty))
+            })
+            .collect()
+    }
+
+    /// The `podcast:soundbite` highlights of the episode, as `(start, duration)` in seconds.
+    pub fn soundbites(&self) -> Vec<(f64, f64)> {
+        let soundbites = match self.raw.get_val("podcast:soundbite") {
+            Ok(serde_json::Value::Array(vals)) => vals.iter().collect(),
+            Ok(val) => vec![val],
+            Err(_) => vec![],
+        };
+
+        let secs = |val: &serde_json::Value, key: &str| -> Option<f64> {
+            let val = val.get(key)?;
+            val.as_f64()
+                .or_else(|| utils::val_to_str(val)?.trim().parse().ok())
+        };
+
+        soundbites
+            .into_iter()
+            .filter_map(|val| Some((secs(val, "@startTime")?, secs(val, "@duration")?)))
+            .filter(|(start, duration)| *start >= 0. && *duration > 0.)
+            .collect()
+    }
+
+    /// Size of the media chosen by [`Self::select_media`] in bytes, as reported by the feed.
+    ///
+    /// If its entry has no size, the size given for the same url elsewhere in the item is used,
+    /// like the `fileSize` of a `media:content` repeating the enclosure.
+    pub fn file_size(&self) -> Option<u64> {
+        self.size
+    }
+
+    /// Duration of the episode, as reported by the feed.
+    ///
+    /// Falls back to the `duration` of `media:content` if `itunes:duration` is missing.
+    pub fn duration(&self) -> Option<time::Duration> {
+        let itunes_duration =
+            self.raw
+                .get_val("itunes:duration")
+                .ok()
+                .and_then(|val| match utils::val_to_str(val) {
+                    Some(s) => utils::parse_duration_secs(s),
+                    None => utils::val_to_u64(val),
+                });
+
+        itunes_duration
+            .filter(|secs| *secs > 0)
+            .or_else(|| {
+                self.media_contents()
+                    .into_iter()
+                    .find_map(|content| content.get("@duration").and_then(utils::val_to_u64))
+            })
+            .map(time::Duration::from_secs)
     }
 }
 
 #[derive(Debug, Clone)]
 pub struct Episode {
     pub config: Config,
-    pub tags: Option<id3::Tag>,
+    pub tags: Option<Tags>,
     pub index: usize,
     pub attrs: Attributes,
     pub image_url: Option<String>,
+    /// Contents of the `.nfo` sidecar, if `write_nfo` is enabled.
+    pub nfo: Option<String>,
 }
 
 impl Episode {
@@ -157,8 +496,9 @@ impl Episode {
         attrs: Attributes,
         index: usize,
         config: Config,
-        tags: Option<id3::Tag>,
+        tags: Option<Tags>,
         image_url: Option<String>,
+        nfo: Option<String>,
     ) -> Self {
         Self {
             attrs,
@@ -166,6 +506,7 @@ impl Episode {
             tags,
             index,
             image_url,
+            nfo,
         }
     }
 
@@ -195,7 +536,7 @@ impl Episode {
         ui.log_debug(msg);
     }
 
-    fn is_downloaded(&self) -> bool {
+    pub fn is_downloaded(&self) -> bool {
         let id = self.get_id();
         let path = self.tracker_path();
         DownloadedEpisodes::load(&path).contains_episode(&id)
@@ -206,8 +547,8 @@ impl Episode {
             
             DownloadMode::Backlog { start, interval, max_episodes: _ } => {
                 
-                let time_passed = utils::current_unix() - *start;
-                let intervals_passed = time_passed.as_secs() / interval.as_secs();
+                let time_passed = utils::current_unix().saturating_sub(*start);
+                let intervals_passed = time_passed.as_secs() / interval.as_secs().max(1);
                 intervals_passed >= self.index as u64
             }
 
@@ -217,7 +558,7 @@ impl Episode {
                 earliest_date,
             } => {
                 let max_time_exceeded = max_time.map_or(false, |max_time| {
-                    (utils::current_unix() - self.attrs.published) > max_time
+                    utils::current_unix().saturating_sub(self.attrs.published) > max_time
                 });
 
                 let episode_too_old =
@@ -227,24 +568,185 @@ impl Episode {
             }
         };
 
-        passed_filter && !self.is_downloaded()
+        passed_filter
+            && self.within_size_limit()
+            && self.matches_tag_filters()
+            && self.matches_title_filters()
+            && !(self.config.skip_explicit && self.attrs.explicit == Some(true))
+            && !self.is_downloaded()
+    }
+
+    /// Checks `max_file_size`. Episodes where the feed doesn't report the size pass the check.
+    fn within_size_limit(&self) -> bool {
+        match (self.config.max_file_size, self.attrs.file_size()) {
+            (Some(max_size), Some(size)) => size <= max_size,
+            _ => true,
+        }
+    }
 
+    /// Checks the `require_tag` and `skip_tag` filters against the episode's values.
+    /// A missing key is treated as an empty value.
+    fn matches_tag_filters(&self) -> bool {
+        let value = |key: &str| self.attrs.get_str(key).unwrap_or_default();
+
+        let filters = &self.config.tag_filters;
+        filters
+            .require
+            .iter()
+            .all(|(key, regex)| regex.is_match(value(key)))
+            && !filters
+                .skip
+                .iter()
+                .any(|(key, regex)| regex.is_match(value(key)))
+    }
+
+    /// Checks the `only_titles` and `skip_titles` lists, which match case-insensitive substrings.
+    /// A title in `only_titles` is downloaded even if it's also in `skip_titles`.
+    fn matches_title_filters(&self) -> bool {
+        let title = self.attrs.title().to_lowercase();
+        let contains_any = |titles: &[String]| titles.iter().any(|t| title.contains(t.as_str()));
+
+        if !self.config.only_titles.is_empty() {
+            return contains_any(&self.config.only_titles);
+        }
+
+        !contains_any(&self.config.skip_titles)
+    }
+
+    /// The path of the file in the same directory, named according to the `name_pattern`.
+    pub fn named_path(&self, current: &Path, download_number: u64) -> PathBuf {
+        let config = &self.config;
+        let name = patterns::eval_download_number(&config.name_pattern, download_number);
+        let name = patterns::eval_tags(&name, |field| {
+            tags::lookup(self.tags.as_ref(), config, field)
+        });
+        let new_name = config
+            .filename_transform
+            .apply(&name, config.space_replacement);
+        let new_name = sanitize_filename::sanitize(new_name);
+
+        let dir = current.parent().unwrap_or(Path::new(""));
+        let extension = current.extension().map(|ext| ext.to_string_lossy());
+        let new_name = utils::fit_file_name(
+            &new_name,
+            extension.as_deref(),
+            dir,
+            config.max_filename_length,
+            config.max_path_length,
+        );
+
+        current.with_file_name(new_name)
+    }
+
+    /// Compares the size of the downloaded file with the length reported by the feed.
+    ///
+    /// Only a clear mismatch fails, since many feeds report lengths that are slightly off.
+    fn verify_size(&self, size: u64, ui: &DownloadBar) -> Result<(), String> {
+        let expected = self.attrs.file_size();
+
+        match SizeCheck::classify(expected, size) {
+            SizeCheck::Exact => self.log_trace(ui, "size matches the feed"),
+            SizeCheck::WithinTolerance => self.log_debug(
+                ui,
+                format!("size {} is close to the {:?} in the feed", size, expected),
+            ),
+            SizeCheck::Unverifiable => self.log_debug(
+                ui,
+                "can't verify size, the feed didn't provide a reliable length",
+            ),
+            SizeCheck::Mismatch => {
+                return Err(format!(
+                    "size mismatch, got {} of {} bytes reported by the feed",
+                    size,
+                    expected.unwrap_or_default()
+                ))
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Fails if the download path has less free space than `min_free_space`.
+    pub fn check_free_space(&self) -> Result<(), String> {
+        let Some(min_free_space) = self.config.min_free_space else {
+            return Ok(());
+        };
+
+        let available = fs2::available_space(&self.config.download_path)
+            .map_err(|_| "failed to check free disk space".to_string())?;
+
+        if available < min_free_space {
+            return Err(format!(
+                "low disk space: {} free, 'min_free_space' is {}",
+                HumanBytes(available),
+                HumanBytes(min_free_space)
+            ));
+        }
+
+        Ok(())
     }
 
     /// Filename of episode when it's being downloaded.
+    ///
+    /// Guids that aren't valid filenames, like permalinks, are hashed so that different guids
+    /// can't end up with the same partial file.
     fn partial_name(&self) -> String {
+        let guid = &self.attrs.guid;
+        let file_name = sanitize_filename::sanitize(guid);
+        if file_name.is_empty() || file_name != *guid || file_name.len() > MAX_PARTIAL_NAME_LEN {
+            format!("{}.partial", cache::hashed_url(guid))
+        } else {
+            format!("{}.partial", file_name)
+        }
+    }
+
+    /// Filename that older versions gave the partial download, which is still resumed.
+    fn legacy_partial_name(&self) -> Option<String> {
         let file_name = sanitize_filename::sanitize(&self.attrs.guid);
-        format!("{}.partial", file_name)
+        (!file_name.is_empty()).then(|| format!("{}.partial", file_name))
     }
 
-    fn get_id(&self) -> String {
+    pub fn get_id(&self) -> String {
         self.config.id_pattern.replace(" ", "_")
     }
 
-    fn tracker_path(&self) -> &Path {
+    pub fn tracker_path(&self) -> &Path {
         self.config.tracker_path.as_path()
     }
 
+    /// Hash of the values the tags of the episode are written from.
+    pub fn tag_hash(&self) -> String {
+        let items: Vec<String> = self
+            .tags
+            .iter()
+            .flat_map(|tags| tags.items())
+            .map(|item| format!("{:?}", item))
+            .collect();
+        let custom: BTreeMap<_, _> = self.config.tags.iter().collect();
+        let id3: BTreeMap<_, _> = self.config.id3_tags.iter().collect();
+
+        cache::hashed_url(&format!("{:?} {:?} {:?}", items, custom, id3))
+    }
+
+    /// Marks an episode downloaded by another podcatcher as downloaded.
+    ///
+    /// Returns false if it's already marked.
+    pub fn mark_imported(&self, path: Option<PathBuf>) -> Result<bool, String> {
+        if self.is_downloaded() {
+            return Ok(false);
+        }
+
+        let path = path.filter(|path| path.is_file());
+        DownloadedEpisodes::append(
+            self.tracker_path(),
+            &self.get_id(),
+            self.attrs.title(),
+            path.as_deref(),
+        )?;
+
+        Ok(true)
+    }
+
     fn into_downloaded(&self, path: PathBuf) -> DownloadedEpisode<'_> {
         DownloadedEpisode::new(self, path)
     }
@@ -253,61 +755,278 @@ impl Episode {
         &'a self,
         client: &reqwest::Client,
         ui: &DownloadBar,
-    ) -> Result<DownloadedEpisode<'a>, String> {
-        self.log_debug(ui, "downloading episode");
-        let audio_file = self.download_enclosure(client, ui).await?;
-        let mut episode = self.into_downloaded(audio_file);
-        episode.process(ui).await?;
+        observer: &dyn SyncObserver,
+    ) -> Result<DownloadedEpisode<'a>, Error> {
+        let download_error = |reason| DownloadError {
+            podcast: self.config.podcast_name.clone(),
+            episode: self.attrs.title().to_string(),
+            url: self.attrs.url().to_string(),
+            reason,
+        };
+
+        let mut episode = if let Some(episode) = self.link_duplicate(ui).map_err(download_error)? {
+            episode
+        } else if let Some(episode) = self.reuse_intact_file(client, ui).await {
+            episode
+        } else {
+            self.log_debug(ui, "downloading episode");
+            let (audio_file, bytes, resumed) = self
+                .download_enclosure(client, ui, observer)
+                .await
+                .map_err(download_error)?;
+            let mut episode = self.into_downloaded(audio_file);
+            episode.bytes = bytes;
+            episode.resumed = resumed;
+            episode
+        };
+
+        let download_number = self
+            .config
+            .name_pattern
+            .contains("{download_number")
+            .then(|| DownloadNumbers::reserve(&self.config.tracker_path));
+        episode.download_number = download_number.as_ref().map_or(0, |n| n.number());
+        episode.process(client, ui).await.map_err(download_error)?;
+        episode.download_transcript(client, ui).await;
+        episode.write_nfo(ui);
+        if let Err(e) = episode.extract_soundbites(ui).await {
+            self.log_warn(ui, format!("failed to extract soundbites: {}", e));
+        }
         episode.run_download_hook(ui);
-        episode.mark_downloaded()?;
+        episode.mark_downloaded().map_err(download_error)?;
+
+        if let Some(download_number) = download_number {
+            if let Err(e) = download_number.commit() {
+                self.log_warn(ui, e);
+            }
+        }
+
+        if self.config.global_dedup && !episode.deduplicated {
+            if let Err(e) = MediaIndex::append(self.attrs.url(), episode.path()) {
+                self.log_warn(ui, e);
+            }
+        }
+
         Ok(episode)
     }
 
+    /// Keeps the file that's already at the path of the episode instead of downloading it
+    /// again, if `reuse_intact_files` is enabled and its end matches the end of the enclosure.
+    ///
+    /// Only the end is compared, as the tags at the start of the file may have changed. The
+    /// file is then tagged and tracked like a new download.
+    async fn reuse_intact_file(
+        &self,
+        client: &reqwest::Client,
+        ui: &DownloadBar,
+    ) -> Option<DownloadedEpisode<'_>> {
+        let config = &self.config;
+        // The download number in the name of the existing file can't be known.
+        if !config.reuse_intact_files || config.name_pattern.contains("{download_number") {
+            return None;
+        }
+
+        // Looked for before asking the server, so episodes without a file cost no request.
+        let candidate_exists = utils::get_extensions_from_feed(self)
+            .into_iter()
+            .any(|extension| {
+                let mut path = config.download_path.join(self.partial_name());
+                path.set_extension(extension);
+                self.named_path(&path, 0).is_file()
+            });
+        if !candidate_exists {
+            return None;
+        }
+
+        let url = self.attrs.url();
+        let range = format!("bytes=-{}", INTACT_CHECK_SIZE);
+        let mut request = config
+            .request
+            .apply(client.get(url), url)
+            .header(reqwest::header::RANGE, range);
+        if let Some(referer) = &config.referer {
+            request = request.header(reqwest::header::REFERER, referer);
+        }
+
+        // Servers that ignore the range would send the whole file.
+        let response = utils::short_handle_response(request.send().await).ok()?;
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return None;
+        }
+
+        let (extension, _) = utils::get_extension_from_response(&response, self);
+        let mut path = config.download_path.join(self.partial_name());
+        path.set_extension(extension);
+        let path = self.named_path(&path, 0);
+        if !path.is_file() {
+            return None;
+        }
+
+        let remote_tail = response.bytes().await.ok()?;
+        let mut file = fs::File::open(&path).ok()?;
+        let offset = i64::try_from(remote_tail.len()).ok()?;
+        file.seek(std::io::SeekFrom::End(-offset)).ok()?;
+        let mut local_tail = vec![0; remote_tail.len()];
+        std::io::Read::read_exact(&mut file, &mut local_tail).ok()?;
+
+        if local_tail != remote_tail {
+            self.log_debug(ui, format!("existing file differs: {:?}", &path));
+            return None;
+        }
+
+        self.log_debug(ui, format!("reusing intact file: {:?}", &path));
+        Some(self.into_downloaded(path))
+    }
+
+    /// Links to the file of an episode with the same enclosure that's already downloaded,
+    /// if `global_dedup` is enabled.
+    fn link_duplicate(&self, ui: &DownloadBar) -> Result<Option<DownloadedEpisode<'_>>, String> {
+        if !self.config.global_dedup {
+            return Ok(None);
+        }
+
+        let Some(existing) = MediaIndex::lookup(self.attrs.url()) else {
+            return Ok(None);
+        };
+
+        self.log_debug(
+            ui,
+            format!("linking to already downloaded file: {:?}", &existing),
+        );
+
+        let mut path = self.config.download_path.join(self.partial_name());
+        if let Some(extension) = existing.extension() {
+            path.set_extension(extension);
+        }
+
+        MediaIndex::link(&existing, &path)?;
+        let mut episode = self.into_downloaded(path);
+        episode.deduplicated = true;
+        Ok(Some(episode))
+    }
+
     async fn download_enclosure<'a>(
         &'a self,
         client: &reqwest::Client,
         ui: &DownloadBar,
-    ) -> Result<PathBuf, String> {
+        observer: &dyn SyncObserver,
+    ) -> Result<(PathBuf, u64, bool), String> {
         let config = &self.config;
+        let _host_permit = host_permit(self.attrs.url(), config.max_connections_per_host).await;
 
         let partial_path = config
             .partial_path
             .clone()
             .unwrap_or_else(|| config.download_path.clone())
             .join(self.partial_name());
+        self.adopt_legacy_partial(&partial_path);
 
         let mut file = fs::OpenOptions::new()
             .write(true)
             .create(true)
             .open(&partial_path)
-            .map_err(|_| "failed to write file".to_string())?;
+            .map_err(|e| format!("failed to write file {:?}: {}", &partial_path, e))?;
 
-        let mut downloaded = file
-            .seek(std::io::SeekFrom::End(0))
-            .map_err(|_| "file error".to_string())?;
+        // The ETag of the partial download, to check that a resumed download is the same file.
+        let etag_path = partial_path.with_extension("partial.etag");
 
         self.log_trace(ui, format!("connecting to url: {:?}", self.as_ref().url()));
-        let response = client
-            .get(self.as_ref().url())
-            .header(reqwest::header::RANGE, format!("bytes={}-", downloaded))
-            .send()
-            .await;
+        let mut enclosure = EnclosureUrl {
+            url: self.as_ref().url().to_string(),
+            refreshed: false,
+        };
+        let (mut response, mut downloaded) = self
+            .resume(client, &mut enclosure, &mut file, &etag_path, ui)
+            .await?;
+        let resumed = downloaded > 0;
+        let limiters = ratelimit::limiters(
+            &config.podcast_name,
+            config.download_rate,
+            config.total_download_rate,
+        );
+        let (extension, source) = utils::get_extension_from_response(&response, self);
+        self.log_debug(
+            ui,
+            format!(
+                "file extension {:?}, from {}",
+                &extension,
+                source.map_or("none of the extension sources", |source| source.describe())
+            ),
+        );
+        let mut transferred = 0;
+        let mut retries = 0;
+
+        loop {
+            // The content length of a partial response is only the remaining bytes.
+            let total_size = response
+                .content_length()
+                .map(|len| len + downloaded)
+                .unwrap_or(0);
+
+            observer.on_episode_progress(downloaded, total_size);
+
+            let mut stream = response.bytes_stream();
+            let mut interrupted = false;
+
+            while let Some(item) = stream.next().await {
+                let Ok(chunk) = item else {
+                    interrupted = true;
+                    break;
+                };
 
-        let response = utils::short_handle_response(response)?;
+                file.write_all(&chunk)
+                    .map_err(|_| "failed to write chunk to file".to_string())?;
+                transferred += chunk.len() as u64;
+                downloaded += chunk.len() as u64;
+                observer.on_episode_progress(cmp::min(downloaded, total_size), total_size);
+
+                for limiter in &limiters {
+                    limiter.consume(chunk.len()).await;
+                }
+            }
 
-        let total_size = response.content_length().unwrap_or(0);
-        let extension = utils::get_extension_from_response(&response, &self);
+            if !interrupted {
+                break;
+            }
 
-        ui.init_download_bar(downloaded, total_size);
+            // Retries from where the download stopped, waiting longer after each attempt.
+            loop {
+                retries += 1;
+                if retries > Self::CHUNK_RETRIES {
+                    return Err("failed to load chunk".to_string());
+                }
 
-        let mut stream = response.bytes_stream();
+                let delay = time::Duration::from_secs(1 << (retries - 1));
+                self.log_warn(
+                    ui,
+                    format!(
+                        "connection interrupted, resuming in {}s ({}/{})",
+                        delay.as_secs(),
+                        retries,
+                        Self::CHUNK_RETRIES
+                    ),
+                );
+                tokio::time::sleep(delay).await;
+
+                match self
+                    .resume(client, &mut enclosure, &mut file, &etag_path, ui)
+                    .await
+                {
+                    Ok(resumed) => {
+                        (response, downloaded) = resumed;
+                        break;
+                    }
+                    Err(e) => self.log_warn(ui, e),
+                }
+            }
+        }
 
-        while let Some(item) = stream.next().await {
-            let chunk = item.map_err(|_| "failed to load chunk".to_string())?;
-            file.write_all(&chunk)
-                .map_err(|_| "failed to write chunk to file".to_string())?;
-            downloaded = cmp::min(downloaded + (chunk.len() as u64), total_size);
-            ui.set_progress(downloaded);
+        if config.verify_size {
+            let size = file
+                .metadata()
+                .map_err(|_| "failed to read size of file".to_string())?
+                .len();
+            self.verify_size(size, ui)?;
         }
 
         let path = {
@@ -320,8 +1039,225 @@ impl Episode {
         };
 
         fs::rename(partial_path, &path).map_err(|_| "failed to rename episode file".to_string())?;
+        let _ = fs::remove_file(&etag_path);
 
-        Ok(path)
+        Ok((path, transferred, resumed))
+    }
+
+    /// How many times an interrupted download is resumed before giving up.
+    const CHUNK_RETRIES: u32 = 5;
+
+    /// Moves a partial download that was started under the legacy name to `partial_path`, so
+    /// that it's resumed rather than started over.
+    fn adopt_legacy_partial(&self, partial_path: &Path) {
+        let Some(legacy_name) = self.legacy_partial_name() else {
+            return;
+        };
+
+        let legacy_path = partial_path.with_file_name(legacy_name);
+        if legacy_path == partial_path || partial_path.exists() || !legacy_path.is_file() {
+            return;
+        }
+
+        if fs::rename(&legacy_path, partial_path).is_ok() {
+            let _ = fs::rename(
+                legacy_path.with_extension("partial.etag"),
+                partial_path.with_extension("partial.etag"),
+            );
+        }
+    }
+
+    /// Requests the enclosure from the end of the partial `file`.
+    ///
+    /// If the server doesn't continue the same file, the partial file is emptied and the
+    /// download restarts. Returns the response and the bytes already in the file.
+    async fn resume(
+        &self,
+        client: &reqwest::Client,
+        enclosure: &mut EnclosureUrl,
+        file: &mut fs::File,
+        etag_path: &Path,
+        ui: &DownloadBar,
+    ) -> Result<(reqwest::Response, u64), String> {
+        let mut downloaded = file
+            .seek(std::io::SeekFrom::End(0))
+            .map_err(|_| "file error".to_string())?;
+
+        let etag = match downloaded {
+            0 => None,
+            _ => fs::read_to_string(etag_path).ok(),
+        };
+
+        let mut response = self
+            .request(client, enclosure, downloaded, etag.as_deref(), ui)
+            .await?;
+
+        if downloaded > 0 && !Self::resumes(&response, etag.as_deref()) {
+            self.log_warn(
+                ui,
+                format!(
+                    "server didn't resume the partial download (status {}), restarting",
+                    response.status()
+                ),
+            );
+
+            file.set_len(0)
+                .and_then(|_| file.seek(std::io::SeekFrom::Start(0)))
+                .map_err(|_| "failed to reset partial file".to_string())?;
+            downloaded = 0;
+
+            // A 200 response is already the full file.
+            if response.status() != reqwest::StatusCode::OK {
+                response = self.request(client, enclosure, 0, None, ui).await?;
+            }
+        }
+
+        match response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|etag| etag.to_str().ok())
+        {
+            Some(etag) => {
+                let _ = fs::write(etag_path, etag);
+            }
+            None => {
+                let _ = fs::remove_file(etag_path);
+            }
+        }
+
+        Ok((response, downloaded))
+    }
+
+    /// Requests the enclosure from the byte `start`.
+    ///
+    /// With an `etag`, the server sends the whole file instead if it has changed since.
+    /// If the URL is forbidden, it's assumed to be an expired signed URL, and a fresh one is
+    /// looked up in the feed, once per download.
+    async fn request(
+        &self,
+        client: &reqwest::Client,
+        enclosure: &mut EnclosureUrl,
+        start: u64,
+        etag: Option<&str>,
+        ui: &DownloadBar,
+    ) -> Result<reqwest::Response, String> {
+        let response = self
+            .send_request(client, &enclosure.url, start, etag)
+            .await?;
+        if response.status() != reqwest::StatusCode::FORBIDDEN || enclosure.refreshed {
+            return Self::check_status(response, start);
+        }
+
+        enclosure.refreshed = true;
+        self.log_warn(
+            ui,
+            "enclosure url is forbidden, refreshing it from the feed",
+        );
+        let Some(url) = podcast::refresh_enclosure_url(client, &self.config, &self.attrs.guid, ui)
+            .await
+            .filter(|url| url != &enclosure.url)
+        else {
+            return Self::check_status(response, start);
+        };
+
+        self.log_debug(ui, format!("refreshed enclosure url: {:?}", &url));
+        enclosure.url = url;
+        let response = self
+            .send_request(client, &enclosure.url, start, etag)
+            .await?;
+        Self::check_status(response, start)
+    }
+
+    /// Fails on error statuses, except when the range of a resumed download is rejected.
+    fn check_status(response: reqwest::Response, start: u64) -> Result<reqwest::Response, String> {
+        let status = response.status();
+        let range_rejected = start > 0 && status == reqwest::StatusCode::RANGE_NOT_SATISFIABLE;
+        if (status.is_client_error() || status.is_server_error()) && !range_rejected {
+            return Err(format!("server responded with {}", status));
+        }
+
+        Ok(response)
+    }
+
+    async fn send_request(
+        &self,
+        client: &reqwest::Client,
+        url: &str,
+        start: u64,
+        etag: Option<&str>,
+    ) -> Result<reqwest::Response, String> {
+        let config = &self.config;
+        let mut request = config
+            .request
+            .apply(client.get(url), url)
+            .header(reqwest::header::RANGE, format!("bytes={}-", start));
+
+        if let Some(etag) = etag {
+            request = request.header(reqwest::header::IF_RANGE, etag);
+        }
+
+        if let Some(referer) = &config.referer {
+            request = request.header(reqwest::header::REFERER, referer);
+        }
+
+        utils::short_handle_response(request.send().await)
+    }
+
+    /// Whether the response continues the partial download with the ETag `etag`.
+    ///
+    /// Servers that ignore the range send the whole file with a 200 status, and a different
+    /// ETag means the file changed since the partial download.
+    fn resumes(response: &reqwest::Response, etag: Option<&str>) -> bool {
+        if response.status() != reqwest::StatusCode::PARTIAL_CONTENT {
+            return false;
+        }
+
+        let new_etag = response
+            .headers()
+            .get(reqwest::header::ETAG)
+            .and_then(|etag| etag.to_str().ok());
+
+        match (etag, new_etag) {
+            (Some(etag), Some(new_etag)) => etag.trim() == new_etag.trim(),
+            _ => true,
+        }
+    }
+}
+
+/// The URL an episode is downloaded from, which can be refreshed once if it has expired.
+struct EnclosureUrl {
+    url: String,
+    refreshed: bool,
+}
+
+/// How the size of a downloaded file compares to the length reported by the feed.
+#[derive(Debug)]
+enum SizeCheck {
+    Exact,
+    /// Slightly smaller, or larger, which is common when ads are inserted dynamically.
+    WithinTolerance,
+    /// The feed didn't report a length, or reported 0.
+    Unverifiable,
+    /// Much smaller than reported, likely a truncated download.
+    Mismatch,
+}
+
+impl SizeCheck {
+    /// Files can be this much smaller than the reported length before it's a mismatch.
+    const TOLERANCE: f64 = 0.1;
+
+    fn classify(expected: Option<u64>, actual: u64) -> Self {
+        let Some(expected) = expected.filter(|expected| *expected > 0) else {
+            return Self::Unverifiable;
+        };
+
+        if actual == expected {
+            Self::Exact
+        } else if actual as f64 >= expected as f64 * (1. - Self::TOLERANCE) {
+            Self::WithinTolerance
+        } else {
+            Self::Mismatch
+        }
     }
 }
 
@@ -331,6 +1267,14 @@ pub struct DownloadedEpisode<'a> {
     path: PathBuf,
     /// The handle to the process of an optional post-download hook.
     handle: Option<JoinHandle<()>>,
+    /// Whether the file is linked to the same episode of another podcast.
+    deduplicated: bool,
+    /// Bytes transferred when downloading the episode.
+    bytes: u64,
+    /// Whether the download continued an earlier partial download.
+    resumed: bool,
+    /// Number of downloads of the podcast including this one, for the `{download_number}` pattern.
+    download_number: u64,
 }
 
 impl<'a> DownloadedEpisode<'a> {
@@ -339,6 +1283,10 @@ impl<'a> DownloadedEpisode<'a> {
             inner,
             path,
             handle: None,
+            deduplicated: false,
+            bytes: 0,
+            resumed: false,
+            download_number: 0,
         }
     }
 
@@ -347,66 +1295,108 @@ impl<'a> DownloadedEpisode<'a> {
     }
 
     pub fn mark_downloaded(&self) -> Result<(), String> {
-        let id = self.inner.config.id_pattern.replace(" ", "_");
+        let id = self.inner.get_id();
         let path = self.inner.config.tracker_path.as_path();
-        DownloadedEpisodes::append(&path, &id, self)
+        DownloadedEpisodes::append(path, &id, self.inner.attrs.title(), Some(self.path()))
     }
 
-    pub fn inner(&self) -> &Episode {
-        &self.inner
+    pub fn bytes(&self) -> u64 {
+        self.bytes
     }
 
     pub fn path(&self) -> &Path {
         &self.path
     }
 
-    pub async fn normalize_id3v2(&self, ui: &DownloadBar) {
-        use id3::TagLike;
-        if self.path.extension().is_some_and(|ext| ext == "mp3") {
-            self.inner.log_trace(ui, "normalizing id3 tags");
-            if let Some(xml_tags) = &self.inner.tags {
-                let mut file_tags = id3::Tag::read_from_path(&self.path()).unwrap_or_default();
+    /// Adds the tags of the episode to the file, in the tag format of its container, keeping the
+    /// values already in the file unless `overwrite` is true.
+    ///
+    /// Files in formats that can't be tagged are left alone.
+    pub async fn normalize_tags(
+        &self,
+        client: &reqwest::Client,
+        ui: &DownloadBar,
+        overwrite: bool,
+    ) -> Result<(), TagError> {
+        let Some(xml_tags) = &self.inner.tags else {
+            return Ok(());
+        };
 
-                for frame in xml_tags.frames() {
-                    if !file_tags.get(frame.id()).is_some() {
-                        file_tags.add_frame(frame.to_owned());
-                        self.inner
-                            .log_trace(ui, format!("adding frame: {:?}", &frame));
-                    }
-                }
+        let Some(mut file) = tags::read_file(&self.path).map_err(|e| self.tag_error(e))? else {
+            self.inner
+                .log_trace(ui, "skipping tags: format can't be tagged");
+            return Ok(());
+        };
 
-                for (id, value) in &self.inner.config.id3_tags {
-                    file_tags.set_text(id, value);
-                }
+        self.inner.log_trace(ui, "normalizing tags");
+        let has_cover = file
+            .primary_tag()
+            .is_some_and(|tag| tag.get_picture_type(PictureType::CoverFront).is_some());
+        let mut cover = None;
+        if let Some(img_url) = self.inner.image_url.as_ref().filter(|_| !has_cover) {
+            let resize = self.inner.config.artwork_resize;
+            cover = cache::get_image(client, img_url, PictureType::CoverFront, resize, ui).await;
+            if cover.is_none() {
+                self.inner
+                    .log_warn(ui, format!("failed to fetch image from url: {:?}", img_url));
+            }
+        }
 
-                if !file_tags
-                    .pictures()
-                    .any(|pic| pic.picture_type == id3::frame::PictureType::CoverFront)
-                {
-                    if let Some(img_url) = self.inner.image_url.as_ref() {
-                        if let Some(frame) =
-                            cache::get_image(img_url, id3::frame::PictureType::CoverFront, ui).await
-                        {
-                            file_tags.add_frame(frame);
-                            self.inner
-                                .log_debug(ui, "added cover image to podcast episode");
-                        } else {
-                            self.inner.log_warn(
-                                ui,
-                                format!("failed to fetch image from url: {:?}", img_url),
-                            );
-                        };
-                    }
-                }
+        let config = &self.inner.config;
+        let tag = tags::primary_tag(&mut file);
+        tags::write_to(tag, xml_tags, overwrite);
+        tags::write_to(tag, &Tags::from_fields(&config.tags), true);
+        if tag.tag_type() == TagType::Id3v2 {
+            tags::write_to(tag, &Tags::from_id3_frames(&config.id3_tags), true);
+        }
+        if !overwrite {
+            tags::write_to(tag, &Tags::from_fields(&config.tag_overrides), true);
+        }
 
-                if let Err(e) = file_tags.write_to_path(&self.path(), id3::Version::Id3v24) {
-                    ui.log_error(format!("failed to write tags to file: {:?}", e));
-                };
-            }
-        } else {
+        if let Some(cover) = cover {
+            tag.push_picture(cover);
             self.inner
-                .log_trace(ui, "skipping id3 tag normalization: enclosure not an mp3");
-        };
+                .log_debug(ui, "added cover image to podcast episode");
+        }
+
+        tags::save_file(&file, &self.path).map_err(|e| self.tag_error(e))
+    }
+
+    /// Writes the tags of the episode, then saves what they were written from.
+    ///
+    /// Failing to write them doesn't fail the episode, so the error is only logged.
+    async fn write_tags(&self, client: &reqwest::Client, ui: &DownloadBar, overwrite: bool) {
+        match self.normalize_tags(client, ui, overwrite).await {
+            Ok(()) => self.save_tag_hash(ui),
+            Err(e) => ui.log_error(e.to_string()),
+        }
+    }
+
+    fn tag_error(&self, reason: impl ToString) -> TagError {
+        TagError {
+            podcast: self.inner.config.podcast_name.clone(),
+            path: self.path.clone(),
+            reason: reason.to_string(),
+        }
+    }
+
+    /// Rewrites the tags of an episode downloaded earlier, after the feed changed its metadata.
+    pub async fn retag(&self, client: &reqwest::Client, ui: &DownloadBar) {
+        self.inner.log_debug(ui, "rewriting tags");
+        self.write_tags(client, ui, true).await;
+    }
+
+    /// Saves what the tags were written from, for `retag_on_change`.
+    fn save_tag_hash(&self, ui: &DownloadBar) {
+        if !self.inner.config.retag_on_change {
+            return;
+        }
+
+        let id = self.inner.get_id();
+        let hash = self.inner.tag_hash();
+        if let Err(e) = TagHashes::save(self.inner.tracker_path(), &id, &hash) {
+            self.inner.log_warn(ui, e);
+        }
     }
 
     fn file_name(&self) -> &str {
@@ -421,20 +1411,24 @@ impl<'a> DownloadedEpisode<'a> {
     }
 
     fn run_download_hook(&mut self, ui: &DownloadBar) {
-        let Some(script_path) = self.inner.config.download_hook.clone() else {
+        let Some(hook) = &self.inner.config.download_hook else {
             self.inner.log_trace(ui, "no download hook configured");
             return;
         };
 
         self.inner.log_debug(ui, "running download hook");
 
-        let path = self.path().to_owned();
+        let mut command = hook.command(self.path());
+        let size = fs::metadata(self.path()).map_or(0, |metadata| metadata.len());
+        command
+            .env("TALECAST_FILE", self.path())
+            .env("TALECAST_SIZE", size.to_string())
+            .env("TALECAST_PODCAST", &self.inner.config.podcast_name)
+            .env("TALECAST_GUID", self.inner.attrs.guid())
+            .env("TALECAST_RESUMED", if self.resumed { "1" } else { "0" });
 
         let handle = tokio::task::spawn_blocking(move || {
-            std::process::Command::new(script_path)
-                .arg(path)
-                .output()
-                .unwrap();
+            command.output().unwrap();
         });
 
         self.handle = Some(handle);
@@ -460,35 +1454,198 @@ impl<'a> DownloadedEpisode<'a> {
         Ok(())
     }
 
-    async fn process(&mut self, ui: &DownloadBar) -> Result<(), String> {
+    async fn process(&mut self, client: &reqwest::Client, ui: &DownloadBar) -> Result<(), String> {
         self.inner.log_debug(ui, "processing episode");
         self.rename()?;
         self.make_symlink(ui)?;
-        self.normalize_id3v2(ui).await;
+
+        // The file is shared with another podcast, so it's left alone.
+        if !self.deduplicated {
+            if let Err(e) = self.fix_mp3_headers(ui).await {
+                self.inner.log_warn(ui, e);
+            }
+
+            if self.inner.config.write_tags {
+                self.write_tags(client, ui, false).await;
+            }
+        }
 
         Ok(())
     }
 
-    fn rename(&mut self) -> Result<(), String> {
-        let new_name = &self.inner.config.name_pattern;
-        let mut new_name = sanitize_filename::sanitize(new_name);
-
-        let new_path = match self.path.extension() {
-            Some(extension) => {
-                let max_file_len: usize = 255;
-                let ext_len = extension.len() + 1; // + 1 for the dot.
-                let overflow = (new_name.len() + ext_len).saturating_sub(max_file_len);
-                for _ in 0..overflow {
-                    new_name.pop();
-                }
+    /// Saves the transcript of the most preferred type next to the episode, if any.
+    ///
+    /// Failing to download the transcript doesn't fail the episode.
+    async fn download_transcript(&self, client: &reqwest::Client, ui: &DownloadBar) {
+        let config = &self.inner.config;
+        if config.transcript_types.is_empty() {
+            return;
+        }
+
+        let transcripts = self.inner.attrs.transcripts();
+        let Some((url, extension)) = config.transcript_types.iter().find_map(|preferred| {
+            transcripts.iter().find_map(|(url, ty)| {
+                let extension = transcript_extension(ty)?;
+                let preferred = preferred.to_lowercase();
+                (preferred == extension || preferred == ty.to_lowercase())
+                    .then_some((*url, extension))
+            })
+        }) else {
+            self.inner
+                .log_trace(ui, "no transcript of the configured types");
+            return;
+        };
+
+        self.inner
+            .log_debug(ui, format!("downloading transcript: {:?}", url));
+
+        let response = config
+            .request
+            .apply(client.get(url), url)
+            .send()
+            .await
+            .and_then(reqwest::Response::error_for_status);
+        let bytes = match utils::short_handle_response(response) {
+            Ok(response) => response.bytes().await.map_err(|e| e.to_string()),
+            Err(e) => Err(e),
+        };
 
-                let mut new_path = self.path.with_file_name(new_name);
-                new_path.set_extension(extension);
-                new_path
+        let result = bytes.and_then(|bytes| {
+            fs::write(self.path.with_extension(extension), bytes).map_err(|e| e.to_string())
+        });
+
+        if let Err(e) = result {
+            self.inner
+                .log_warn(ui, format!("failed to download transcript: {}", e));
+        }
+    }
+
+    /// Cuts the soundbites of the episode into a `soundbites` directory next to it, with the
+    /// `soundbite_command`. Failing to extract one is only logged.
+    ///
+    /// Fails if the `soundbites` directory can't be created.
+    async fn extract_soundbites(&self, ui: &DownloadBar) -> Result<(), String> {
+        let Some(command) = &self.inner.config.soundbite_command else {
+            return Ok(());
+        };
+
+        let soundbites = self.inner.attrs.soundbites();
+        if soundbites.is_empty() {
+            return Ok(());
+        }
+
+        let (Some(dir), Some(stem)) = (self.path.parent(), self.path.file_stem()) else {
+            return Ok(());
+        };
+        if command.split_whitespace().next().is_none() {
+            return Err("empty soundbite_command".to_string());
+        }
+
+        let dir = dir.join("soundbites");
+        utils::try_create_dir(&dir)?;
+
+        for (index, (start, duration)) in soundbites.into_iter().enumerate() {
+            // Not `set_extension`, which would cut a stem like "Ep. 12" at its dot.
+            let mut name = format!("{} - soundbite {}", stem.to_string_lossy(), index + 1);
+            if let Some(extension) = self.path.extension() {
+                name = format!("{}.{}", name, extension.to_string_lossy());
+            }
+            let output = dir.join(name);
+
+            let args: Vec<String> = command
+                .split_whitespace()
+                .map(|arg| {
+                    arg.replace("{input}", &self.path.to_string_lossy())
+                        .replace("{start}", &start.to_string())
+                        .replace("{duration}", &duration.to_string())
+                        .replace("{output}", &output.to_string_lossy())
+                })
+                .collect();
+
+            self.inner
+                .log_debug(ui, format!("extracting soundbite: {:?}", &args));
+            let result = tokio::task::spawn_blocking(move || {
+                std::process::Command::new(&args[0])
+                    .args(&args[1..])
+                    .output()
+            })
+            .await;
+
+            match result {
+                Ok(Ok(output)) if output.status.success() => {}
+                _ => self.inner.log_warn(ui, "failed to extract soundbite"),
             }
-            None => self.path.with_file_name(new_name),
+        }
+
+        Ok(())
+    }
+
+    /// Rewrites an mp3 episode with the `mp3_fix_command`, so that it has a Xing header with the
+    /// correct frame count and players show the right duration.
+    ///
+    /// The command writes to a temporary file that replaces the episode. Failing to fix the
+    /// headers doesn't fail the episode, so the caller only logs the error.
+    async fn fix_mp3_headers(&self, ui: &DownloadBar) -> Result<(), String> {
+        let Some(command) = &self.inner.config.mp3_fix_command else {
+            return Ok(());
         };
 
+        let is_mp3 = self
+            .path
+            .extension()
+            .is_some_and(|extension| extension.eq_ignore_ascii_case("mp3"));
+        if !is_mp3 {
+            return Ok(());
+        }
+
+        if command.split_whitespace().next().is_none() {
+            return Err("empty mp3_fix_command".to_string());
+        }
+
+        let output = self.path.with_extension("fixing.mp3");
+        let args: Vec<String> = command
+            .split_whitespace()
+            .map(|arg| {
+                arg.replace("{input}", &self.path.to_string_lossy())
+                    .replace("{output}", &output.to_string_lossy())
+            })
+            .collect();
+
+        self.inner
+            .log_debug(ui, format!("fixing mp3 headers: {:?}", &args));
+        let result = tokio::task::spawn_blocking(move || {
+            std::process::Command::new(&args[0])
+                .args(&args[1..])
+                .output()
+        })
+        .await;
+
+        let fixed = match result {
+            Ok(Ok(result)) if result.status.success() => output.is_file(),
+            _ => false,
+        };
+
+        if !fixed || fs::rename(&output, &self.path).is_err() {
+            let _ = fs::remove_file(&output);
+            return Err("failed to fix mp3 headers".to_string());
+        }
+
+        Ok(())
+    }
+
+    /// Writes the `.nfo` sidecar next to the episode. Failing to write it doesn't fail the episode.
+    fn write_nfo(&self, ui: &DownloadBar) {
+        let Some(nfo) = &self.inner.nfo else {
+            return;
+        };
+
+        if fs::write(self.path.with_extension("nfo"), nfo).is_err() {
+            self.inner.log_warn(ui, "failed to write nfo file");
+        }
+    }
+
+    fn rename(&mut self) -> Result<(), String> {
+        let new_path = self.inner.named_path(&self.path, self.download_number);
         fs::rename(&self.path, &new_path).map_err(|_| "failed to rename episode".to_string())?;
         self.path = new_path;
         Ok(())
@@ -506,3 +1663,281 @@ impl AsRef<Attributes> for Episode {
         &self.attrs
     }
 }
+
+/// File extension of a transcript based on its mime type.
+fn transcript_extension(ty: &str) -> Option<&'static str> {
+    let extension = match ty.to_lowercase().as_str() {
+        "text/vtt" => "vtt",
+        "application/x-subrip" | "application/srt" | "text/srt" => "srt",
+        "application/json" => "json",
+        "text/html" => "html",
+        "text/plain" => "txt",
+        _ => return None,
+    };
+
+    Some(extension)
+}
+
+/// Semaphores of the hosts that episodes are downloaded from, shared by every podcast.
+static HOST_LIMITS: OnceLock<Mutex<HashMap<String, Arc<Semaphore>>>> = OnceLock::new();
+
+/// Waits until fewer than `limit` episodes are downloading from the host of `url`.
+///
+/// The permit is held for the whole download.
+async fn host_permit(url: &str, limit: Option<usize>) -> Option<OwnedSemaphorePermit> {
+    let limit = limit?;
+    let host = url::Url::parse(url).ok()?.host_str()?.to_string();
+
+    let semaphore = {
+        let mut limits = HOST_LIMITS.get_or_init(Default::default).lock().unwrap();
+        let semaphore = limits
+            .entry(host)
+            .or_insert_with(|| Arc::new(Semaphore::new(limit.max(1))));
+        Arc::clone(semaphore)
+    };
+
+    semaphore.acquire_owned().await.ok()
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+    use crate::testing::NoProgress;
+    use crate::testing::TestServer;
+
+    fn episode(url: &str, guid: &str, dir: &Path) -> Episode {
+        let raw = serde_json::json!({
+            "title": "Episode",
+            "pubDate": "Mon, 01 Jan 2024 00:00:00 +0000",
+            "guid": guid,
+            "enclosure": { "@url": url, "@type": "audio/mpeg" },
+        });
+        let serde_json::Value::Object(raw) = raw else {
+            unreachable!();
+        };
+
+        let attrs = Attributes::new(RawEpisode::new(raw), GuidFallback::default()).unwrap();
+        let config = Config {
+            url: url.to_string(),
+            podcast_name: "test".to_string(),
+            name_pattern: "{title}".to_string(),
+            id_pattern: guid.to_string(),
+            download_path: dir.to_path_buf(),
+            tracker_path: dir.join(".downloaded"),
+            ..Default::default()
+        };
+
+        Episode::new(attrs, 0, config, None, None, None)
+    }
+
+    #[test]
+    fn partial_name_of_plain_guid() {
+        let dir = testing::temp_dir("partial-plain");
+        let episode = episode("http://example.com/ep.mp3", "ep-1", &dir);
+        assert_eq!(episode.partial_name(), "ep-1.partial");
+    }
+
+    #[test]
+    fn partial_name_hashes_unsafe_guids() {
+        let dir = testing::temp_dir("partial-unsafe");
+        let url = "http://example.com/ep.mp3";
+
+        let permalink = episode(url, "https://example.com/episodes/1?id=2", &dir);
+        let name = permalink.partial_name();
+        assert!(!name.contains('/'));
+        assert_eq!(
+            name,
+            format!("{}.partial", cache::hashed_url(permalink.attrs.guid()))
+        );
+
+        let long_guid = "a".repeat(MAX_PARTIAL_NAME_LEN + 1);
+        let long = episode(url, &long_guid, &dir);
+        assert_eq!(
+            long.partial_name(),
+            format!("{}.partial", cache::hashed_url(&long_guid))
+        );
+
+        // Both sanitize to "ab", but mustn't share a partial file.
+        let first = episode(url, "a/b", &dir);
+        let second = episode(url, "a?b", &dir);
+        assert_ne!(first.partial_name(), second.partial_name());
+    }
+
+    #[test]
+    fn legacy_partial_is_adopted() {
+        let dir = testing::temp_dir("partial-legacy");
+        let episode = episode("http://example.com/ep.mp3", "https://example.com/1", &dir);
+        let legacy_path = dir.join(episode.legacy_partial_name().unwrap());
+        fs::write(&legacy_path, "partial data").unwrap();
+        fs::write(legacy_path.with_extension("partial.etag"), "\"v1\"").unwrap();
+
+        let partial_path = dir.join(episode.partial_name());
+        episode.adopt_legacy_partial(&partial_path);
+
+        assert!(!legacy_path.exists());
+        assert_eq!(fs::read_to_string(&partial_path).unwrap(), "partial data");
+        assert_eq!(
+            fs::read_to_string(partial_path.with_extension("partial.etag")).unwrap(),
+            "\"v1\""
+        );
+
+        // A partial under the new name is never overwritten.
+        fs::write(&legacy_path, "older data").unwrap();
+        episode.adopt_legacy_partial(&partial_path);
+        assert_eq!(fs::read_to_string(&partial_path).unwrap(), "partial data");
+    }
+
+    #[tokio::test]
+    async fn partial_download_is_resumed() {
+        const DATA: &str = "the whole episode";
+        let server = TestServer::start(|request| {
+            let start = request
+                .header("range")
+                .and_then(|range| range.strip_prefix("bytes="))
+                .and_then(|range| range.trim_end_matches('-').parse::<usize>().ok())
+                .unwrap_or(0);
+            let range = format!("bytes {}-{}/{}", start, DATA.len() - 1, DATA.len());
+            testing::Response::new(206, &DATA[start..])
+                .header("Content-Range", &range)
+                .header("ETag", "\"v1\"")
+        });
+        let dir = testing::temp_dir("resume");
+        let episode = episode(&server.url("/ep.mp3"), "ep-1", &dir);
+
+        let partial_path = dir.join(episode.partial_name());
+        fs::write(&partial_path, "the whole").unwrap();
+        fs::write(partial_path.with_extension("partial.etag"), "\"v1\"").unwrap();
+
+        let ui = DownloadBar::hidden("test".to_string());
+        let (path, transferred, resumed) = episode
+            .download_enclosure(&testing::client(), &ui, &NoProgress)
+            .await
+            .unwrap();
+
+        assert!(resumed);
+        assert_eq!(transferred, " episode".len() as u64);
+        assert_eq!(fs::read_to_string(&path).unwrap(), DATA);
+        assert!(!partial_path.with_extension("partial.etag").exists());
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(requests[0].header("range"), Some("bytes=9-"));
+    }
+
+    #[tokio::test]
+    async fn partial_is_rewritten_when_range_is_ignored() {
+        let server = TestServer::start(|_| {
+            testing::Response::new(200, "the whole episode").header("ETag", "\"v1\"")
+        });
+        let dir = testing::temp_dir("range-ignored");
+        let episode = episode(&server.url("/ep.mp3"), "ep-1", &dir);
+
+        let partial_path = dir.join(episode.partial_name());
+        fs::write(&partial_path, "the whole").unwrap();
+        fs::write(partial_path.with_extension("partial.etag"), "\"v1\"").unwrap();
+
+        let ui = DownloadBar::hidden("test".to_string());
+        let (path, _, resumed) = episode
+            .download_enclosure(&testing::client(), &ui, &NoProgress)
+            .await
+            .unwrap();
+
+        assert!(!resumed);
+        assert_eq!(fs::read_to_string(&path).unwrap(), "the whole episode");
+        assert!(!partial_path.exists());
+
+        let requests = server.requests();
+        assert_eq!(requests[0].method, "GET");
+        assert_eq!(requests[0].path, "/ep.mp3");
+        assert_eq!(requests[0].header("range"), Some("bytes=9-"));
+        assert_eq!(requests[0].header("if-range"), Some("\"v1\""));
+    }
+
+    #[tokio::test]
+    async fn intact_file_is_only_checked_when_it_exists() {
+        let server = TestServer::start(|_| testing::Response::new(206, "episode"));
+        let dir = testing::temp_dir("reuse-intact");
+        let mut episode = episode(&server.url("/ep.mp3"), "ep-1", &dir);
+        episode.config.reuse_intact_files = true;
+        episode.config.extension_source = vec![crate::config::ExtensionSource::Url];
+
+        let ui = DownloadBar::hidden("test".to_string());
+        let client = testing::client();
+        assert!(episode.reuse_intact_file(&client, &ui).await.is_none());
+        assert!(server.requests().is_empty());
+
+        let path = episode.named_path(&dir.join(episode.partial_name()).with_extension("mp3"), 0);
+        fs::write(&path, "the whole episode").unwrap();
+        let reused = episode.reuse_intact_file(&client, &ui).await.unwrap();
+        assert_eq!(reused.path(), path);
+
+        let requests = server.requests();
+        assert_eq!(requests.len(), 1);
+        assert_eq!(
+            requests[0].header("range"),
+            Some(format!("bytes=-{}", INTACT_CHECK_SIZE).as_str())
+        );
+    }
+
+    #[tokio::test]
+    async fn soundbites_keep_dotted_names() {
+        let dir = testing::temp_dir("soundbites-dotted");
+        let mut episode = episode("http://example.com/ep.mp3", "ep-1", &dir);
+        let raw = serde_json::json!({
+            "title": "Ep. 12 Intro",
+            "pubDate": "Mon, 01 Jan 2024 00:00:00 +0000",
+            "guid": "ep-1",
+            "enclosure": { "@url": "http://example.com/ep.mp3" },
+            "podcast:soundbite": [
+                { "@startTime": "1", "@duration": "2" },
+                { "@startTime": "5", "@duration": "2" },
+            ],
+        });
+        let serde_json::Value::Object(raw) = raw else {
+            unreachable!();
+        };
+        episode.attrs = Attributes::new(RawEpisode::new(raw), GuidFallback::default()).unwrap();
+        episode.config.soundbite_command = Some("cp {input} {output}".to_string());
+
+        let path = dir.join("Ep. 12 Intro.mp3");
+        fs::write(&path, "audio").unwrap();
+        let ui = DownloadBar::hidden("test".to_string());
+        DownloadedEpisode::new(&episode, path)
+            .extract_soundbites(&ui)
+            .await
+            .unwrap();
+
+        let soundbites = dir.join("soundbites");
+        assert!(soundbites.join("Ep. 12 Intro - soundbite 1.mp3").is_file());
+        assert!(soundbites.join("Ep. 12 Intro - soundbite 2.mp3").is_file());
+        assert!(!soundbites.join("Ep.mp3").exists());
+    }
+
+    #[tokio::test]
+    async fn empty_soundbite_command_is_an_error() {
+        let dir = testing::temp_dir("soundbites-empty");
+        let mut episode = episode("http://example.com/ep.mp3", "ep-1", &dir);
+        let raw = serde_json::json!({
+            "title": "Episode",
+            "pubDate": "Mon, 01 Jan 2024 00:00:00 +0000",
+            "guid": "ep-1",
+            "enclosure": { "@url": "http://example.com/ep.mp3" },
+            "podcast:soundbite": { "@startTime": "1", "@duration": "2" },
+        });
+        let serde_json::Value::Object(raw) = raw else {
+            unreachable!();
+        };
+        episode.attrs = Attributes::new(RawEpisode::new(raw), GuidFallback::default()).unwrap();
+        episode.config.soundbite_command = Some(" ".to_string());
+
+        let path = dir.join("Episode.mp3");
+        fs::write(&path, "audio").unwrap();
+        let ui = DownloadBar::hidden("test".to_string());
+        let result = DownloadedEpisode::new(&episode, path)
+            .extract_soundbites(&ui)
+            .await;
+
+        assert!(result.is_err());
+    }
+}
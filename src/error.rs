@@ -0,0 +1,69 @@
+//! Errors of loading, syncing and tagging podcasts.
+//!
+//! Each kind of failure has its own type carrying what it failed on, so callers can tell e.g. a
+//! feed that's down apart from a broken config. The podcast name isn't part of the messages,
+//! as it's shown in front of them.
+
+use std::path::PathBuf;
+use thiserror::Error;
+
+/// The feed of a podcast couldn't be fetched, from the network or the feed cache.
+#[derive(Debug, Clone, Error)]
+#[error("{reason} ({url})")]
+pub struct FeedFetchError {
+    pub podcast: String,
+    pub url: String,
+    pub reason: String,
+}
+
+/// The feed of a podcast was fetched but isn't a valid RSS feed.
+#[derive(Debug, Clone, Error)]
+#[error("{reason} ({url})")]
+pub struct ParseError {
+    pub podcast: String,
+    pub url: String,
+    pub reason: String,
+}
+
+/// An episode couldn't be downloaded or saved.
+#[derive(Debug, Clone, Error)]
+#[error("failed to download {episode:?}: {reason}")]
+pub struct DownloadError {
+    pub podcast: String,
+    /// The title of the episode.
+    pub episode: String,
+    pub url: String,
+    pub reason: String,
+}
+
+/// The settings of a podcast are invalid for the podcast or one of its episodes.
+#[derive(Debug, Clone, Error)]
+#[error("invalid config: {reason}")]
+pub struct ConfigError {
+    pub podcast: String,
+    pub reason: String,
+}
+
+/// The tags of a downloaded episode couldn't be written.
+#[derive(Debug, Clone, Error)]
+#[error("failed to tag {path:?}: {reason}")]
+pub struct TagError {
+    pub podcast: String,
+    pub path: PathBuf,
+    pub reason: String,
+}
+
+/// Any of the errors above, for functions that can fail in more than one way.
+#[derive(Debug, Clone, Error)]
+pub enum Error {
+    #[error(transparent)]
+    FeedFetch(#[from] FeedFetchError),
+    #[error(transparent)]
+    Parse(#[from] ParseError),
+    #[error(transparent)]
+    Download(#[from] DownloadError),
+    #[error(transparent)]
+    Config(#[from] ConfigError),
+    #[error(transparent)]
+    Tag(#[from] TagError),
+}
@@ -0,0 +1,35 @@
+use crate::cache;
+use crate::utils;
+use chrono::DateTime;
+use chrono::Local;
+use std::fs;
+use std::path::PathBuf;
+
+/// The last fetched XML of each feed, used by `--offline` to sync without the network.
+pub struct FeedCache;
+
+impl FeedCache {
+    pub fn save(url: &str, xml: &str) -> Result<(), String> {
+        let dir = Self::dir();
+        utils::create_dir(&dir);
+
+        fs::write(Self::path(url), xml).map_err(|_| "failed to cache feed".to_string())
+    }
+
+    /// The cached XML of the feed, along with when it was fetched.
+    pub fn load(url: &str) -> Option<(String, DateTime<Local>)> {
+        let path = Self::path(url);
+        let fetched = fs::metadata(&path).ok()?.modified().ok()?;
+        let xml = fs::read_to_string(&path).ok()?;
+
+        Some((xml, fetched.into()))
+    }
+
+    fn path(url: &str) -> PathBuf {
+        Self::dir().join(format!("{}.xml", cache::hashed_url(url)))
+    }
+
+    fn dir() -> PathBuf {
+        utils::state_dir().join("feeds")
+    }
+}
@@ -0,0 +1,179 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfig;
+use crate::config::PodcastConfigs;
+use crate::display::DownloadBar;
+use crate::podcast::Podcast;
+use rusqlite::{Connection, OpenFlags};
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
+use std::path::PathBuf;
+use std::process;
+
+/// A subscription in the gPodder database.
+struct Subscription {
+    id: i64,
+    title: String,
+    url: String,
+    download_folder: String,
+}
+
+/// Episodes gPodder has downloaded for a podcast, including those deleted since.
+#[derive(Default)]
+struct DownloadedEpisodes {
+    guids: HashSet<String>,
+    urls: HashSet<String>,
+    /// File of the episode, by guid.
+    files: HashMap<String, String>,
+}
+
+fn open_database(path: &Path) -> Connection {
+    match Connection::open_with_flags(path, OpenFlags::SQLITE_OPEN_READ_ONLY) {
+        Ok(conn) => conn,
+        Err(e) => {
+            eprintln!("failed to open gPodder database {:?}: {}", path, e);
+            process::exit(1);
+        }
+    }
+}
+
+fn subscriptions(conn: &Connection) -> rusqlite::Result<Vec<Subscription>> {
+    let mut stmt = conn.prepare("SELECT id, title, url, download_folder FROM podcast")?;
+    let rows = stmt.query_map([], |row| {
+        Ok(Subscription {
+            id: row.get(0)?,
+            title: row.get::<_, Option<String>>(1)?.unwrap_or_default(),
+            url: row.get(2)?,
+            download_folder: row.get::<_, Option<String>>(3)?.unwrap_or_default(),
+        })
+    })?;
+
+    rows.collect()
+}
+
+/// Episodes with the state downloaded (1) or deleted (2).
+fn downloaded_episodes(conn: &Connection) -> rusqlite::Result<HashMap<i64, DownloadedEpisodes>> {
+    let mut stmt = conn.prepare(
+        "SELECT podcast_id, guid, url, download_filename FROM episode WHERE state IN (1, 2)",
+    )?;
+
+    let rows = stmt.query_map([], |row| {
+        Ok((
+            row.get::<_, i64>(0)?,
+            row.get::<_, Option<String>>(1)?,
+            row.get::<_, Option<String>>(2)?,
+            row.get::<_, Option<String>>(3)?,
+        ))
+    })?;
+
+    let mut episodes: HashMap<i64, DownloadedEpisodes> = HashMap::new();
+    for row in rows {
+        let (podcast_id, guid, url, filename) = row?;
+        let downloaded = episodes.entry(podcast_id).or_default();
+
+        if let Some(guid) = guid {
+            if let Some(filename) = filename {
+                downloaded.files.insert(guid.clone(), filename);
+            }
+            downloaded.guids.insert(guid);
+        }
+
+        if let Some(url) = url {
+            downloaded.urls.insert(url);
+        }
+    }
+
+    Ok(episodes)
+}
+
+/// Imports the subscriptions of a gPodder database, along with its download history.
+///
+/// Each feed is fetched to mark the episodes gPodder downloaded, matched on guid or enclosure
+/// url, in the download tracker. This way they won't be downloaded again.
+pub async fn import(path: &Path, global_config: &GlobalConfig, catch_up: bool) {
+    let conn = open_database(path);
+
+    let (subscriptions, mut downloaded) =
+        match subscriptions(&conn).and_then(|subs| Ok((subs, downloaded_episodes(&conn)?))) {
+            Ok(data) => data,
+            Err(e) => {
+                eprintln!("failed to read gPodder database: {}", e);
+                process::exit(1);
+            }
+        };
+
+    if subscriptions.is_empty() {
+        eprintln!("no podcasts found.");
+        return;
+    }
+
+    // gPodder keeps its downloads in the same directory as the database.
+    let downloads_dir = path
+        .parent()
+        .map(|dir| dir.join("Downloads"))
+        .unwrap_or_default();
+
+    let existing = PodcastConfigs::load();
+    let client = global_config.reqwest_client();
+    let mut podcasts = HashMap::new();
+
+    for sub in subscriptions {
+        let name = if sub.title.is_empty() {
+            sub.url.clone()
+        } else {
+            sub.title.clone()
+        };
+
+        if existing.contains(&name) {
+            eprintln!("skipping '{}': already exists", &name);
+            continue;
+        }
+
+        let config = PodcastConfig::new(sub.url.clone());
+        let history = downloaded.remove(&sub.id).unwrap_or_default();
+        let download_dir = downloads_dir.join(&sub.download_folder);
+
+        let client = config.reqwest_client(&name, global_config, &client);
+        let ui = DownloadBar::hidden(name.clone());
+        match Podcast::new(name.clone(), config.clone(), global_config, client, &ui).await {
+            Ok(podcast) => {
+                let imported = import_history(&podcast, &history, &download_dir);
+                eprintln!("'{}': imported {} downloaded episodes", &name, imported);
+            }
+            Err(e) => eprintln!("'{}': failed to import download history: {}", &name, e),
+        }
+
+        let mut config = config;
+        if catch_up {
+            config.catch_up();
+        }
+
+        podcasts.insert(name, config);
+    }
+
+    PodcastConfigs::extend(podcasts);
+}
+
+fn import_history(podcast: &Podcast, history: &DownloadedEpisodes, download_dir: &Path) -> usize {
+    let mut imported = 0;
+
+    for episode in podcast.episodes() {
+        let guid = episode.attrs.guid();
+        if !history.guids.contains(guid) && !history.urls.contains(episode.attrs.url()) {
+            continue;
+        }
+
+        let path: Option<PathBuf> = history
+            .files
+            .get(guid)
+            .map(|filename| download_dir.join(filename));
+
+        match episode.mark_imported(path) {
+            Ok(true) => imported += 1,
+            Ok(false) => {}
+            Err(e) => eprintln!("{}: {:?}", e, episode.attrs.title()),
+        }
+    }
+
+    imported
+}
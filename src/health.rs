@@ -0,0 +1,106 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfigs;
+use crate::stats::RunStats;
+use crate::utils;
+use std::collections::HashMap;
+use std::fs;
+use std::path::PathBuf;
+
+/// Consecutive failed feed fetches of each podcast, to find subscriptions that went dead.
+#[derive(Default)]
+pub struct FeedHealth(HashMap<String, u32>);
+
+impl FeedHealth {
+    fn load() -> Self {
+        let failures = fs::read_to_string(Self::path())
+            .ok()
+            .and_then(|s| toml::from_str(&s).ok())
+            .unwrap_or_default();
+
+        Self(failures)
+    }
+
+    fn save(&self) -> Result<(), String> {
+        let s =
+            toml::to_string(&self.0).map_err(|_| "failed to serialize feed health".to_string())?;
+        fs::write(Self::path(), s).map_err(|_| "failed to save feed health".to_string())
+    }
+
+    fn path() -> PathBuf {
+        utils::state_dir().join("feed_failures.toml")
+    }
+
+    /// Podcasts that failed at least `threshold` times in a row, with their failure count.
+    fn dead(&self, threshold: u32) -> Vec<(&str, u32)> {
+        let mut dead: Vec<(&str, u32)> = self
+            .0
+            .iter()
+            .filter(|(_, failures)| **failures >= threshold)
+            .map(|(name, failures)| (name.as_str(), *failures))
+            .collect();
+        dead.sort();
+        dead
+    }
+
+    /// Counts the failed fetches of the sync, and resets the podcasts that succeeded.
+    ///
+    /// Podcasts at or past the `threshold` are disabled if `disable` is set, which includes
+    /// those that were past it before disabling was turned on or the threshold was lowered.
+    pub fn record(run: &RunStats, threshold: u32, disable: bool) {
+        let mut health = Self::load();
+        for podcast in &run.podcasts {
+            if podcast.fetch_failed {
+                *health.0.entry(podcast.name.clone()).or_default() += 1;
+            } else {
+                health.0.remove(&podcast.name);
+            }
+        }
+
+        if let Err(e) = health.save() {
+            eprintln!("{}", e);
+        }
+
+        if !disable {
+            return;
+        }
+
+        let podcasts = PodcastConfigs::load();
+        let dead: Vec<(&str, u32)> = health
+            .dead(threshold)
+            .into_iter()
+            .filter(|(name, _)| podcasts.is_enabled(name))
+            .collect();
+
+        for (name, failures) in &dead {
+            eprintln!(
+                "disabled '{}' after {} failed fetches in a row",
+                name, failures
+            );
+        }
+
+        if !dead.is_empty() {
+            let names: Vec<String> = dead.iter().map(|(name, _)| name.to_string()).collect();
+            PodcastConfigs::disable(&names);
+        }
+    }
+}
+
+/// Prints the podcasts that failed to fetch for `dead_feed_threshold` syncs in a row.
+pub fn report_dead(global_config: &GlobalConfig) {
+    let health = FeedHealth::load();
+    let podcasts = PodcastConfigs::load();
+    let dead: Vec<(&str, u32)> = health
+        .dead(global_config.dead_feed_threshold())
+        .into_iter()
+        .filter(|(name, _)| podcasts.contains(name))
+        .collect();
+
+    if dead.is_empty() {
+        eprintln!("no dead feeds found.");
+        return;
+    }
+
+    for (name, failures) in dead {
+        println!("{}: {} failed fetches in a row", name, failures);
+    }
+}
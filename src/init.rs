@@ -0,0 +1,100 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfig;
+use crate::config::PodcastConfigs;
+use crate::config::RequestSettings;
+use crate::display::DownloadBar;
+use crate::utils;
+use regex::Regex;
+
+/// Prompts for a value, returning `default` if the input is empty.
+fn prompt(question: &str, default: &str) -> String {
+    utils::get_input(Some(&format!("{} [{}]: ", question, default)))
+        .unwrap_or_else(|| default.to_string())
+}
+
+/// Prompts for an optional number, where `none` or `0` disables it.
+fn prompt_limit(question: &str, default: Option<i64>) -> Option<i64> {
+    let default_str = default.map_or("none".to_string(), |val| val.to_string());
+
+    loop {
+        let input = prompt(question, &default_str);
+        if input == "none" {
+            return None;
+        }
+
+        match input.parse::<i64>() {
+            Ok(0) => return None,
+            Ok(val) if val > 0 => return Some(val),
+            _ => eprintln!("enter a positive number, or 'none'"),
+        }
+    }
+}
+
+/// The title of the feed's channel, which is the first `<title>` in the feed.
+fn feed_title(xml: &str) -> Option<String> {
+    let re = Regex::new(r"(?s)<title>\s*(?:<!\[CDATA\[)?(.*?)(?:\]\]>)?\s*</title>").unwrap();
+    let title = re.captures(xml)?.get(1)?.as_str().trim().to_string();
+    Some(title).filter(|title| !title.is_empty())
+}
+
+/// Interactively configures the download settings and adds podcasts, for first-time setup.
+pub async fn init(mut global_config: GlobalConfig) {
+    eprintln!(
+        "Setting up {}. Press enter to keep the value in brackets.\n",
+        crate::APPNAME
+    );
+
+    let download_path = prompt(
+        "Where should episodes be downloaded? Supports patterns",
+        global_config.download_path(),
+    );
+    let max_days = prompt_limit(
+        "Skip episodes older than this many days",
+        global_config.max_days(),
+    );
+    let max_episodes = prompt_limit(
+        "Only download this many of the latest episodes of a podcast",
+        global_config.max_episodes(),
+    );
+
+    global_config.set_download_defaults(download_path, max_days, max_episodes);
+    global_config.save();
+    eprintln!("saved {:?}\n", global_config.path());
+
+    let client = global_config.reqwest_client();
+    let mut added = 0;
+
+    eprintln!("Add podcasts by their feed URL. Leave empty to finish.");
+    while let Some(url) = utils::get_input(Some("feed url: ")) {
+        let ui = DownloadBar::hidden(url.clone());
+        let request = RequestSettings::default();
+        let xml = match utils::download_text(&client, &url, &request, &ui).await {
+            Ok(xml) => xml,
+            Err(e) => {
+                eprintln!("{}", e);
+                eprintln!("check the URL and try again.");
+                continue;
+            }
+        };
+
+        let Some(title) = feed_title(&xml) else {
+            eprintln!("no podcast found at the URL, make sure it's an RSS feed.");
+            continue;
+        };
+
+        let name = prompt(&format!("found '{}'. Name of podcast", &title), &title);
+
+        if PodcastConfigs::push(name.clone(), PodcastConfig::new(url)) {
+            eprintln!("'{}' added!", &name);
+            added += 1;
+        } else {
+            eprintln!("'{}' already exists!", &name);
+        }
+    }
+
+    eprintln!(
+        "\nSetup complete, {} podcasts added. Run '{}' to download episodes.",
+        added,
+        crate::APPNAME
+    );
+}
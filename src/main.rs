@@ -1,18 +1,43 @@
 use crate::config::GlobalConfig;
 use crate::config::PodcastConfigs;
+use crate::progress::ProgressSink;
+use crate::stats::PrintOrder;
+use clap::CommandFactory;
 use clap::Parser;
+use clap_complete::Shell;
 use regex::Regex;
+use std::io::Write;
+use std::path::Path;
 use std::path::PathBuf;
 
 mod cache;
 mod config;
+mod dedup;
+mod diff;
 mod display;
 mod download_tracker;
 mod episode;
+mod error;
+mod feed_cache;
+mod gpodder;
+mod health;
+mod init;
+mod netcheck;
+mod nfo;
+mod observer;
 mod opml;
 mod patterns;
+mod pin;
 mod podcast;
+mod progress;
+mod prune;
+mod ratelimit;
+mod rename;
+mod repair;
+mod stats;
 mod tags;
+#[cfg(test)]
+mod testing;
 mod utils;
 
 pub const APPNAME: &'static str = "talecast";
@@ -32,6 +57,13 @@ struct Args {
         help = "Import podcasts from an OPML file"
     )]
     import: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "FORMAT",
+        default_value = "opml",
+        help = "Format of the file to import"
+    )]
+    format: ImportFormat,
     #[arg(
         short,
         long,
@@ -41,6 +73,18 @@ struct Args {
     export: Option<PathBuf>,
     #[arg(short, long, help = "Print the downloaded paths to stdout")]
     print: bool,
+    #[arg(
+        long,
+        value_name = "ORDER",
+        default_value = "name",
+        help = "Order of the paths printed with --print or --print0"
+    )]
+    sort: PrintOrder,
+    #[arg(
+        long,
+        help = "Print the downloaded paths to stdout separated by NUL bytes, for 'xargs -0'"
+    )]
+    print0: bool,
     #[arg(
         short,
         long,
@@ -61,14 +105,155 @@ struct Args {
         help = "Override the path to the config file"
     )]
     config: Option<PathBuf>,
-    #[arg(long, help = "Edit the config.toml file")]
+    #[arg(
+        long,
+        value_name = "NAME",
+        conflicts_with = "init",
+        help = "Use the settings of a profile in the config file"
+    )]
+    profile: Option<String>,
+    #[arg(
+        long,
+        value_name = "FILE",
+        help = "Override the path to the podcasts file"
+    )]
+    podcasts: Option<PathBuf>,
+    #[arg(
+        long,
+        value_name = "DIR",
+        help = "Override the download path of all podcasts. Supports patterns"
+    )]
+    path: Option<String>,
+    #[arg(long, help = "Interactively set up the config and add podcasts")]
+    init: bool,
+    #[arg(long, help = "Edit the config.toml file, checking it before saving")]
     edit_config: bool,
-    #[arg(long, help = "Edit the podcasts.toml file")]
+    #[arg(long, help = "Edit the podcasts.toml file, checking it before saving")]
     edit_podcasts: bool,
     #[arg(short, long, value_name = "QUERY",  num_args = 1.., help = "Search for podcasts to add")]
     search: Option<Vec<String>>,
     #[arg(long, help = "Print your podcasts to stdout")]
     list: bool,
+    #[arg(
+        long,
+        help = "Compare the feeds with the downloaded episodes, ignoring the download filters"
+    )]
+    diff: bool,
+    #[arg(
+        long,
+        help = "Print the number of episodes each podcast would download as JSON, exiting with 10 if there are any"
+    )]
+    check_new: bool,
+    #[arg(
+        long,
+        value_name = "N",
+        help = "Fetch at most N feeds at the same time"
+    )]
+    parallel_feeds: Option<usize>,
+    #[arg(
+        long,
+        help = "Take turns downloading one episode of each podcast, see 'download_concurrency'"
+    )]
+    interleave: bool,
+    #[arg(
+        long,
+        help = "Sync from the feeds cached by 'cache_feeds' without downloading anything"
+    )]
+    offline: bool,
+    #[arg(
+        long,
+        help = "List podcasts that failed to fetch for 'dead_feed_threshold' syncs in a row"
+    )]
+    report_dead: bool,
+    #[arg(
+        long,
+        help = "Check the connection to the host of the first podcast, printing latency and rough throughput"
+    )]
+    check_net: bool,
+    #[arg(
+        long,
+        value_name = "FD",
+        help = "Write the sync progress as JSON lines to the file descriptor, e.g. 2 for stderr"
+    )]
+    progress_fd: Option<i32>,
+    #[arg(
+        long,
+        help = "Leave the downloaded files untagged, like 'write_tags = false'"
+    )]
+    no_tags: bool,
+    #[arg(
+        long,
+        help = "Sync one podcast and one episode at a time, printing plain lines instead of progress bars"
+    )]
+    sequential: bool,
+    #[arg(
+        long,
+        value_name = "FIELD=VALUE",
+        help = "Tag every file downloaded in this run, on top of the 'tags' setting. Can be repeated"
+    )]
+    set_tag: Vec<String>,
+    #[arg(
+        long,
+        help = "Sync the feed urls read from stdin, one per line, with the global settings instead of podcasts.toml"
+    )]
+    stdin: bool,
+    #[arg(
+        long,
+        help = "Only download episodes published since the last successful sync of each podcast, like 'since_last_run = true'"
+    )]
+    since_last_run: bool,
+    #[arg(
+        long,
+        value_name = "RATE",
+        help = "Limit the download speed of all podcasts together, in bytes per second like 2MB, like 'total_download_rate'"
+    )]
+    limit_rate: Option<String>,
+    #[arg(
+        long,
+        value_name = "RATE",
+        help = "Limit the download speed of each podcast, in bytes per second like 500KB, like 'download_rate'"
+    )]
+    limit_rate_per_podcast: Option<String>,
+    #[arg(long, help = "Print bandwidth and time used by the sync")]
+    stats: bool,
+    #[arg(
+        long,
+        help = "List downloaded episodes exceeding the 'keep', 'delete_after' or 'archive_after' setting, or removed from the feed. Combine with --yes to delete or move them"
+    )]
+    prune: bool,
+    #[arg(
+        long,
+        help = "Rename downloaded episodes according to the current name_pattern"
+    )]
+    rename_only: bool,
+    #[arg(
+        long,
+        help = "Rebuild the download trackers from the episodes already on disk, matched by file name or tags"
+    )]
+    repair: bool,
+    #[arg(
+        long,
+        value_name = "GUID",
+        requires = "filter",
+        help = "Download the episode with this guid from the podcast matching --filter, ignoring the download filters"
+    )]
+    episode: Option<String>,
+    #[arg(long, help = "Confirm deleting files with --prune")]
+    yes: bool,
+    #[arg(
+        long,
+        value_name = "SHELL",
+        help = "Print a completion script for the given shell to stdout"
+    )]
+    completions: Option<Shell>,
+}
+
+#[derive(Clone, Copy, clap::ValueEnum)]
+enum ImportFormat {
+    /// An OPML file, which most podcatchers can export.
+    Opml,
+    /// The database of gPodder, including which episodes are downloaded.
+    Gpodder,
 }
 
 impl From<Args> for Action {
@@ -81,18 +266,63 @@ impl From<Args> for Action {
         let print = args.print;
         let catch_up = args.catch_up;
 
+        if let Some(shell) = args.completions {
+            return Self::Completions { shell };
+        }
+
         if args.list {
             return Self::List { filter };
         }
 
+        if args.diff {
+            return Self::Diff { filter };
+        }
+
+        if args.check_new {
+            return Self::CheckNew { filter };
+        }
+
+        if args.report_dead {
+            return Self::ReportDead;
+        }
+
+        if args.check_net {
+            return Self::CheckNet { filter };
+        }
+
+        if args.prune {
+            return Self::Prune {
+                filter,
+                delete: args.yes,
+            };
+        }
+
+        if args.rename_only {
+            return Self::Rename { filter };
+        }
+
+        if args.repair {
+            return Self::Repair { filter };
+        }
+
+        if let Some(guid) = args.episode {
+            return Self::Episode { filter, guid };
+        }
+
+        if args.init {
+            return Self::Init;
+        }
+
         if args.edit_config {
-            let path = GlobalConfig::default_path();
-            return Self::Edit { path };
+            let path = args.config.unwrap_or_else(GlobalConfig::default_path);
+            let validate = GlobalConfig::validate_file;
+            return Self::Edit { path, validate };
         }
 
         if args.edit_podcasts {
             let path = config::PodcastConfigs::path();
-            return Self::Edit { path };
+            let validate = config::PodcastConfigs::validate_file;
+            return Self::Edit { path, validate };
         }
 
         if let Some(query) = args.search {
@@ -101,7 +331,11 @@ impl From<Args> for Action {
         }
 
         if let Some(path) = args.import {
-            return Self::Import { path, catch_up };
+            return Self::Import {
+                path,
+                format: args.format,
+                catch_up,
+            };
         }
 
         if let Some(path) = args.export {
@@ -123,7 +357,15 @@ impl From<Args> for Action {
             return Self::CatchUp { filter };
         }
 
-        Self::Sync { filter, print }
+        Self::Sync {
+            filter,
+            print,
+            print0: args.print0,
+            sort: args.sort,
+            stats: args.stats,
+            progress_fd: args.progress_fd,
+            stdin: args.stdin,
+        }
     }
 }
 
@@ -136,9 +378,11 @@ enum Action {
     },
     Edit {
         path: PathBuf,
+        validate: fn(&Path) -> Result<(), String>,
     },
     Import {
         path: PathBuf,
+        format: ImportFormat,
         catch_up: bool,
     },
     Export {
@@ -157,7 +401,40 @@ enum Action {
     Sync {
         filter: Option<Regex>,
         print: bool,
+        print0: bool,
+        sort: PrintOrder,
+        stats: bool,
+        progress_fd: Option<i32>,
+        stdin: bool,
+    },
+    Prune {
+        filter: Option<Regex>,
+        delete: bool,
+    },
+    Rename {
+        filter: Option<Regex>,
+    },
+    Repair {
+        filter: Option<Regex>,
+    },
+    Episode {
+        filter: Option<Regex>,
+        guid: String,
     },
+    Diff {
+        filter: Option<Regex>,
+    },
+    CheckNew {
+        filter: Option<Regex>,
+    },
+    ReportDead,
+    CheckNet {
+        filter: Option<Regex>,
+    },
+    Completions {
+        shell: Shell,
+    },
+    Init,
 }
 
 use chrono::Local;
@@ -189,17 +466,82 @@ fn setup_logging(config: &config::LogConfig) -> Result<PathBuf, fern::InitError>
 async fn main() {
     let args = Args::parse();
 
-    let global_config = match args.config.as_ref() {
+    let mut global_config = match args.config.as_ref() {
         Some(path) => GlobalConfig::load_from_path(path),
         None => GlobalConfig::load(),
     };
 
+    if let Some(profile) = &args.profile {
+        if let Err(e) = global_config.apply_profile(profile) {
+            eprintln!("error: {}", e);
+            std::process::exit(1);
+        }
+    }
+
+    if let Some(path) = args.podcasts.clone() {
+        PodcastConfigs::override_path(path);
+    }
+
+    if let Some(path) = args.path.clone() {
+        global_config.override_download_path(path);
+    }
+
+    if let Some(qty) = args.parallel_feeds {
+        global_config.override_feed_fetch_concurrency(qty);
+    }
+
+    if args.no_tags {
+        global_config.set_no_tags();
+    }
+
+    if args.interleave {
+        global_config.set_interleave_downloads();
+    }
+
+    if args.sequential {
+        global_config.set_sequential();
+    }
+
+    if args.since_last_run {
+        global_config.set_since_last_run();
+    }
+
+    if let Some(rate) = args.limit_rate.clone() {
+        global_config.set_total_download_rate(rate);
+    }
+
+    if let Some(rate) = args.limit_rate_per_podcast.clone() {
+        global_config.set_download_rate(rate);
+    }
+
+    for tag in &args.set_tag {
+        let Some((field, value)) = tag.split_once('=') else {
+            eprintln!("invalid --set-tag {:?}, use FIELD=VALUE", tag);
+            std::process::exit(1);
+        };
+
+        global_config.set_tag_override(field.trim().to_string(), value.to_string());
+    }
+
+    if args.offline {
+        global_config.set_offline();
+    }
+
     let log_path = setup_logging(&global_config.log()).unwrap();
 
     match Action::from(args) {
-        Action::Import { path, catch_up } => opml::import(&path, catch_up),
+        Action::Import {
+            path,
+            format,
+            catch_up,
+        } => match format {
+            ImportFormat::Opml => opml::import(&path, catch_up),
+            ImportFormat::Gpodder => gpodder::import(&path, &global_config, catch_up).await,
+        },
+
+        Action::Edit { path, validate } => utils::edit_file(&path, validate),
 
-        Action::Edit { path } => utils::edit_file(&path),
+        Action::Init => init::init(global_config).await,
 
         Action::CatchUp { filter } => config::PodcastConfigs::catch_up(filter),
 
@@ -213,7 +555,29 @@ async fn main() {
             utils::search_podcasts(&global_config, query, catch_up).await
         }
 
-        Action::Export { path, filter } => opml::export(&path, filter).await,
+        Action::Export { path, filter } => opml::export(&path, filter, &global_config).await,
+
+        Action::Prune { filter, delete } => prune::prune(global_config, filter, delete).await,
+
+        Action::Rename { filter } => rename::rename(global_config, filter).await,
+
+        Action::Repair { filter } => repair::repair(global_config, filter).await,
+
+        Action::Episode { filter, guid } => {
+            pin::download_episode(global_config, filter, &guid).await
+        }
+
+        Action::Diff { filter } => diff::diff(global_config, filter).await,
+
+        Action::CheckNew { filter } => diff::check_new(global_config, filter).await,
+
+        Action::ReportDead => health::report_dead(&global_config),
+
+        Action::CheckNet { filter } => netcheck::check_net(global_config, filter).await,
+
+        Action::Completions { shell } => {
+            clap_complete::generate(shell, &mut Args::command(), APPNAME, &mut std::io::stdout())
+        }
 
         Action::Add {
             name,
@@ -242,21 +606,79 @@ async fn main() {
             }
         }
 
-        Action::Sync { filter, print } => {
-            let paths = PodcastConfigs::load()
+        Action::Sync {
+            filter,
+            print,
+            print0,
+            sort,
+            stats,
+            progress_fd,
+            stdin,
+        } => {
+            let progress = progress_fd.map(|fd| match ProgressSink::from_fd(fd) {
+                Ok(sink) => sink,
+                Err(e) => {
+                    eprintln!("error: {}", e);
+                    std::process::exit(1);
+                }
+            });
+
+            let stats_log = global_config.stats_log();
+            let record_health = !global_config.offline();
+            let dead_feed_threshold = global_config.dead_feed_threshold();
+            let disable_dead_feeds = global_config.disable_dead_feeds();
+            let podcasts = if stdin {
+                PodcastConfigs::from_stdin(&global_config).await
+            } else {
+                PodcastConfigs::load()
+            };
+
+            let run_stats = podcasts
                 .assert_not_empty()
+                .without_disabled()
                 .filter(filter)
-                .sync(global_config, &log_path)
+                .validate_patterns(&global_config)
+                .without_invalid_modes(&global_config)
+                .sync(global_config, &log_path, progress)
                 .await;
 
+            if record_health {
+                health::FeedHealth::record(&run_stats, dead_feed_threshold, disable_dead_feeds);
+            }
+
             eprintln!("Syncing complete!");
-            eprintln!("{} episodes downloaded.", paths.len());
+            eprintln!("{} episodes downloaded.", run_stats.episode_qty());
+
+            if stats {
+                run_stats.print();
+            }
+
+            if let Some(path) = stats_log {
+                if let Err(e) = run_stats.append_to_log(&path) {
+                    eprintln!("{}: {:?}", e, path);
+                }
+            }
 
             if print {
-                for path in paths {
+                for path in run_stats.paths(sort) {
                     println!("{}", path.to_str().unwrap());
                 }
             }
+
+            if print0 {
+                let mut stdout = std::io::stdout().lock();
+                for path in run_stats.paths(sort) {
+                    let _ = stdout
+                        .write_all(&utils::path_bytes(path))
+                        .and_then(|_| stdout.write_all(b"\0"));
+                }
+                let _ = stdout.flush();
+            }
+
+            if run_stats.low_disk_space() {
+                eprintln!("some downloads were skipped due to low disk space.");
+                std::process::exit(1);
+            }
         }
     }
 }
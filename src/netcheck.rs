@@ -0,0 +1,112 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfigs;
+use futures_util::StreamExt;
+use regex::Regex;
+use std::process;
+use std::time;
+
+/// Bytes requested for the throughput measurement.
+const SAMPLE_SIZE: u64 = 1024 * 1024;
+
+/// Checks the connection to the host of the first podcast, by name.
+///
+/// Prints how long resolving the host and the first response took, and the rough throughput of
+/// a small range download of the feed.
+pub async fn check_net(global_config: GlobalConfig, filter: Option<Regex>) {
+    let podcasts = PodcastConfigs::load().assert_not_empty().filter(filter);
+    let Some((name, mut podcast_config)) = podcasts.sorted().into_iter().next() else {
+        eprintln!("no podcasts matched the filter");
+        process::exit(1);
+    };
+
+    if let Err(e) = podcast_config.resolve_url().await {
+        eprintln!("{}: {}", &name, e);
+        process::exit(1);
+    }
+
+    let url = &podcast_config.url;
+    let Some((host, port)) = url::Url::parse(url).ok().and_then(|url| {
+        let host = url.host_str()?.to_string();
+        Some((host, url.port_or_known_default()?))
+    }) else {
+        eprintln!("{}: invalid url: {:?}", &name, url);
+        process::exit(1);
+    };
+
+    println!("checking {} ({})", &host, &name);
+
+    let start = time::Instant::now();
+    match tokio::net::lookup_host((host.as_str(), port)).await {
+        Ok(mut addrs) => match addrs.next() {
+            Some(addr) => println!("resolved: {} in {:?}", addr.ip(), start.elapsed()),
+            None => {
+                eprintln!("failed to resolve {}", &host);
+                process::exit(1);
+            }
+        },
+        Err(e) => {
+            eprintln!("failed to resolve {}: {}", &host, e);
+            process::exit(1);
+        }
+    }
+
+    let client = global_config.reqwest_client();
+    let client = podcast_config.reqwest_client(&name, &global_config, &client);
+    let request = client.get(url).header(
+        reqwest::header::RANGE,
+        format!("bytes=0-{}", SAMPLE_SIZE - 1),
+    );
+    let request = podcast_config.request_settings().apply(request, url);
+
+    let start = time::Instant::now();
+    let response = match request.send().await {
+        Ok(response) => response,
+        Err(e) => {
+            eprintln!("failed to connect to {}: {}", &host, e);
+            process::exit(1);
+        }
+    };
+    println!(
+        "latency: {:?} ({})",
+        start.elapsed(),
+        response.status().as_u16()
+    );
+
+    let start = time::Instant::now();
+    let mut downloaded: u64 = 0;
+    let mut stream = response.bytes_stream();
+    while let Some(chunk) = stream.next().await {
+        match chunk {
+            Ok(chunk) => downloaded += chunk.len() as u64,
+            Err(e) => {
+                eprintln!("download was interrupted: {}", e);
+                process::exit(1);
+            }
+        }
+
+        // Servers that ignore the range send the whole body.
+        if downloaded >= SAMPLE_SIZE {
+            break;
+        }
+    }
+
+    let secs = start.elapsed().as_secs_f64();
+    let megabytes = downloaded as f64 / 1_000_000.;
+    if secs > 0. {
+        println!(
+            "throughput: {:.2} MB/s ({} bytes in {:.2}s)",
+            megabytes / secs,
+            downloaded,
+            secs
+        );
+    } else {
+        println!(
+            "throughput: {} bytes downloaded too quickly to measure",
+            downloaded
+        );
+    }
+
+    if downloaded < SAMPLE_SIZE / 10 {
+        println!("note: the feed is small, so the throughput is only a rough estimate");
+    }
+}
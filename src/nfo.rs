@@ -0,0 +1,56 @@
+use crate::config::EvalData;
+use crate::utils;
+
+fn escape(text: &str) -> String {
+    text.replace('&', "&amp;")
+        .replace('<', "&lt;")
+        .replace('>', "&gt;")
+        .replace('"', "&quot;")
+}
+
+/// An `.nfo` file describing the episode, as read by media centers like Kodi and Jellyfin.
+pub fn episode_details(data: EvalData<'_>) -> String {
+    let episode = data.episode;
+    let mut fields = vec![
+        ("title", episode.title().to_string()),
+        ("showtitle", data.podcast.title().to_string()),
+    ];
+
+    if let Ok(html) = episode.description().or_else(|_| episode.content_encoded()) {
+        fields.push(("plot", utils::strip_html(html)));
+    }
+
+    let aired = episode.published_at();
+    fields.push(("aired", aired.format("%Y-%m-%d").to_string()));
+
+    if let Ok(season) = episode.itunes_season() {
+        fields.push(("season", season.to_string()));
+    }
+
+    if let Ok(number) = episode.itunes_episode() {
+        fields.push(("episode", number.to_string()));
+    }
+
+    let podcast = data.podcast;
+    let channel_fields = [
+        ("author", podcast.author()),
+        // The episode's own page if it has one.
+        ("link", episode.link().or(podcast.link())),
+        ("copyright", podcast.copyright()),
+        ("funding", podcast.funding().map(|(url, _)| url)),
+    ];
+    for (tag, value) in channel_fields {
+        if let Some(value) = value {
+            fields.push((tag, value.to_string()));
+        }
+    }
+
+    let mut nfo = String::from("<?xml version=\"1.0\" encoding=\"UTF-8\" standalone=\"yes\"?>\n");
+    nfo.push_str("<episodedetails>\n");
+    for (tag, value) in fields {
+        nfo.push_str(&format!("  <{}>{}</{}>\n", tag, escape(&value), tag));
+    }
+    nfo.push_str("</episodedetails>\n");
+
+    nfo
+}
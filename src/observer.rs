@@ -0,0 +1,110 @@
+use crate::episode::Episode;
+use std::ops::Deref;
+use std::path::Path;
+
+/// Observes the progress of syncing a podcast.
+///
+/// Lets the sync be displayed without depending on `indicatif`. The CLI implements it with
+/// [`crate::display::DownloadBar`]. All methods have empty default implementations, so only
+/// the events of interest need to be implemented.
+pub trait SyncObserver: Send + Sync {
+    /// The feed is loaded, and `episode_qty` episodes are pending download.
+    fn on_feed_loaded(&self, _episode_qty: usize) {}
+
+    /// The feed is loaded, but the `episode_qty` pending episodes are outside the download window.
+    fn on_downloads_deferred(&self, _episode_qty: usize, _window_start: &str) {}
+
+    /// The feed is loaded from the cache, so the `episode_qty` pending episodes aren't downloaded.
+    fn on_offline(&self, _episode_qty: usize, _fetched: &str) {}
+
+    /// The podcast is synced for the first time with `initial = "now"`, so the `episode_qty`
+    /// episodes in the feed are marked as downloaded instead.
+    fn on_marked_seen(&self, _episode_qty: usize) {}
+
+    fn on_episode_start(&self, _episode: &Episode, _index: usize, _episode_qty: usize) {}
+
+    /// Called for every downloaded chunk. `total` is 0 if the size is unknown.
+    fn on_episode_progress(&self, _downloaded: u64, _total: u64) {}
+
+    fn on_episode_done(&self, _episode: &Episode, _path: &Path) {}
+
+    /// All episodes are downloaded, and the download hooks are being awaited.
+    fn on_hooks_start(&self) {}
+
+    fn on_sync_done(&self) {}
+
+    /// The sync of the podcast failed.
+    fn on_error(&self, _msg: &str) {}
+
+    /// A separate observer for one of several episodes downloaded at the same time.
+    ///
+    /// It gets the events from `on_episode_start` to `on_episode_done` or `on_error` of its
+    /// episode. Without one, those events go to this observer.
+    fn episode_observer(&self) -> Option<Box<dyn SyncObserver>> {
+        None
+    }
+}
+
+/// Forwards the events to each of the observers, in order.
+pub struct Observers<O>(pub Vec<O>);
+
+impl<'a, O> SyncObserver for Observers<O>
+where
+    O: Deref<Target = dyn SyncObserver + 'a> + Send + Sync,
+{
+    fn on_feed_loaded(&self, episode_qty: usize) {
+        self.0.iter().for_each(|o| o.on_feed_loaded(episode_qty));
+    }
+
+    fn on_downloads_deferred(&self, episode_qty: usize, window_start: &str) {
+        self.0
+            .iter()
+            .for_each(|o| o.on_downloads_deferred(episode_qty, window_start));
+    }
+
+    fn on_offline(&self, episode_qty: usize, fetched: &str) {
+        self.0
+            .iter()
+            .for_each(|o| o.on_offline(episode_qty, fetched));
+    }
+
+    fn on_marked_seen(&self, episode_qty: usize) {
+        self.0.iter().for_each(|o| o.on_marked_seen(episode_qty));
+    }
+
+    fn on_episode_start(&self, episode: &Episode, index: usize, episode_qty: usize) {
+        self.0
+            .iter()
+            .for_each(|o| o.on_episode_start(episode, index, episode_qty));
+    }
+
+    fn on_episode_progress(&self, downloaded: u64, total: u64) {
+        self.0
+            .iter()
+            .for_each(|o| o.on_episode_progress(downloaded, total));
+    }
+
+    fn on_episode_done(&self, episode: &Episode, path: &Path) {
+        self.0.iter().for_each(|o| o.on_episode_done(episode, path));
+    }
+
+    fn on_hooks_start(&self) {
+        self.0.iter().for_each(|o| o.on_hooks_start());
+    }
+
+    fn on_sync_done(&self) {
+        self.0.iter().for_each(|o| o.on_sync_done());
+    }
+
+    fn on_error(&self, msg: &str) {
+        self.0.iter().for_each(|o| o.on_error(msg));
+    }
+
+    fn episode_observer(&self) -> Option<Box<dyn SyncObserver>> {
+        let observers: Vec<_> = self.0.iter().filter_map(|o| o.episode_observer()).collect();
+        match observers.is_empty() {
+            true => None,
+            false => Some(Box::new(Observers(observers))),
+        }
+    }
+}
@@ -4,6 +4,41 @@ use strum_macros::EnumIter;
 use crate::utils;
 
 use regex::Regex;
+use std::sync::Mutex;
+
+/// Unknown pattern tokens that have already been warned about.
+static WARNED_TOKENS: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// `{download_number}`, optionally with the width it's zero-padded to, e.g. `{download_number::4}`.
+const DOWNLOAD_NUMBER: &str = r"download_number(?:::(\d+))?";
+
+/// Replaces the `{download_number}` tokens, which are only known once the episode is downloaded.
+pub fn eval_download_number(s: &str, number: u64) -> String {
+    let re = Regex::new(&format!(r"\{{{}\}}", DOWNLOAD_NUMBER)).unwrap();
+    re.replace_all(s, |caps: &regex::Captures| {
+        let width = caps
+            .get(1)
+            .and_then(|width| width.as_str().parse().ok())
+            .unwrap_or(0);
+        format!("{:0width$}", number, width = width)
+    })
+    .into_owned()
+}
+
+/// `{tag::<field>}`, with the value the episode is tagged with, e.g. `{tag::album}`.
+///
+/// `{id3::<frame>}` is the same, from when only MP3 files were tagged.
+const TAG: &str = r"(?:tag|id3)::([^{}]+)";
+
+/// Replaces the `{tag::<field>}` tokens with the values from `lookup`, which are only known
+/// once the tags of the episode are extracted.
+pub fn eval_tags(s: &str, lookup: impl Fn(&str) -> Option<String>) -> String {
+    let re = Regex::new(&format!(r"\{{{}\}}", TAG)).unwrap();
+    re.replace_all(s, |caps: &regex::Captures| {
+        lookup(&caps[1]).unwrap_or_else(|| "<value not found>".to_string())
+    })
+    .into_owned()
+}
 
 #[derive(Debug, Clone)]
 pub struct FullPattern(Vec<Segment>);
@@ -46,22 +81,75 @@ impl FullPattern {
         Self(segments)
     }
 
+    /// Tokens in the pattern that aren't recognized.
+    pub fn unknown_tokens(&self) -> Vec<&str> {
+        self.0
+            .iter()
+            .filter_map(|segment| match segment {
+                Segment::Pattern(Pattern::Unknown(token)) => Some(token.as_str()),
+                _ => None,
+            })
+            .collect()
+    }
+
+    /// Whether the pattern can evaluate differently for each episode, or each sync.
+    fn varies(&self) -> bool {
+        self.0.iter().any(|segment| match segment {
+            Segment::Pattern(Pattern::Unit(unit)) => {
+                matches!(unit, UnitPattern::Guid | UnitPattern::Url)
+            }
+            Segment::Pattern(Pattern::Data(data)) => !matches!(
+                data.ty,
+                DataPatternType::RssChannel | DataPatternType::Channel
+            ),
+            Segment::Pattern(Pattern::Deferred(_)) => true,
+            Segment::Text(_) | Segment::Pattern(Pattern::Unknown(_)) => false,
+        })
+    }
+
+    /// The leading directories of a path pattern that are the same for every episode.
+    ///
+    /// E.g. `{home}/{podname}/{pubdate::%Y/%m}` gives `{home}/{podname}`.
+    pub fn stable_prefix(s: &str) -> String {
+        // Splits on the slashes outside of tokens, since date formats can contain slashes.
+        let mut components = vec![String::new()];
+        let mut is_inside = false;
+        for c in s.chars() {
+            match c {
+                '{' => is_inside = true,
+                '}' => is_inside = false,
+                '/' if !is_inside => {
+                    components.push(String::new());
+                    continue;
+                }
+                _ => {}
+            }
+            components.last_mut().unwrap().push(c);
+        }
+
+        components
+            .into_iter()
+            .take_while(|component| !Self::from_str(component).varies())
+            .collect::<Vec<_>>()
+            .join("/")
+    }
+
     pub fn direct_eval(s: &str, data: EvalData<'_>) -> String {
         Self::from_str(s).evaluate(data)
     }
 
-    pub fn direct_eval_file(s: &str, data: EvalData<'_>) -> PathBuf {
+    pub fn direct_eval_file(s: &str, data: EvalData<'_>) -> Result<PathBuf, String> {
         let p = PathBuf::from(Self::direct_eval(s, data));
         if let Some(path) = p.parent() {
-            utils::create_dir(&path);
+            utils::try_create_dir(&path)?;
         }
-        p
+        Ok(p)
     }
 
-    pub fn direct_eval_dir(s: &str, data: EvalData<'_>) -> PathBuf {
+    pub fn direct_eval_dir(s: &str, data: EvalData<'_>) -> Result<PathBuf, String> {
         let p = PathBuf::from(Self::direct_eval(s, data));
-        utils::create_dir(&p);
-        p
+        utils::try_create_dir(&p)?;
+        Ok(p)
     }
 }
 
@@ -75,6 +163,10 @@ enum Segment {
 enum Pattern {
     Unit(UnitPattern),
     Data(DataPattern),
+    /// Evaluated after downloading, so it evaluates to the original `{token}` text.
+    Deferred(String),
+    /// Evaluates to the original `{token}` text.
+    Unknown(String),
 }
 
 impl Pattern {
@@ -83,13 +175,30 @@ impl Pattern {
             Self::Unit(unit)
         } else if let Some(data) = DataPattern::from_str(s) {
             Self::Data(data)
+        } else if Regex::new(&format!("^(?:{}|{})$", DOWNLOAD_NUMBER, TAG))
+            .unwrap()
+            .is_match(s)
+        {
+            Self::Deferred(s.to_owned())
         } else {
-            eprintln!("invalid pattern: \"{}\"", s);
-            std::process::exit(1);
+            Self::Unknown(s.to_owned())
         }
     }
 }
 
+fn eval_unknown(token: &str) -> String {
+    let mut warned = WARNED_TOKENS.lock().unwrap();
+    if !warned.iter().any(|warned| warned == token) {
+        eprintln!(
+            "warning: unknown pattern \"{{{}}}\" is kept as is, set 'strict_patterns = true' to make it an error",
+            token
+        );
+        warned.push(token.to_owned());
+    }
+
+    format!("{{{}}}", token)
+}
+
 #[derive(Clone, Debug)]
 struct DataPattern {
     ty: DataPatternType,
@@ -133,9 +242,7 @@ impl Evaluate for DataPattern {
             Ty::PubDate => {
                 let formatting = &self.data;
 
-                let datetime = chrono::Utc
-                    .timestamp_opt(data.episode.published().as_secs() as i64, 0)
-                    .unwrap();
+                let datetime = data.episode.published_at();
 
                 if formatting == "unix" {
                     data.episode.published().as_secs().to_string()
@@ -143,16 +250,15 @@ impl Evaluate for DataPattern {
                     datetime.format(formatting).to_string()
                 }
             }
-            Ty::RssEpisode => {
-                let key = &self.data;
-
-                data.episode.get_str(&key).unwrap_or(null).to_string()
-            }
-            Ty::RssChannel => {
-                let key = &self.data;
-
-                data.podcast.get_str(&key).unwrap_or(null).to_string()
-            }
+            Ty::RssEpisode => data
+                .episode
+                .rss_value(&self.data)
+                .unwrap_or_else(|| null.to_string()),
+            Ty::RssChannel => data
+                .podcast
+                .rss_value(&self.data)
+                .unwrap_or_else(|| null.to_string()),
+            Ty::Channel => data.podcast.field(&self.data).unwrap_or(null).to_string(),
         }
     }
 }
@@ -163,6 +269,8 @@ enum DataPatternType {
     RssChannel,
     PubDate,
     CurrDate,
+    // After `RssChannel`, since the regexes aren't anchored.
+    Channel,
 }
 
 impl DataPatternType {
@@ -172,6 +280,7 @@ impl DataPatternType {
             Self::CurrDate => "currdate",
             Self::RssEpisode => "rss::episode",
             Self::RssChannel => "rss::channel",
+            Self::Channel => "channel",
         };
 
         let s = format!("{}::(.+)", s);
@@ -184,9 +293,11 @@ impl DataPatternType {
 enum UnitPattern {
     Guid,
     Url,
+    Link,
     PodName,
     AppName,
     Home,
+    State,
 }
 
 impl UnitPattern {
@@ -194,9 +305,11 @@ impl UnitPattern {
         match s {
             "guid" => Self::Guid,
             "url" => Self::Url,
+            "link" => Self::Link,
             "podname" => Self::PodName,
             "appname" => Self::AppName,
             "home" => Self::Home,
+            "state" => Self::State,
             _ => return None,
         }
         .into()
@@ -208,15 +321,24 @@ impl Evaluate for UnitPattern {
         match self {
             Self::Guid => data.episode.guid().to_string(),
             Self::Url => data.episode.url().to_string(),
+            Self::Link => data.episode.link().unwrap_or_default().to_string(),
             Self::PodName => data.pod_name.to_string(),
             Self::AppName => crate::APPNAME.to_string(),
-            Self::Home => home().unwrap_or("<missing home>".to_string()),
+            Self::Home => match home() {
+                Some(home) => home,
+                None => {
+                    eprintln!("unable to locate home directory for the {{home}} pattern.");
+                    eprintln!("set 'TALECAST_HOME', or configure a download_path without {{home}}, or pass --path.");
+                    std::process::exit(1);
+                }
+            },
+            Self::State => utils::state_dir().to_string_lossy().into_owned(),
         }
     }
 }
 
 fn home() -> Option<String> {
-    Some(dirs::home_dir()?.as_os_str().to_str()?.to_owned())
+    Some(utils::home_dir()?.as_os_str().to_str()?.to_owned())
 }
 use std::path::PathBuf;
 
@@ -241,6 +363,8 @@ impl Evaluate for FullPattern {
                 Segment::Text(text) => text.clone(),
                 Segment::Pattern(Pattern::Unit(pattern)) => pattern.evaluate(data),
                 Segment::Pattern(Pattern::Data(pattern)) => pattern.evaluate(data),
+                Segment::Pattern(Pattern::Deferred(token)) => format!("{{{}}}", token),
+                Segment::Pattern(Pattern::Unknown(token)) => eval_unknown(token),
             };
             output.push_str(&text);
         }
@@ -0,0 +1,117 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfigs;
+use crate::display::DownloadBar;
+use crate::display::PlainProgress;
+use crate::episode::Episode;
+use crate::podcast::Podcast;
+use crate::utils;
+use regex::Regex;
+use std::process;
+
+/// How many similar episodes are suggested when the guid isn't in the feed.
+const SUGGESTION_QTY: usize = 5;
+
+/// Downloads the episode with the given guid from the one podcast matching the filter,
+/// regardless of the download mode, filters and whether it was downloaded before.
+///
+/// Prints the path of the downloaded file to stdout.
+pub async fn download_episode(global_config: GlobalConfig, filter: Option<Regex>, guid: &str) {
+    let podcasts = PodcastConfigs::load().assert_not_empty().filter(filter);
+    let mut podcasts = podcasts.sorted().into_iter();
+    let (name, podcast_config) = match (podcasts.next(), podcasts.next()) {
+        (Some(podcast), None) => podcast,
+        (None, _) => {
+            eprintln!("no podcasts matched the filter");
+            process::exit(1);
+        }
+        (Some(first), Some(second)) => {
+            let names: Vec<String> = [first, second]
+                .into_iter()
+                .chain(podcasts)
+                .map(|(name, _)| name)
+                .collect();
+            eprintln!(
+                "--episode needs a filter matching one podcast, it matched: {}",
+                names.join(", ")
+            );
+            process::exit(1);
+        }
+    };
+
+    let ui = DownloadBar::hidden(name.clone());
+    let client = global_config.reqwest_client();
+    let client = podcast_config.reqwest_client(&name, &global_config, &client);
+    let podcast = match Podcast::new(
+        name.clone(),
+        podcast_config,
+        &global_config,
+        client.clone(),
+        &ui,
+    )
+    .await
+    {
+        Ok(podcast) => podcast,
+        Err(e) => {
+            eprintln!("{}: {}", &name, e);
+            process::exit(1);
+        }
+    };
+
+    let Some(episode) = podcast
+        .episodes()
+        .iter()
+        .find(|episode| episode.attrs.guid() == guid)
+    else {
+        eprintln!("{}: no episode with the guid {:?}", &name, guid);
+        let suggestions = closest_episodes(podcast.episodes(), guid);
+        if !suggestions.is_empty() {
+            eprintln!("closest matches:");
+            for episode in suggestions {
+                eprintln!("  {:?} ({})", episode.attrs.title(), episode.attrs.guid());
+            }
+        }
+        process::exit(1);
+    };
+
+    if episode.is_downloaded() {
+        eprintln!(
+            "{}: {:?} was downloaded before, downloading it again",
+            &name,
+            episode.attrs.title()
+        );
+    }
+
+    let observer = PlainProgress::new(name.clone());
+    match episode.download(&client, &ui, &observer).await {
+        Ok(mut downloaded) => {
+            downloaded.await_handle(&ui).await;
+            println!("{}", downloaded.path().display());
+        }
+        Err(e) => {
+            eprintln!("{}: {}", &name, e);
+            process::exit(1);
+        }
+    }
+}
+
+/// The episodes whose title or guid is closest to `query`, best first.
+fn closest_episodes<'a>(episodes: &'a [Episode], query: &str) -> Vec<&'a Episode> {
+    let query = query.to_lowercase();
+    let mut scored: Vec<(usize, &Episode)> = episodes
+        .iter()
+        .map(|episode| {
+            let title = episode.attrs.title().to_lowercase();
+            let guid = episode.attrs.guid().to_lowercase();
+            let distance =
+                utils::edit_distance(&query, &title).min(utils::edit_distance(&query, &guid));
+            (distance, episode)
+        })
+        .collect();
+
+    scored.sort_by_key(|(distance, _)| *distance);
+    scored
+        .into_iter()
+        .take(SUGGESTION_QTY)
+        .map(|(_, episode)| episode)
+        .collect()
+}
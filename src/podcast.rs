@@ -1,18 +1,44 @@
 use crate::config::DownloadMode;
 use crate::config::EvalData;
+use crate::config::InitialSync;
+use crate::config::OpenHours;
 use crate::config::PodcastConfig;
 use crate::config::{Config, GlobalConfig};
 use crate::display::DownloadBar;
+use crate::download_tracker::DownloadQueue;
+use crate::download_tracker::DownloadedEpisodes;
+use crate::download_tracker::LastRun;
+use crate::download_tracker::TagHashes;
 use crate::episode;
+use crate::episode::DownloadedEpisode;
 use crate::episode::Episode;
 use crate::episode::RawEpisode;
+use crate::error::ConfigError;
+use crate::error::Error;
+use crate::error::FeedFetchError;
+use crate::error::ParseError;
+use crate::feed_cache::FeedCache;
+use crate::nfo;
+use crate::observer::SyncObserver;
+use crate::stats::SyncedFile;
 use crate::tags;
 use crate::utils;
+use futures::StreamExt;
 use quickxml_to_serde::{xml_string_to_json, Config as XmlConfig};
+use regex::Regex;
 use serde_json::Map;
 use serde_json::Value;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::path::Path;
 use std::path::PathBuf;
+use std::sync::atomic::AtomicBool;
+use std::sync::atomic::Ordering;
 use std::sync::Arc;
+use std::time::Duration;
+use tokio::sync::Mutex;
+use tokio::sync::Semaphore;
+use tokio::time::Instant;
 
 fn get_inner_channel(xml: String) -> Option<serde_json::Value> {
     let conf = XmlConfig::new_with_defaults();
@@ -25,16 +51,66 @@ fn get_inner_channel(xml: String) -> Option<serde_json::Value> {
     .into()
 }
 
+/// Namespaces whose prefix is preserved in the keys of the serde values.
+const PRESERVED_NAMESPACES: &[&str] = &["itunes", "media", "podcast"];
+
+/// Replaces the placeholder in the keys of the value with the namespace separator, recursively.
+fn restore_namespaces(val: &Value, placeholder: &str) -> Value {
+    match val {
+        Value::Object(obj) => Value::Object(
+            obj.iter()
+                .map(|(key, val)| {
+                    let key = key.replace(placeholder, ":");
+                    (key, restore_namespaces(val, placeholder))
+                })
+                .collect(),
+        ),
+        Value::Array(vals) => Value::Array(
+            vals.iter()
+                .map(|val| restore_namespaces(val, placeholder))
+                .collect(),
+        ),
+        val => val.clone(),
+    }
+}
+
+/// Publish dates this far in the future are assumed to be caused by a wrong system clock,
+/// rather than the feed scheduling episodes ahead of time.
+const CLOCK_SKEW_TOLERANCE: Duration = Duration::from_secs(2 * 86400);
+
+/// Warns if the newest episode is published far in the future, since date filters
+/// compare publish dates with the system clock.
+fn warn_clock_skew(newest: Duration, ui: &DownloadBar) {
+    let ahead = newest.saturating_sub(utils::current_unix());
+    if ahead > CLOCK_SKEW_TOLERANCE {
+        ui.warn(&format!(
+            "newest episode is published {} days in the future, check the system clock",
+            ahead.as_secs() / 86400
+        ));
+    }
+}
+
+/// The title of the channel, if the feed has one.
+pub fn feed_title(xml: &str, ui: &DownloadBar) -> Option<String> {
+    let (raw_podcast, _) = xml_to_value(xml, ui)?;
+    raw_podcast.get_str("title").map(str::to_string)
+}
+
 /// Converts the podcast's xml string to serde values of the channel and the episodes.
 ///
 /// The library will merge different namespaces together, which is why we manually change
-/// the itunes namespace, and then after converting it, we change it back. Preserving itunes:XXX as
-/// separate keys.
+/// the namespace of the tags in [`PRESERVED_NAMESPACES`], and then after converting it,
+/// we change it back. Preserving e.g. itunes:XXX as separate keys.
 fn xml_to_value(xml: &str, ui: &DownloadBar) -> Option<(RawPodcast, Vec<RawEpisode>)> {
     ui.log_info("converting xml to serde values");
+    // A byte order mark or whitespace before the `<?xml` declaration makes the document invalid,
+    // and both are common enough in the wild. Cached feeds go through here as well.
+    let xml = xml.trim_start_matches(|c: char| c == '\u{feff}' || c.is_whitespace());
     let placeholder = "__placeholder__";
-    let replacement = format!("itunes{}", placeholder);
-    let xml = xml.replace("itunes:", &replacement);
+    let tag_regex = Regex::new(&format!(r"<(/?)({}):", PRESERVED_NAMESPACES.join("|"))).unwrap();
+    let xml = tag_regex
+        .replace_all(xml, format!("<${{1}}${{2}}{}", placeholder))
+        .into_owned();
     let mut val = match get_inner_channel(xml) {
         Some(val) => val,
         None => {
@@ -48,8 +124,8 @@ fn xml_to_value(xml: &str, ui: &DownloadBar) -> Option<(RawPodcast, Vec<RawEpiso
 
     if let Some(obj) = val.as_object() {
         for (key, value) in obj {
-            let new_key = key.replace(&replacement, "itunes:");
-            new_map.insert(new_key, value.clone());
+            let new_key = key.replace(placeholder, ":");
+            new_map.insert(new_key, restore_namespaces(value, placeholder));
         }
     }
 
@@ -62,8 +138,8 @@ fn xml_to_value(xml: &str, ui: &DownloadBar) -> Option<(RawPodcast, Vec<RawEpiso
         .map(|item| {
             let mut new_item_map: Map<String, Value> = Map::new();
             for (key, val) in item.as_object().expect("unexpected serde type").iter() {
-                let new_key = key.replace(&replacement, "itunes:");
-                new_item_map.insert(new_key, val.clone());
+                let new_key = key.replace(placeholder, ":");
+                new_item_map.insert(new_key, restore_namespaces(val, placeholder));
             }
             RawEpisode::new(new_item_map)
         })
@@ -84,6 +160,12 @@ impl RawPodcast {
         utils::val_to_str(self.0.get(key)?)
     }
 
+    /// The text of an element of the channel, for `{rss::channel::key}`. See
+    /// [`utils::xml_lookup`].
+    pub fn rss_value(&self, key: &str) -> Option<String> {
+        utils::xml_lookup(&self.0, key)
+    }
+
     pub fn title(&self) -> &str {
         self.get_str("title").unwrap()
     }
@@ -93,6 +175,11 @@ impl RawPodcast {
         self.get_str(&key)
     }
 
+    pub fn explicit(&self) -> Option<bool> {
+        self.get_str("itunes:explicit")
+            .and_then(utils::parse_explicit)
+    }
+
     pub fn categories(&self) -> Vec<&str> {
         let key = "itunes:category";
         match self.0.get(key).and_then(|x| x.as_array()) {
@@ -114,13 +201,99 @@ impl RawPodcast {
         let inner = self.0.get("image")?;
         utils::val_to_url(inner)
     }
+
+    /// The website of the podcast.
+    ///
+    /// `atom:link` elements end up under the same key, so the first plain text link is used.
+    pub fn link(&self) -> Option<&str> {
+        match self.0.get("link")? {
+            Value::Array(vals) => vals.iter().find_map(|val| val.as_str()),
+            val => val.as_str(),
+        }
+    }
+
+    /// The URL and description of the first `podcast:funding` element.
+    pub fn funding(&self) -> Option<(&str, Option<&str>)> {
+        let funding = match self.0.get("podcast:funding")? {
+            Value::Array(vals) => vals.first()?,
+            val => val,
+        };
+
+        let url = funding.get("@url").and_then(|url| url.as_str())?;
+        Some((url, utils::val_to_str(funding)))
+    }
+
+    /// Channel metadata by name, for the `{channel::...}` pattern.
+    pub fn field(&self, name: &str) -> Option<&str> {
+        match name {
+            "title" => self.get_str("title"),
+            "author" => self.author(),
+            "link" => self.link(),
+            "copyright" => self.copyright(),
+            "language" => self.language(),
+            "funding" => self.funding().and_then(|(_, text)| text),
+            "funding_url" => self.funding().map(|(url, _)| url),
+            _ => None,
+        }
+    }
+}
+
+/// Fetches the feed again for the current URL of the episode with the given guid.
+///
+/// For feeds that sign enclosure URLs with short-lived tokens.
+pub async fn refresh_enclosure_url(
+    client: &reqwest::Client,
+    config: &Config,
+    guid: &str,
+    ui: &DownloadBar,
+) -> Option<String> {
+    let xml = utils::download_text(client, &config.url, &config.request, ui)
+        .await
+        .map_err(|e| ui.log_warn(e))
+        .ok()?;
+    let (_, raw_episodes) = xml_to_value(&xml, ui)?;
+
+    let mut attrs = raw_episodes
+        .into_iter()
+        .filter_map(|raw| episode::Attributes::new(raw, config.guid_fallback).ok())
+        .find(|attrs| attrs.guid == guid)?;
+
+    attrs.select_media(config.prefer_media, config.prefer_bitrate);
+    match utils::resolve_relative_url(&config.url, attrs.url()) {
+        Some(url) => Some(url),
+        None => Some(attrs.url),
+    }
+}
+
+/// The episodes downloaded by [`Podcast::sync`].
+#[derive(Debug, Default)]
+pub struct SyncedEpisodes {
+    pub files: Vec<SyncedFile>,
+    /// Bytes transferred for the episodes.
+    pub bytes: u64,
+    /// Downloads stopped because of the `min_free_space` setting.
+    pub low_disk_space: bool,
 }
 
 #[derive(Debug)]
 pub struct Podcast {
+    /// Key of the podcast in `podcasts.toml`.
+    name: String,
     episodes: Vec<Episode>,
     client: Arc<reqwest::Client>,
     mode: DownloadMode,
+    download_window: Option<OpenHours>,
+    initial: InitialSync,
+    /// Episode types in the order they're downloaded.
+    download_priority: Vec<String>,
+    /// How many episodes are downloaded at the same time.
+    episode_concurrency: usize,
+    /// Minimum time between starting two episode downloads.
+    request_delay: Option<Duration>,
+    /// When the last episode download started, for the `request_delay`.
+    last_request: Mutex<Option<Instant>>,
+    /// When the cached feed was fetched, if syncing offline.
+    offline_since: Option<String>,
 }
 
 impl Podcast {
@@ -130,24 +303,104 @@ impl Podcast {
         global_config: &GlobalConfig,
         client: Arc<reqwest::Client>,
         ui: &DownloadBar,
-    ) -> Result<Podcast, String> {
-        ui.fetching();
-        ui.log_info("downloading podcast info...");
-        let Some(xml_string) = utils::download_text(&client, &config.url, ui).await else {
-            return Err("failed to download xml-file".into());
+    ) -> Result<Podcast, Error> {
+        let mut config = config;
+        let config_error = |reason| ConfigError {
+            podcast: name.clone(),
+            reason,
+        };
+        let mut mode = DownloadMode::new(global_config, &config).map_err(config_error)?;
+        if config.since_last_run(global_config) {
+            if let Some(last_run) = LastRun::load(&name) {
+                mode.published_after(last_run);
+            }
+        }
+
+        let tag_filters = config.tag_filters(global_config).map_err(config_error)?;
+        let download_window = config
+            .download_window(global_config)
+            .map_err(config_error)?;
+        let timezone = config
+            .pubdate_timezone(global_config)
+            .map_err(config_error)?;
+
+        let (xml_string, offline_since) = if global_config.offline() {
+            ui.log_info("loading cached podcast info...");
+            let Some((xml, fetched)) = FeedCache::load(config.feed_key()) else {
+                return Err(FeedFetchError {
+                    podcast: name,
+                    url: config.url,
+                    reason: "no cached feed, enable 'cache_feeds' and sync once".into(),
+                }
+                .into());
+            };
+
+            (xml, Some(fetched.format("%Y-%m-%d %H:%M").to_string()))
+        } else {
+            ui.fetching();
+            let feed_error = |url: &str, reason| FeedFetchError {
+                podcast: name.clone(),
+                url: url.to_string(),
+                reason,
+            };
+            config
+                .resolve_url()
+                .await
+                .map_err(|reason| feed_error(&config.url, reason))?;
+            ui.log_info("downloading podcast info...");
+            let request = config.request_settings();
+            let xml = utils::download_text(&client, &config.url, &request, ui)
+                .await
+                .map_err(|reason| feed_error(&config.url, reason))?;
+
+            if global_config.cache_feeds() {
+                if let Err(e) = FeedCache::save(config.feed_key(), &xml) {
+                    ui.log_warn(e);
+                }
+            }
+
+            (xml, None)
         };
 
         let Some((raw_podcast, raw_episodes)) = xml_to_value(&xml_string, ui) else {
-            return Err("failed to parse xml".into());
+            return Err(ParseError {
+                podcast: name,
+                url: config.url,
+                reason: "failed to parse xml".into(),
+            }
+            .into());
         };
 
+        let (media_kind, bitrate) = config.media_preference(global_config);
+        let guid_fallback = config.guid_fallback(global_config);
+        let channel_explicit = raw_podcast.explicit();
         let episode_attrs = {
             let mut attrs = vec![];
 
             for episode in raw_episodes {
                 ui.log_trace("parsing attributes from raw episode");
-                match episode::Attributes::new(episode) {
-                    Ok(attr) => attrs.push(attr),
+                match episode::Attributes::new(episode, guid_fallback) {
+                    Ok(mut attr) => {
+                        attr.offset = timezone.offset(attr.published(), attr.offset);
+                        attr.explicit = attr.explicit.or(channel_explicit);
+                        if let Some(url) = attr.select_media(media_kind, bitrate) {
+                            ui.log_info(format!(
+                                "{:?}: chose {:?} over the enclosure",
+                                attr.title(),
+                                &url
+                            ));
+                        }
+
+                        if let Some(url) = utils::resolve_relative_url(&config.url, attr.url()) {
+                            ui.log_warn(format!(
+                                "non-standard feed: resolved relative enclosure url {:?} to {:?}",
+                                attr.url(),
+                                &url
+                            ));
+                            attr.url = url;
+                        }
+                        attrs.push(attr);
+                    }
                     Err(e) => {
                         ui.log_debug(e);
                     }
@@ -158,62 +411,441 @@ impl Podcast {
             attrs
         };
 
+        if let Some(newest) = episode_attrs.last() {
+            warn_clock_skew(newest.published(), ui);
+        }
+
         let mut episodes = vec![];
         for (index, attr) in episode_attrs.into_iter().enumerate() {
-            let tags = tags::extract_tags_from_raw(&raw_podcast, &attr, ui).await;
-            let config = {
-                let data = EvalData::new(&name, &raw_podcast, &attr);
-                Config::new(global_config, &config, data)
-            };
+            let data = EvalData::new(&name, &raw_podcast, &attr);
+            let config = Config::new(global_config, &config, &tag_filters, data)?;
+            // Also extracted when the files aren't tagged, for `{tag::<field>}` in the name.
+            let tags = tags::extract_tags_from_raw(data, &config, index, ui).await;
 
-            let url = attr
-                .image()
-                .ok()
+            let url = config
+                .embed_episode_art
+                .then(|| attr.image().ok())
+                .flatten()
                 .or(raw_podcast.image())
                 .map(ToString::to_string);
 
-            let episode = Episode::new(attr, index, config, tags, url);
+            let nfo = config.write_nfo.then(|| nfo::episode_details(data));
+            let episode = Episode::new(attr, index, config, tags, url, nfo);
             episodes.push(episode);
         }
 
-        let mode = DownloadMode::new(global_config, &config);
+        let initial = config.initial_sync(global_config);
+        let download_priority = config.download_priority(global_config);
+        let episode_concurrency = config.episode_concurrency(global_config);
+        let request_delay = config.request_delay(global_config);
 
         Ok(Podcast {
+            name,
             episodes,
             client,
             mode,
+            download_window,
+            initial,
+            download_priority,
+            episode_concurrency,
+            request_delay,
+            last_request: Mutex::new(None),
+            offline_since,
         })
     }
 
-    pub async fn sync(self, ui: &mut DownloadBar) -> Vec<PathBuf> {
-        ui.init();
+    /// Loads the podcast without showing progress, for the commands that look at the downloaded
+    /// episodes rather than syncing.
+    pub async fn load_quietly(
+        name: String,
+        config: PodcastConfig,
+        global_config: &GlobalConfig,
+        client: &Arc<reqwest::Client>,
+    ) -> Result<Podcast, Error> {
+        let ui = DownloadBar::hidden(name.clone());
+        let client = config.reqwest_client(&name, global_config, client);
+        Self::new(name, config, global_config, client, &ui).await
+    }
+
+    pub fn episodes(&self) -> &[Episode] {
+        &self.episodes
+    }
+
+    /// The episodes grouped by their download tracker, as the tracker path can depend on the
+    /// episode.
+    pub fn episodes_by_tracker(&self) -> HashMap<PathBuf, Vec<&Episode>> {
+        let mut trackers: HashMap<PathBuf, Vec<&Episode>> = HashMap::new();
+        for episode in &self.episodes {
+            trackers
+                .entry(episode.config.tracker_path.clone())
+                .or_default()
+                .push(episode);
+        }
+
+        trackers
+    }
+
+    /// Downloads the pending episodes.
+    ///
+    /// The `ui` is used for logging, while the progress is reported to the `observer`.
+    /// With `episode_limit`, a permit is held for each episode rather than the whole sync.
+    pub async fn sync(
+        self,
+        ui: &DownloadBar,
+        observer: &dyn SyncObserver,
+        episode_limit: Option<&Semaphore>,
+    ) -> SyncedEpisodes {
         ui.log_info("syncing...");
+        let started = utils::current_unix();
+
+        let episodes = self.queued_and_pending(ui);
+
+        if let Some(fetched) = &self.offline_since {
+            for episode in &episodes {
+                ui.log_info(format!("pending: {:?}", episode.attrs.title()));
+            }
+
+            observer.on_offline(episodes.len(), fetched);
+            return SyncedEpisodes::default();
+        }
+
+        if self.initial == InitialSync::Now {
+            match self.mark_seen_if_new() {
+                Ok(0) => {}
+                Ok(marked) => {
+                    ui.log_info(format!("first sync, marked {} episodes as seen", marked));
+                    observer.on_marked_seen(marked);
+                    return SyncedEpisodes::default();
+                }
+                Err(e) => {
+                    observer.on_error(&e);
+                    return SyncedEpisodes::default();
+                }
+            }
+        }
+
+        if let Some(window) = &self.download_window {
+            if !episodes.is_empty() && !window.is_open(chrono::Local::now().naive_local()) {
+                for episode in &episodes {
+                    ui.log_info(format!(
+                        "deferred until download window: {:?}",
+                        episode.attrs.title()
+                    ));
+                }
+
+                observer.on_downloads_deferred(episodes.len(), &window.start());
+                return SyncedEpisodes::default();
+            }
+        }
+
+        observer.on_feed_loaded(episodes.len());
+
+        // Checked up front, so a read-only mount fails the podcast once instead of every episode.
+        if let Err(e) = Self::check_download_dirs(&episodes) {
+            observer.on_error(&e);
+            return SyncedEpisodes::default();
+        }
+
+        self.save_queue(&episodes, ui);
+
+        let (downloaded, low_disk_space) = if self.episode_concurrency > 1 {
+            self.download_concurrently(&episodes, ui, observer, episode_limit)
+                .await
+        } else {
+            self.download_sequentially(&episodes, ui, observer, episode_limit)
+                .await
+        };
+
+        // Whatever failed stays queued for the next sync.
+        let remaining: Vec<&Episode> = episodes
+            .into_iter()
+            .filter(|episode| !episode.is_downloaded())
+            .collect();
+        self.save_queue(&remaining, ui);
+
+        if remaining.is_empty() && !low_disk_space {
+            if let Err(e) = LastRun::save(&self.name, started) {
+                ui.log_warn(e);
+            }
+        }
+
+        self.retag_changed(ui).await;
+
+        let mut synced = SyncedEpisodes {
+            low_disk_space,
+            ..Default::default()
+        };
 
-        let episodes = self.pending_episodes();
+        observer.on_hooks_start();
+        for mut episode in downloaded {
+            episode.await_handle(ui).await;
+            synced.bytes += episode.bytes();
+            let published = episode.as_ref().attrs.published();
+            synced.files.push(SyncedFile {
+                path: episode.into_path(),
+                published,
+            });
+        }
+
+        observer.on_sync_done();
+        synced
+    }
+
+    /// The episodes a sync would download: the ones left in the queue by an interrupted sync,
+    /// followed by the pending ones.
+    ///
+    /// Queued episodes are looked up in the current feed so their urls are up to date. The ones
+    /// no longer in the feed or already downloaded are dropped.
+    pub fn queued_and_pending(&self, ui: &DownloadBar) -> Vec<&Episode> {
+        let pending = self.pending_episodes();
+        let queued = DownloadQueue::load(&self.name);
+        if queued.is_empty() {
+            return pending;
+        }
+
+        let mut episodes: Vec<&Episode> = vec![];
+        for id in &queued {
+            match self.episodes.iter().find(|episode| &episode.get_id() == id) {
+                Some(episode) if !episode.is_downloaded() => episodes.push(episode),
+                Some(_) => {}
+                None => ui.log_info(format!("queued episode no longer in the feed: {}", id)),
+            }
+        }
+
+        if !episodes.is_empty() {
+            ui.log_info(format!("resuming {} queued episodes", episodes.len()));
+        }
+
+        for episode in pending {
+            if !episodes.iter().any(|queued| std::ptr::eq(*queued, episode)) {
+                episodes.push(episode);
+            }
+        }
+
+        episodes
+    }
+
+    /// Fails if files can't be created in the download or partial directories of the episodes.
+    fn check_download_dirs(episodes: &[&Episode]) -> Result<(), String> {
+        let dirs: HashSet<&Path> = episodes
+            .iter()
+            .flat_map(|episode| {
+                let config = &episode.config;
+                [
+                    Some(config.download_path.as_path()),
+                    config.partial_path.as_deref(),
+                ]
+            })
+            .flatten()
+            .collect();
+
+        dirs.into_iter().try_for_each(utils::check_writable)
+    }
+
+    fn save_queue(&self, episodes: &[&Episode], ui: &DownloadBar) {
+        let ids: Vec<String> = episodes.iter().map(|episode| episode.get_id()).collect();
+        if let Err(e) = DownloadQueue::save(&self.name, &ids) {
+            ui.log_warn(e);
+        }
+    }
+
+    /// Rewrites the tags of downloaded episodes whose feed metadata changed since they were
+    /// tagged, if `retag_on_change` is enabled.
+    ///
+    /// Episodes tagged before it was enabled only get their hash saved, to compare with later.
+    async fn retag_changed(&self, ui: &DownloadBar) {
+        let mut hashes = None;
+        let mut trackers: HashMap<PathBuf, DownloadedEpisodes> = HashMap::new();
+
+        for episode in &self.episodes {
+            if !episode.config.retag_on_change || !episode.config.write_tags {
+                continue;
+            }
+
+            let tracker_path = episode.tracker_path();
+            let tracked = trackers
+                .entry(tracker_path.to_path_buf())
+                .or_insert_with(|| DownloadedEpisodes::load(tracker_path));
+            let id = episode.get_id();
+            let Some(path) = tracked
+                .get(&id)
+                .and_then(|tracked| tracked.path.clone())
+                .filter(|path| path.exists())
+            else {
+                continue;
+            };
+
+            let hashes = hashes.get_or_insert_with(TagHashes::load);
+            let hash = episode.tag_hash();
+            match hashes.get(tracker_path, &id) {
+                Some(saved) if saved == hash => {}
+                Some(_) => {
+                    ui.log_info(format!(
+                        "feed metadata changed: {:?}",
+                        episode.attrs.title()
+                    ));
+                    DownloadedEpisode::new(episode, path)
+                        .retag(&self.client, ui)
+                        .await;
+                }
+                None => {
+                    if let Err(e) = TagHashes::save(tracker_path, &id, &hash) {
+                        episode.log_warn(ui, e);
+                    }
+                }
+            }
+        }
+    }
+
+    /// Waits until `request_delay` has passed since the last episode download started.
+    ///
+    /// The lock is held while waiting, so concurrent downloads start one delay apart.
+    async fn wait_for_request_delay(&self) {
+        let Some(delay) = self.request_delay else {
+            return;
+        };
+
+        let mut last_request = self.last_request.lock().await;
+        if let Some(last_request) = *last_request {
+            tokio::time::sleep_until(last_request + delay).await;
+        }
+        *last_request = Some(Instant::now());
+    }
+
+    /// Downloads the episodes one at a time, stopping at the first error.
+    ///
+    /// Returns the downloaded episodes, and whether it stopped because of low disk space.
+    async fn download_sequentially<'a>(
+        &self,
+        episodes: &[&'a Episode],
+        ui: &DownloadBar,
+        observer: &dyn SyncObserver,
+        episode_limit: Option<&Semaphore>,
+    ) -> (Vec<DownloadedEpisode<'a>>, bool) {
         let mut downloaded = vec![];
+        let mut low_disk_space = false;
+
+        for (index, episode) in episodes.iter().copied().enumerate() {
+            if let Err(e) = episode.check_free_space() {
+                observer.on_error(&e);
+                low_disk_space = true;
+                break;
+            }
 
-        for (index, episode) in episodes.iter().enumerate() {
-            ui.begin_download(&episode, index, episodes.len());
+            let _permit = match episode_limit {
+                Some(limit) => Some(limit.acquire().await.unwrap()),
+                None => None,
+            };
+
+            self.wait_for_request_delay().await;
+            observer.on_episode_start(episode, index, episodes.len());
 
-            match episode.download(&self.client, ui).await {
-                Ok(downloaded_episode) => downloaded.push(downloaded_episode),
+            match episode.download(&self.client, ui, observer).await {
+                Ok(downloaded_episode) => {
+                    observer.on_episode_done(episode, downloaded_episode.path());
+                    downloaded.push(downloaded_episode);
+                }
                 Err(e) => {
-                    ui.error(&e);
+                    observer.on_error(&e.to_string());
                     break;
                 }
             };
         }
 
-        let mut paths = vec![];
+        (downloaded, low_disk_space)
+    }
 
-        ui.hook_status();
-        for mut episode in downloaded {
-            episode.await_handle(ui).await;
-            paths.push(episode.into_path());
+    /// Downloads up to `episode_concurrency` episodes at the same time, each with its own
+    /// observer if available.
+    ///
+    /// An error only fails its own episode. The downloaded episodes are returned in the same
+    /// order as `episodes`, regardless of which finished first.
+    async fn download_concurrently<'a>(
+        &self,
+        episodes: &[&'a Episode],
+        ui: &DownloadBar,
+        observer: &dyn SyncObserver,
+        episode_limit: Option<&Semaphore>,
+    ) -> (Vec<DownloadedEpisode<'a>>, bool) {
+        let low_disk_space = AtomicBool::new(false);
+        let episode_qty = episodes.len();
+
+        let downloads: Vec<_> = episodes
+            .iter()
+            .copied()
+            .enumerate()
+            .map(|(index, episode)| {
+                let low_disk_space = &low_disk_space;
+                async move {
+                    if low_disk_space.load(Ordering::SeqCst) {
+                        return None;
+                    }
+
+                    if let Err(e) = episode.check_free_space() {
+                        if !low_disk_space.swap(true, Ordering::SeqCst) {
+                            observer.on_error(&e);
+                        }
+                        return None;
+                    }
+
+                    let _permit = match episode_limit {
+                        Some(limit) => Some(limit.acquire().await.unwrap()),
+                        None => None,
+                    };
+
+                    self.wait_for_request_delay().await;
+                    let episode_observer = observer.episode_observer();
+                    let episode_observer = episode_observer.as_deref().unwrap_or(observer);
+                    episode_observer.on_episode_start(episode, index, episode_qty);
+
+                    match episode.download(&self.client, ui, episode_observer).await {
+                        Ok(downloaded_episode) => {
+                            episode_observer.on_episode_done(episode, downloaded_episode.path());
+                            Some(downloaded_episode)
+                        }
+                        Err(e) => {
+                            episode_observer.on_error(&e.to_string());
+                            None
+                        }
+                    }
+                }
+            })
+            .collect();
+
+        let downloaded: Vec<Option<DownloadedEpisode>> = futures::stream::iter(downloads)
+            .buffered(self.episode_concurrency)
+            .collect()
+            .await;
+
+        let downloaded = downloaded.into_iter().flatten().collect();
+        (downloaded, low_disk_space.load(Ordering::SeqCst))
+    }
+
+    /// Marks every episode as downloaded if nothing has been downloaded yet, for `initial = "now"`.
+    ///
+    /// Returns how many episodes were marked.
+    fn mark_seen_if_new(&self) -> Result<usize, String> {
+        let mut trackers: Vec<&Path> = self
+            .episodes
+            .iter()
+            .map(|episode| episode.config.tracker_path.as_path())
+            .collect();
+        trackers.sort();
+        trackers.dedup();
+
+        let is_new = trackers
+            .iter()
+            .all(|path| DownloadedEpisodes::load(path).episodes().next().is_none());
+        if !is_new {
+            return Ok(0);
+        }
+
+        for episode in &self.episodes {
+            let path = episode.config.tracker_path.as_path();
+            DownloadedEpisodes::append(path, &episode.get_id(), episode.attrs.title(), None)?;
         }
 
-        ui.complete();
-        paths
+        Ok(self.episodes.len())
     }
 
     fn pending_episodes(&self) -> Vec<&Episode> {
@@ -227,24 +859,301 @@ impl Podcast {
         
         // In backlog mode it makes more sense to download earliest episode first.
         // in standard mode, the most recent episodes are more relevant.
-        match self.mode {
-            
-            DownloadMode::Backlog { start: _, interval: _,max_episodes} => {
+        let max_episodes = match self.mode {
+            DownloadMode::Backlog { max_episodes, .. } => {
                 pending.sort_by_key(|ep| ep.index);
-                if max_episodes.is_some() { 
-                    pending.truncate(max_episodes.unwrap() as usize);
-                }
+                max_episodes
             }
 
-            DownloadMode::Standard { max_time: _, earliest_date: _, max_episodes } => {
+            DownloadMode::Standard { max_episodes, .. } => {
                 pending.sort_by_key(|ep| ep.index);
                 pending.reverse();
-                if max_episodes.is_some() { 
-                    pending.truncate(max_episodes.unwrap() as usize);
-                }
+                max_episodes
             }
+        };
+
+        // Negative values download nothing, and values larger than the feed download everything.
+        if let Some(max_episodes) = max_episodes {
+            pending.truncate(usize::try_from(max_episodes).unwrap_or(0));
+        }
+
+        // Only changes the order, so it's applied after choosing which episodes to download.
+        // Stable, so episodes of the same priority keep the order of the mode.
+        if !self.download_priority.is_empty() {
+            pending.sort_by_key(|episode| {
+                let ty = episode.attrs.episode_type();
+                self.download_priority
+                    .iter()
+                    .position(|priority| priority == &ty)
+                    .unwrap_or(self.download_priority.len())
+            });
         }
 
         pending
     }
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::episode::XmlWrapper;
+    use crate::testing;
+    use crate::testing::NoProgress;
+    use lofty::tag::ItemKey;
+
+    #[test]
+    fn parses_feed_with_bom_and_leading_whitespace() {
+        let ui = DownloadBar::hidden("test".to_string());
+        let xml = "\u{feff}\n  <?xml version=\"1.0\" encoding=\"UTF-8\"?>
+<rss version=\"2.0\" xmlns:itunes=\"http://www.itunes.com/dtds/podcast-1.0.dtd\">
+  <channel>
+    <title>Show</title>
+    <itunes:author>Host</itunes:author>
+    <item><title>First</title></item>
+    <item><title>Second</title></item>
+  </channel>
+</rss>";
+
+        let (podcast, episodes) = xml_to_value(xml, &ui).unwrap();
+        assert_eq!(podcast.title(), "Show");
+        assert_eq!(podcast.get_str("itunes:author"), Some("Host"));
+
+        let titles: Vec<String> = episodes
+            .iter()
+            .map(|episode| episode.get_string("title").unwrap())
+            .collect();
+        assert_eq!(titles, ["First", "Second"]);
+    }
+
+    const EPISODE_DATA: &[u8] = &testing::SILENT_MP3;
+
+    fn feed(base: &str, enclosures: [&str; 2]) -> String {
+        format!(
+            r#"<?xml version="1.0" encoding="UTF-8"?>
+<rss version="2.0">
+  <channel>
+    <title>Test Podcast</title>
+    <item>
+      <title>Episode One</title>
+      <guid>ep-1</guid>
+      <pubDate>Mon, 01 Jan 2024 00:00:00 +0000</pubDate>
+      <enclosure url="{base}{}" length="{len}" type="audio/mpeg"/>
+    </item>
+    <item>
+      <title>Episode Two</title>
+      <guid>ep-2</guid>
+      <pubDate>Tue, 02 Jan 2024 00:00:00 +0000</pubDate>
+      <enclosure url="{base}{}" length="{len}" type="audio/mpeg"/>
+    </item>
+  </channel>
+</rss>"#,
+            enclosures[0],
+            enclosures[1],
+            len = EPISODE_DATA.len(),
+        )
+    }
+
+    /// Serves feeds and episodes, along with redirects and missing files.
+    fn serve(request: &testing::Request) -> testing::Response {
+        let base = format!("http://{}", request.header("host").unwrap_or_default());
+        let redirect = |status, path: &str| {
+            testing::Response::new(status, "").header("Location", &format!("{}{}", base, path))
+        };
+
+        match request.path.as_str() {
+            "/feed.xml" => testing::Response::new(200, feed(&base, ["/ep1.mp3", "/ep2.mp3"])),
+            "/moved.xml" => testing::Response::new(200, feed(&base, ["/ep1.mp3", "/old.mp3"])),
+            "/broken.xml" => testing::Response::new(200, feed(&base, ["/gone.mp3", "/gone.mp3"])),
+            "/old-feed.xml" => redirect(301, "/moved.xml"),
+            "/old.mp3" => redirect(302, "/ep2.mp3"),
+            "/ep1.mp3" | "/ep2.mp3" => {
+                testing::Response::new(200, EPISODE_DATA).header("Content-Type", "audio/mpeg")
+            }
+            _ => testing::Response::new(404, "not found"),
+        }
+    }
+
+    async fn load(url: &str, download_path: &Path) -> Result<Podcast, Error> {
+        load_with(url, download_path, "").await
+    }
+
+    /// Like [`load`], with more settings for the podcast.
+    async fn load_with(url: &str, download_path: &Path, settings: &str) -> Result<Podcast, Error> {
+        testing::isolate_app_dirs();
+        let config = format!(
+            "url = {:?}\ndownload_path = {:?}\n{}",
+            url, download_path, settings
+        );
+        let config: PodcastConfig = toml::from_str(&config).unwrap();
+        let ui = DownloadBar::hidden("test".to_string());
+        let client = Arc::new(testing::client());
+        Podcast::new(
+            "test".to_string(),
+            config,
+            &GlobalConfig::default(),
+            client,
+            &ui,
+        )
+        .await
+    }
+
+    #[tokio::test]
+    async fn syncs_episodes_from_feed() {
+        let server = testing::TestServer::start(serve);
+        let dir = testing::temp_dir("sync");
+        let podcast = load(&server.url("/feed.xml"), &dir).await.unwrap();
+        assert_eq!(podcast.episodes().len(), 2);
+
+        let ui = DownloadBar::hidden("test".to_string());
+        let synced = podcast.sync(&ui, &NoProgress, None).await;
+        assert_eq!(synced.files.len(), 2);
+        assert_eq!(synced.bytes, 2 * EPISODE_DATA.len() as u64);
+
+        let tracker = DownloadedEpisodes::load(&dir.join(".downloaded"));
+        for (id, title) in [("ep-1", "Episode One"), ("ep-2", "Episode Two")] {
+            let episode = tracker.get(id).unwrap();
+            assert_eq!(episode.title.as_deref(), Some(title));
+
+            let path = episode.path.as_ref().unwrap();
+            assert!(path.starts_with(&dir));
+            assert!(std::fs::read(path).unwrap().ends_with(EPISODE_DATA));
+
+            let mut file = tags::read_file(path).unwrap().unwrap();
+            let tag = tags::primary_tag(&mut file);
+            assert_eq!(tag.get_string(&ItemKey::TrackTitle), Some(title));
+        }
+    }
+
+    #[tokio::test]
+    async fn max_episodes_limits_pending_episodes() {
+        let server = testing::TestServer::start(|_| {
+            let items: String = (1..=3)
+                .map(|n| {
+                    let day = 1 + (n - 1) * 7;
+                    format!(
+                        "<item><title>Episode {n}</title><guid>ep-{n}</guid>\
+                         <pubDate>Mon, {day:02} Jan 2024 00:00:00 +0000</pubDate>\
+                         <enclosure url=\"http://127.0.0.1/ep{n}.mp3\" type=\"audio/mpeg\"/></item>"
+                    )
+                })
+                .collect();
+            let feed = format!(
+                "<rss version=\"2.0\"><channel><title>Three</title>{items}</channel></rss>"
+            );
+            testing::Response::new(200, feed)
+        });
+
+        let pending = |podcast: &Podcast| -> Vec<String> {
+            podcast
+                .pending_episodes()
+                .into_iter()
+                .map(|episode| episode.attrs.title().to_string())
+                .collect()
+        };
+
+        // A feed smaller than the limit downloads everything, newest first.
+        let dir = testing::temp_dir("max-episodes");
+        let podcast = load_with(&server.url("/feed.xml"), &dir, "max_episodes = 10")
+            .await
+            .unwrap();
+        assert_eq!(pending(&podcast), ["Episode 3", "Episode 2", "Episode 1"]);
+
+        let podcast = load_with(&server.url("/feed.xml"), &dir, "max_episodes = 2")
+            .await
+            .unwrap();
+        assert_eq!(pending(&podcast), ["Episode 3", "Episode 2"]);
+
+        // Negative values download nothing.
+        let podcast = load_with(&server.url("/feed.xml"), &dir, "max_episodes = -1")
+            .await
+            .unwrap();
+        assert!(pending(&podcast).is_empty());
+    }
+
+    #[cfg(unix)]
+    #[tokio::test]
+    async fn unwritable_download_dir_skips_only_that_podcast() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let server = testing::TestServer::start(serve);
+        let read_only = testing::temp_dir("read-only-podcast");
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o555)).unwrap();
+        // Permissions don't stop root from writing.
+        let writable = std::fs::create_dir(read_only.join("probe")).is_ok();
+
+        let result = load(&server.url("/feed.xml"), &read_only.join("episodes")).await;
+        std::fs::set_permissions(&read_only, std::fs::Permissions::from_mode(0o755)).unwrap();
+        if !writable {
+            match result {
+                Err(Error::Config(e)) => assert!(e.reason.contains("episodes"), "{}", e.reason),
+                other => panic!("expected a config error, got {:?}", other.map(|_| ())),
+            }
+        }
+
+        let dir = testing::temp_dir("writable-podcast");
+        let podcast = load(&server.url("/feed.xml"), &dir).await.unwrap();
+        let ui = DownloadBar::hidden("test".to_string());
+        let synced = podcast.sync(&ui, &NoProgress, None).await;
+        assert_eq!(synced.files.len(), 2);
+        for file in &synced.files {
+            assert!(file.path.starts_with(&dir));
+        }
+    }
+
+    #[tokio::test]
+    async fn follows_redirects_of_feed_and_enclosure() {
+        let server = testing::TestServer::start(serve);
+        let dir = testing::temp_dir("redirect");
+        let podcast = load(&server.url("/old-feed.xml"), &dir).await.unwrap();
+
+        let ui = DownloadBar::hidden("test".to_string());
+        let episode = &podcast.episodes()[1];
+        assert_eq!(episode.attrs.url(), server.url("/old.mp3"));
+        let downloaded = episode
+            .download(&testing::client(), &ui, &NoProgress)
+            .await
+            .unwrap();
+        assert!(std::fs::read(downloaded.path())
+            .unwrap()
+            .ends_with(EPISODE_DATA));
+
+        let paths: Vec<String> = server.requests().into_iter().map(|r| r.path).collect();
+        assert!(paths.contains(&"/moved.xml".to_string()));
+        assert!(paths.contains(&"/ep2.mp3".to_string()));
+    }
+
+    #[tokio::test]
+    async fn missing_feed_fails_to_fetch() {
+        let server = testing::TestServer::start(serve);
+        let dir = testing::temp_dir("missing-feed");
+        let url = server.url("/missing.xml");
+
+        match load(&url, &dir).await {
+            Err(Error::FeedFetch(e)) => {
+                assert_eq!(e.podcast, "test");
+                assert_eq!(e.url, url);
+                assert!(e.reason.contains("404"), "{}", e.reason);
+            }
+            other => panic!("expected a feed fetch error, got {:?}", other.map(|_| ())),
+        }
+    }
+
+    #[tokio::test]
+    async fn missing_enclosure_fails_to_download() {
+        let server = testing::TestServer::start(serve);
+        let dir = testing::temp_dir("missing-enclosure");
+        let podcast = load(&server.url("/broken.xml"), &dir).await.unwrap();
+
+        let ui = DownloadBar::hidden("test".to_string());
+        let episode = &podcast.episodes()[0];
+        match episode.download(&testing::client(), &ui, &NoProgress).await {
+            Err(Error::Download(e)) => {
+                assert_eq!(e.episode, "Episode One");
+                assert_eq!(e.url, server.url("/gone.mp3"));
+            }
+            other => panic!("expected a download error, got {:?}", other.map(|_| ())),
+        }
+
+        assert!(!episode.is_downloaded());
+    }
+}
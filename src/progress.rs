@@ -0,0 +1,162 @@
+use crate::episode::Episode;
+use crate::observer::SyncObserver;
+use serde_json::json;
+use std::io::Write;
+use std::path::Path;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::time::Duration;
+use std::time::Instant;
+
+/// Progress events are throttled to this interval per podcast, except for finished downloads.
+const PROGRESS_INTERVAL: Duration = Duration::from_millis(200);
+
+/// Where the `--progress-fd` events are written, shared between the podcasts.
+#[derive(Clone)]
+pub struct ProgressSink(Arc<Mutex<Box<dyn Write + Send>>>);
+
+impl ProgressSink {
+    pub fn from_fd(fd: i32) -> Result<Self, String> {
+        let writer: Box<dyn Write + Send> = match fd {
+            1 => Box::new(std::io::stdout()),
+            2 => Box::new(std::io::stderr()),
+            fd => Self::open_fd(fd)?,
+        };
+
+        Ok(Self(Arc::new(Mutex::new(writer))))
+    }
+
+    #[cfg(unix)]
+    fn open_fd(fd: i32) -> Result<Box<dyn Write + Send>, String> {
+        use std::os::unix::io::FromRawFd;
+
+        // Checks that the descriptor is open, as the file would otherwise take over whatever
+        // gets opened with that number later.
+        if !Path::new(&format!("/dev/fd/{}", fd)).exists() {
+            return Err(format!("file descriptor {} is not open", fd));
+        }
+
+        // SAFETY: The descriptor is open, and is handed to us by the caller for exclusive use.
+        let file = unsafe { std::fs::File::from_raw_fd(fd) };
+        Ok(Box::new(file))
+    }
+
+    #[cfg(not(unix))]
+    fn open_fd(fd: i32) -> Result<Box<dyn Write + Send>, String> {
+        Err(format!(
+            "file descriptor {} not supported on this platform, use 1 or 2",
+            fd
+        ))
+    }
+
+    fn emit(&self, event: serde_json::Value) {
+        let mut writer = self.0.lock().unwrap();
+        // A frontend that stopped reading shouldn't stop the sync.
+        let _ = writeln!(writer, "{}", event).and_then(|_| writer.flush());
+    }
+}
+
+/// Reports the sync of a podcast as newline-delimited JSON events.
+///
+/// Every event has the `podcast`, `episode`, `phase`, `downloaded` and `total` keys, where
+/// `episode` is null outside of downloads.
+pub struct JsonProgress {
+    sink: ProgressSink,
+    podcast: String,
+    episode: Mutex<Option<String>>,
+    last_progress: Mutex<Option<Instant>>,
+}
+
+impl JsonProgress {
+    pub fn new(podcast: String, sink: ProgressSink) -> Self {
+        Self {
+            sink,
+            podcast,
+            episode: Mutex::new(None),
+            last_progress: Mutex::new(None),
+        }
+    }
+
+    fn emit(&self, phase: &str, downloaded: u64, total: u64, extra: serde_json::Value) {
+        let mut event = json!({
+            "podcast": &self.podcast,
+            "episode": *self.episode.lock().unwrap(),
+            "phase": phase,
+            "downloaded": downloaded,
+            "total": total,
+        });
+
+        if let (Some(event), serde_json::Value::Object(extra)) = (event.as_object_mut(), extra) {
+            event.extend(extra);
+        }
+
+        self.sink.emit(event);
+    }
+
+    fn set_episode(&self, title: Option<&str>) {
+        *self.episode.lock().unwrap() = title.map(ToString::to_string);
+    }
+}
+
+impl SyncObserver for JsonProgress {
+    fn on_feed_loaded(&self, episode_qty: usize) {
+        self.emit("loaded", 0, 0, json!({ "pending": episode_qty }));
+    }
+
+    fn on_downloads_deferred(&self, episode_qty: usize, window_start: &str) {
+        let extra = json!({ "pending": episode_qty, "until": window_start });
+        self.emit("deferred", 0, 0, extra);
+    }
+
+    fn on_offline(&self, episode_qty: usize, fetched: &str) {
+        let extra = json!({ "pending": episode_qty, "fetched": fetched });
+        self.emit("offline", 0, 0, extra);
+    }
+
+    fn on_marked_seen(&self, episode_qty: usize) {
+        self.emit("marked", 0, 0, json!({ "marked": episode_qty }));
+    }
+
+    fn on_episode_start(&self, episode: &Episode, index: usize, episode_qty: usize) {
+        self.set_episode(Some(episode.attrs.title()));
+        *self.last_progress.lock().unwrap() = None;
+        let extra = json!({ "index": index, "pending": episode_qty });
+        self.emit("start", 0, 0, extra);
+    }
+
+    fn on_episode_progress(&self, downloaded: u64, total: u64) {
+        {
+            let mut last = self.last_progress.lock().unwrap();
+            let due = last.is_none_or(|last| last.elapsed() >= PROGRESS_INTERVAL);
+            if !due && downloaded != total {
+                return;
+            }
+            *last = Some(Instant::now());
+        }
+
+        self.emit("progress", downloaded, total, json!({}));
+    }
+
+    fn on_episode_done(&self, _episode: &Episode, path: &Path) {
+        self.emit("done", 0, 0, json!({ "path": path }));
+        self.set_episode(None);
+    }
+
+    fn on_hooks_start(&self) {
+        self.emit("hooks", 0, 0, json!({}));
+    }
+
+    fn on_sync_done(&self) {
+        self.emit("finished", 0, 0, json!({}));
+    }
+
+    fn on_error(&self, msg: &str) {
+        self.emit("error", 0, 0, json!({ "message": msg }));
+        self.set_episode(None);
+    }
+
+    fn episode_observer(&self) -> Option<Box<dyn SyncObserver>> {
+        let podcast = self.podcast.clone();
+        Some(Box::new(JsonProgress::new(podcast, self.sink.clone())))
+    }
+}
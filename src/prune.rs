@@ -0,0 +1,408 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfigs;
+use crate::config::RemovedEpisodePolicy;
+use crate::download_tracker::DownloadedEpisodes;
+use crate::podcast::Podcast;
+use crate::rename;
+use crate::utils;
+use crate::utils::Unix;
+use chrono::Datelike;
+use indicatif::HumanBytes;
+use regex::Regex;
+use std::cmp;
+use std::collections::HashMap;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::UNIX_EPOCH;
+
+/// Folder next to the episodes that `removed_episode_policy = "mark"` moves them into.
+const REMOVED_DIR: &str = "removed";
+
+/// Folder next to the episodes that `archive_after` moves them into, sorted by year.
+const ARCHIVE_DIR: &str = "Archive";
+
+/// A downloaded episode exceeding the retention limit of its podcast.
+struct PrunableFile {
+    id: String,
+    path: PathBuf,
+    size: u64,
+}
+
+/// Lists the downloaded episodes exceeding the `keep` or `delete_after` limit of each podcast,
+/// the ones removed from the feed if `removed_episode_policy` isn't `"keep"`, and the ones
+/// older than `archive_after`.
+///
+/// The files are only deleted, along with their download tracker lines, or moved into the
+/// `removed` or `Archive/{year}` folder if `delete` is true.
+pub async fn prune(global_config: GlobalConfig, filter: Option<Regex>, delete: bool) {
+    let podcasts = PodcastConfigs::load()
+        .assert_not_empty()
+        .filter(filter)
+        .validate_patterns(&global_config);
+    let client = global_config.reqwest_client();
+
+    let mut total_files = 0;
+    let mut total_size = 0;
+    let mut total_marked = 0;
+    let mut total_archived = 0;
+
+    for (name, podcast_config) in podcasts {
+        let keep = podcast_config
+            .keep(&global_config)
+            .map(|keep| keep.max(0) as usize);
+        let max_age = podcast_config.delete_after(&global_config);
+        let policy = podcast_config.removed_episode_policy(&global_config);
+        let archive_after = podcast_config.archive_after(&global_config);
+        if keep.is_none()
+            && max_age.is_none()
+            && policy == RemovedEpisodePolicy::Keep
+            && archive_after.is_none()
+        {
+            continue;
+        }
+
+        let podcast = match Podcast::load_quietly(
+            name.clone(),
+            podcast_config,
+            &global_config,
+            &client,
+        )
+        .await
+        {
+            Ok(podcast) => podcast,
+            Err(e) => {
+                eprintln!("{}: {}", &name, e);
+                continue;
+            }
+        };
+
+        // An empty feed is more likely a broken one than a podcast that removed every episode.
+        if podcast.episodes().is_empty() && policy != RemovedEpisodePolicy::Keep {
+            eprintln!("{}: feed has no episodes, skipping removed episodes", &name);
+            continue;
+        }
+
+        let mut symlinks: HashMap<String, PathBuf> = HashMap::new();
+        for episode in podcast.episodes() {
+            if let Some(symlink) = &episode.config.symlink {
+                symlinks.insert(episode.get_id(), symlink.clone());
+            }
+        }
+
+        for (tracker_path, episodes) in podcast.episodes_by_tracker() {
+            let published: HashMap<String, Unix> = episodes
+                .iter()
+                .map(|episode| (episode.get_id(), episode.attrs.published()))
+                .collect();
+            let delete_removed = policy == RemovedEpisodePolicy::Delete;
+            let prunable = prunable_files(
+                &name,
+                &tracker_path,
+                &published,
+                keep,
+                max_age,
+                delete_removed,
+            );
+
+            for file in &prunable {
+                println!(
+                    "{}: {} ({})",
+                    &name,
+                    file.path.display(),
+                    HumanBytes(file.size)
+                );
+            }
+
+            // Each file is only handled once, deleting takes precedence over moving.
+            let mut handled: HashSet<String> =
+                prunable.iter().map(|file| file.id.clone()).collect();
+
+            if policy == RemovedEpisodePolicy::Mark {
+                let removed: Vec<PrunableFile> = removed_files(&tracker_path, &published)
+                    .into_iter()
+                    .filter(|file| !handled.contains(&file.id))
+                    .collect();
+                handled.extend(removed.iter().map(|file| file.id.clone()));
+
+                for file in &removed {
+                    println!("{}: {} (removed from feed)", &name, file.path.display());
+                }
+
+                total_marked += removed.len();
+                if delete {
+                    mark_files(&tracker_path, removed);
+                }
+            }
+
+            if let Some(archive_after) = archive_after {
+                let archivable: Vec<(i32, PrunableFile)> =
+                    archivable_files(&tracker_path, &published, archive_after)
+                        .into_iter()
+                        .filter(|(_, file)| !handled.contains(&file.id))
+                        .collect();
+
+                for (year, file) in &archivable {
+                    let dir = Path::new(ARCHIVE_DIR).join(year.to_string());
+                    println!("{}: {} ({})", &name, file.path.display(), dir.display());
+                }
+
+                total_archived += archivable.len();
+                if delete {
+                    archive_files(&tracker_path, archivable, &symlinks);
+                }
+            }
+
+            total_files += prunable.len();
+            total_size += prunable.iter().map(|file| file.size).sum::<u64>();
+
+            if delete && !prunable.is_empty() {
+                delete_files(&tracker_path, prunable);
+            }
+        }
+    }
+
+    if delete {
+        eprintln!(
+            "deleted {} episodes, freeing {}",
+            total_files,
+            HumanBytes(total_size)
+        );
+
+        if total_marked > 0 {
+            eprintln!("moved {} removed episodes", total_marked);
+        }
+
+        if total_archived > 0 {
+            eprintln!("archived {} episodes", total_archived);
+        }
+    } else {
+        eprintln!(
+            "{} episodes can be pruned, freeing {}",
+            total_files,
+            HumanBytes(total_size)
+        );
+
+        if total_marked > 0 {
+            eprintln!("{} removed episodes can be moved", total_marked);
+        }
+
+        if total_archived > 0 {
+            eprintln!("{} episodes can be archived", total_archived);
+        }
+
+        if total_files > 0 || total_marked > 0 || total_archived > 0 {
+            eprintln!("run again with --yes to delete them");
+        }
+    }
+}
+
+/// Finds the tracked files beyond the `keep` most recent ones, or older than `max_age`,
+/// as well as the ones no longer in the feed if `delete_removed` is true.
+///
+/// Episodes are dated by publish date, or by modification time for episodes no longer in the feed.
+fn prunable_files(
+    name: &str,
+    tracker_path: &Path,
+    published: &HashMap<String, Unix>,
+    keep: Option<usize>,
+    max_age: Option<Unix>,
+    delete_removed: bool,
+) -> Vec<PrunableFile> {
+    let tracked = DownloadedEpisodes::load(tracker_path);
+
+    let missing_paths = tracked
+        .episodes()
+        .filter(|episode| episode.path.is_none())
+        .count();
+
+    if missing_paths > 0 {
+        eprintln!(
+            "{}: skipping {} episodes downloaded before file paths were tracked",
+            name, missing_paths
+        );
+    }
+
+    let mut files: Vec<(Unix, PrunableFile)> = tracked
+        .episodes()
+        .filter_map(|episode| {
+            let path = episode.path.clone()?;
+            let metadata = fs::metadata(&path).ok()?;
+            let date = episode_date(&episode.id, published, &metadata)?;
+
+            let file = PrunableFile {
+                id: episode.id.clone(),
+                path,
+                size: metadata.len(),
+            };
+
+            Some((date, file))
+        })
+        .collect();
+
+    files.sort_by_key(|(date, _)| cmp::Reverse(*date));
+    let cutoff = max_age.map(|max_age| utils::current_unix().saturating_sub(max_age));
+
+    files
+        .into_iter()
+        .enumerate()
+        .filter(|(index, (date, file))| {
+            keep.is_some_and(|keep| *index >= keep)
+                || cutoff.is_some_and(|cutoff| *date < cutoff)
+                || (delete_removed && !published.contains_key(&file.id))
+        })
+        .map(|(_, (_, file))| file)
+        .collect()
+}
+
+fn delete_files(tracker_path: &Path, files: Vec<PrunableFile>) {
+    let mut ids = HashSet::new();
+
+    for file in files {
+        match fs::remove_file(&file.path) {
+            Ok(()) => {
+                ids.insert(file.id);
+            }
+            Err(e) => eprintln!("failed to delete {:?}: {:?}", &file.path, e),
+        }
+    }
+
+    if let Err(e) = DownloadedEpisodes::remove(tracker_path, &ids) {
+        eprintln!("{}: {:?}", e, tracker_path);
+    }
+}
+
+/// The tracked files of episodes no longer in the feed, except the ones already moved.
+fn removed_files(tracker_path: &Path, published: &HashMap<String, Unix>) -> Vec<PrunableFile> {
+    DownloadedEpisodes::load(tracker_path)
+        .episodes()
+        .filter(|episode| !published.contains_key(&episode.id))
+        .filter_map(|episode| {
+            let path = episode.path.clone()?;
+            let in_removed = path
+                .parent()
+                .and_then(Path::file_name)
+                .is_some_and(|dir| dir == REMOVED_DIR);
+            if in_removed {
+                return None;
+            }
+
+            let size = fs::metadata(&path).ok()?.len();
+            Some(PrunableFile {
+                id: episode.id.clone(),
+                path,
+                size,
+            })
+        })
+        .collect()
+}
+
+fn mark_files(tracker_path: &Path, files: Vec<PrunableFile>) {
+    for file in files {
+        let (Some(dir), Some(file_name)) = (file.path.parent(), file.path.file_name()) else {
+            continue;
+        };
+
+        let new_path = dir.join(REMOVED_DIR).join(file_name);
+        utils::create_dir(&dir.join(REMOVED_DIR));
+        let result = rename::rename_with_sidecars(&file.path, &new_path)
+            .and_then(|_| DownloadedEpisodes::set_path(tracker_path, &file.id, &new_path));
+
+        if let Err(e) = result {
+            eprintln!("failed to move {:?}: {}", &file.path, e);
+        }
+    }
+}
+
+/// The publish date of the episode, or the file's modification time if it's no longer in the feed.
+fn episode_date(
+    id: &str,
+    published: &HashMap<String, Unix>,
+    metadata: &fs::Metadata,
+) -> Option<Unix> {
+    match published.get(id) {
+        Some(date) => Some(*date),
+        None => metadata.modified().ok()?.duration_since(UNIX_EPOCH).ok(),
+    }
+}
+
+/// The tracked files older than `archive_after`, with the year they're archived under.
+/// Files already in an archive folder are skipped.
+fn archivable_files(
+    tracker_path: &Path,
+    published: &HashMap<String, Unix>,
+    archive_after: Unix,
+) -> Vec<(i32, PrunableFile)> {
+    let cutoff = utils::current_unix().saturating_sub(archive_after);
+
+    DownloadedEpisodes::load(tracker_path)
+        .episodes()
+        .filter_map(|episode| {
+            let path = episode.path.clone()?;
+            let archived = path
+                .parent()
+                .and_then(Path::parent)
+                .and_then(Path::file_name)
+                .is_some_and(|dir| dir == ARCHIVE_DIR);
+            if archived {
+                return None;
+            }
+
+            let metadata = fs::metadata(&path).ok()?;
+            let date = episode_date(&episode.id, published, &metadata)?;
+            if date >= cutoff {
+                return None;
+            }
+
+            let year = chrono::DateTime::from_timestamp(date.as_secs() as i64, 0)?.year();
+            let file = PrunableFile {
+                id: episode.id.clone(),
+                path,
+                size: metadata.len(),
+            };
+
+            Some((year, file))
+        })
+        .collect()
+}
+
+/// Moves the files into `Archive/{year}`, updating the tracker and the episodes' symlinks.
+fn archive_files(
+    tracker_path: &Path,
+    files: Vec<(i32, PrunableFile)>,
+    symlinks: &HashMap<String, PathBuf>,
+) {
+    for (year, file) in files {
+        let (Some(dir), Some(file_name)) = (file.path.parent(), file.path.file_name()) else {
+            continue;
+        };
+
+        let archive_dir = dir.join(ARCHIVE_DIR).join(year.to_string());
+        let new_path = archive_dir.join(file_name);
+        utils::create_dir(&archive_dir);
+        let result = rename::rename_with_sidecars(&file.path, &new_path)
+            .and_then(|_| DownloadedEpisodes::set_path(tracker_path, &file.id, &new_path));
+
+        if let Err(e) = result {
+            eprintln!("failed to archive {:?}: {}", &file.path, e);
+            continue;
+        }
+
+        if let Some(symlink_dir) = symlinks.get(&file.id) {
+            relink(&symlink_dir.join(file_name), &file.path, &new_path);
+        }
+    }
+}
+
+/// Points the symlink at the moved file, if it pointed at its old path.
+fn relink(link: &Path, old_path: &Path, new_path: &Path) {
+    if fs::read_link(link).ok().as_deref() != Some(old_path) {
+        return;
+    }
+
+    let result = fs::remove_file(link).and_then(|_| std::os::unix::fs::symlink(new_path, link));
+    if result.is_err() {
+        eprintln!("failed to update symlink {:?}", link);
+    }
+}
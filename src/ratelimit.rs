@@ -0,0 +1,85 @@
+use std::collections::HashMap;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::OnceLock;
+use tokio::sync::Mutex as AsyncMutex;
+use tokio::time::Duration;
+use tokio::time::Instant;
+
+/// Token bucket that limits how many bytes per second are downloaded.
+///
+/// Allows bursts of up to one second worth of bytes.
+pub struct RateLimiter {
+    /// Bytes per second.
+    rate: u64,
+    bucket: AsyncMutex<Bucket>,
+}
+
+struct Bucket {
+    /// Bytes that can be downloaded without waiting, negative if in debt.
+    available: f64,
+    last_refill: Instant,
+}
+
+impl RateLimiter {
+    fn new(rate: u64) -> Self {
+        let rate = rate.max(1);
+        Self {
+            rate,
+            bucket: AsyncMutex::new(Bucket {
+                available: rate as f64,
+                last_refill: Instant::now(),
+            }),
+        }
+    }
+
+    /// Takes `bytes` from the bucket, waiting until they're within the rate.
+    ///
+    /// The lock is held while waiting, so downloads sharing the limiter take turns.
+    pub async fn consume(&self, bytes: usize) {
+        let mut bucket = self.bucket.lock().await;
+        let rate = self.rate as f64;
+
+        let now = Instant::now();
+        let refill = now.duration_since(bucket.last_refill).as_secs_f64() * rate;
+        bucket.available = (bucket.available + refill).min(rate);
+        bucket.last_refill = now;
+
+        bucket.available -= bytes as f64;
+        if bucket.available < 0. {
+            tokio::time::sleep(Duration::from_secs_f64(-bucket.available / rate)).await;
+        }
+    }
+}
+
+/// Limiter shared by the downloads of every podcast.
+static TOTAL: OnceLock<Arc<RateLimiter>> = OnceLock::new();
+
+/// Limiters of the downloads of each podcast, by name.
+static PODCASTS: OnceLock<Mutex<HashMap<String, Arc<RateLimiter>>>> = OnceLock::new();
+
+/// The limiters that an episode download of the podcast `name` has to go through.
+///
+/// The limits of the first download are kept for the rest of the run.
+pub fn limiters(
+    name: &str,
+    podcast_rate: Option<u64>,
+    total_rate: Option<u64>,
+) -> Vec<Arc<RateLimiter>> {
+    let mut limiters = vec![];
+
+    if let Some(rate) = podcast_rate {
+        let mut podcasts = PODCASTS.get_or_init(Default::default).lock().unwrap();
+        let limiter = podcasts
+            .entry(name.to_string())
+            .or_insert_with(|| Arc::new(RateLimiter::new(rate)));
+        limiters.push(Arc::clone(limiter));
+    }
+
+    if let Some(rate) = total_rate {
+        let limiter = TOTAL.get_or_init(|| Arc::new(RateLimiter::new(rate)));
+        limiters.push(Arc::clone(limiter));
+    }
+
+    limiters
+}
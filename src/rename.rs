@@ -0,0 +1,189 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfigs;
+use crate::download_tracker::DownloadedEpisodes;
+use crate::episode::Episode;
+use crate::podcast::Podcast;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Renames the downloaded episodes according to the current `name_pattern`, without
+/// downloading them again.
+///
+/// Episodes are matched to their files through the download tracker. Tracked files of
+/// episodes that are no longer in the feed are left untouched and reported.
+pub async fn rename(global_config: GlobalConfig, filter: Option<Regex>) {
+    let podcasts = PodcastConfigs::load()
+        .assert_not_empty()
+        .filter(filter)
+        .validate_patterns(&global_config);
+    let client = global_config.reqwest_client();
+
+    let mut renamed = 0;
+
+    for (name, podcast_config) in podcasts {
+        let podcast = match Podcast::load_quietly(
+            name.clone(),
+            podcast_config,
+            &global_config,
+            &client,
+        )
+        .await
+        {
+            Ok(podcast) => podcast,
+            Err(e) => {
+                eprintln!("{}: {}", &name, e);
+                continue;
+            }
+        };
+
+        for (tracker_path, episodes) in podcast.episodes_by_tracker() {
+            renamed += rename_tracked(&name, &tracker_path, &episodes);
+        }
+    }
+
+    eprintln!("renamed {} episodes", renamed);
+}
+
+fn rename_tracked(name: &str, tracker_path: &Path, episodes: &[&Episode]) -> usize {
+    let tracked = DownloadedEpisodes::load(tracker_path);
+    let mut renamed = 0;
+
+    for episode in episodes {
+        let id = episode.get_id();
+        let Some(path) = tracked
+            .episodes()
+            .find(|tracked| tracked.id == id)
+            .and_then(|tracked| tracked.path.as_ref())
+        else {
+            continue;
+        };
+
+        if !path.is_file() {
+            eprintln!("{}: file not found, skipping: {}", name, path.display());
+            continue;
+        }
+
+        if episode.config.name_pattern.contains("{download_number") {
+            eprintln!(
+                "{}: can't rename with {{download_number}}, it's only known when downloading",
+                name
+            );
+            return renamed;
+        }
+
+        let new_path = episode.named_path(path, 0);
+        if &new_path == path {
+            continue;
+        }
+
+        if new_path.exists() {
+            eprintln!("{}: already exists, skipping: {}", name, new_path.display());
+            continue;
+        }
+
+        let result = rename_with_sidecars(path, &new_path)
+            .and_then(|_| DownloadedEpisodes::set_path(tracker_path, &id, &new_path));
+
+        match result {
+            Ok(()) => {
+                println!("{}: {} -> {}", name, path.display(), new_path.display());
+                renamed += 1;
+            }
+            Err(e) => eprintln!("{}: {}: {}", name, e, path.display()),
+        }
+    }
+
+    let known: HashSet<String> = episodes.iter().map(|episode| episode.get_id()).collect();
+    for episode in tracked.episodes() {
+        if let Some(path) = episode
+            .path
+            .as_ref()
+            .filter(|_| !known.contains(&episode.id))
+        {
+            match &episode.title {
+                Some(title) => eprintln!(
+                    "{}: not in the feed, left untouched: {:?} {}",
+                    name,
+                    title,
+                    path.display()
+                ),
+                None => eprintln!(
+                    "{}: not in the feed, left untouched: {}",
+                    name,
+                    path.display()
+                ),
+            }
+        }
+    }
+
+    renamed
+}
+
+/// Renames the file along with files next to it with the same stem, like transcripts.
+pub fn rename_with_sidecars(path: &Path, new_path: &Path) -> Result<(), String> {
+    let (Some(stem), Some(new_stem), Some(dir)) =
+        (path.file_stem(), new_path.file_stem(), path.parent())
+    else {
+        return Err("invalid file name".to_string());
+    };
+
+    let sidecars: Vec<PathBuf> = fs::read_dir(dir)
+        .map_err(|_| "failed to read directory".to_string())?
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|sidecar| sidecar != path && sidecar.file_stem() == Some(stem))
+        .collect();
+
+    fs::rename(path, new_path).map_err(|_| "failed to rename episode".to_string())?;
+
+    for sidecar in sidecars {
+        // Not `set_extension`, which would cut a stem like "Ep. 12" at its dot.
+        let new_sidecar = match sidecar.extension() {
+            Some(extension) => new_path.with_file_name(format!(
+                "{}.{}",
+                new_stem.to_string_lossy(),
+                extension.to_string_lossy()
+            )),
+            None => new_path.with_file_name(new_stem),
+        };
+
+        if fs::rename(&sidecar, &new_sidecar).is_err() {
+            eprintln!("failed to rename {}", sidecar.display());
+        }
+    }
+
+    Ok(())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::testing;
+
+    #[test]
+    fn sidecars_keep_dotted_names() {
+        let dir = testing::temp_dir("rename-dotted");
+        fs::write(dir.join("old.mp3"), "audio").unwrap();
+        fs::write(dir.join("old.nfo"), "nfo").unwrap();
+        fs::write(dir.join("old.srt"), "transcript").unwrap();
+
+        rename_with_sidecars(&dir.join("old.mp3"), &dir.join("Ep. 12 Intro.mp3")).unwrap();
+
+        assert_eq!(
+            fs::read_to_string(dir.join("Ep. 12 Intro.mp3")).unwrap(),
+            "audio"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("Ep. 12 Intro.nfo")).unwrap(),
+            "nfo"
+        );
+        assert_eq!(
+            fs::read_to_string(dir.join("Ep. 12 Intro.srt")).unwrap(),
+            "transcript"
+        );
+        assert!(!dir.join("Ep.nfo").exists());
+        assert!(!dir.join("old.nfo").exists());
+    }
+}
@@ -0,0 +1,140 @@
+use crate::config::GlobalConfig;
+use crate::config::PodcastConfigs;
+use crate::download_tracker::DownloadedEpisodes;
+use crate::episode::Episode;
+use crate::podcast::Podcast;
+use crate::tags;
+use lofty::file::TaggedFileExt;
+use lofty::tag::ItemKey;
+use regex::Regex;
+use std::collections::HashSet;
+use std::fs;
+use std::path::Path;
+use std::path::PathBuf;
+
+/// Rebuilds the download trackers from the files on disk, so that episodes whose tracker
+/// lines were lost aren't downloaded again.
+///
+/// Files are matched to the episodes by the name the `name_pattern` gives them, or by the
+/// guid or title in their tags. Media files that match no episode are reported.
+pub async fn repair(global_config: GlobalConfig, filter: Option<Regex>) {
+    let podcasts = PodcastConfigs::load()
+        .assert_not_empty()
+        .filter(filter)
+        .validate_patterns(&global_config);
+    let client = global_config.reqwest_client();
+
+    let mut matched = 0;
+    let mut unmatched = 0;
+
+    for (name, podcast_config) in podcasts.sorted() {
+        let podcast = match Podcast::load_quietly(
+            name.clone(),
+            podcast_config,
+            &global_config,
+            &client,
+        )
+        .await
+        {
+            Ok(podcast) => podcast,
+            Err(e) => {
+                eprintln!("{}: {}", &name, e);
+                continue;
+            }
+        };
+
+        for (tracker_path, episodes) in podcast.episodes_by_tracker() {
+            let (found, leftovers) = repair_tracker(&name, &tracker_path, &episodes);
+            matched += found;
+            unmatched += leftovers.len();
+
+            for path in leftovers {
+                eprintln!("{}: no matching episode: {}", &name, path.display());
+            }
+        }
+    }
+
+    eprintln!("matched {} files, {} unmatched", matched, unmatched);
+}
+
+/// Adds the untracked files in the download directories of the episodes to the tracker.
+///
+/// Returns how many files were matched, and the media files that weren't.
+fn repair_tracker(name: &str, tracker_path: &Path, episodes: &[&Episode]) -> (usize, Vec<PathBuf>) {
+    let tracked = DownloadedEpisodes::load(tracker_path);
+    let tracked_paths: HashSet<&PathBuf> = tracked
+        .episodes()
+        .filter_map(|episode| episode.path.as_ref())
+        .collect();
+
+    let dirs: HashSet<&PathBuf> = episodes
+        .iter()
+        .map(|episode| &episode.config.download_path)
+        .collect();
+    let mut files: Vec<PathBuf> = dirs
+        .into_iter()
+        .filter_map(|dir| fs::read_dir(dir).ok())
+        .flatten()
+        .filter_map(|entry| Some(entry.ok()?.path()))
+        .filter(|path| path.is_file() && is_media(path) && !tracked_paths.contains(path))
+        .collect();
+    files.sort();
+
+    let mut matched = 0;
+    for episode in episodes {
+        let id = episode.get_id();
+        if tracked.contains_episode(&id) {
+            continue;
+        }
+
+        let Some(index) = files.iter().position(|path| matches(episode, path)) else {
+            continue;
+        };
+
+        let path = files.remove(index);
+        match DownloadedEpisodes::append(tracker_path, &id, episode.attrs.title(), Some(&path)) {
+            Ok(()) => {
+                println!(
+                    "{}: {} -> {:?}",
+                    name,
+                    path.display(),
+                    episode.attrs.title()
+                );
+                matched += 1;
+            }
+            Err(e) => eprintln!("{}: {}: {}", name, e, path.display()),
+        }
+    }
+
+    (matched, files)
+}
+
+fn is_media(path: &Path) -> bool {
+    mime_guess::from_path(path)
+        .first()
+        .is_some_and(|mime| matches!(mime.type_().as_str(), "audio" | "video"))
+}
+
+/// Whether the file is named like the episode would be, or is tagged with its guid or title.
+fn matches(episode: &Episode, path: &Path) -> bool {
+    // The download number of a lost tracker line can't be known.
+    if !episode.config.name_pattern.contains("{download_number") {
+        let named = episode.named_path(path, 0);
+        if named.file_stem() == path.file_stem() {
+            return true;
+        }
+    }
+
+    let Ok(Some(file)) = tags::read_file(path) else {
+        return false;
+    };
+    let Some(tag) = file.primary_tag() else {
+        return false;
+    };
+
+    let guid = tag
+        .get_string(&ItemKey::PodcastGlobalUniqueId)
+        .is_some_and(|guid| guid == episode.attrs.guid());
+
+    guid || tag.get_string(&ItemKey::TrackTitle) == Some(episode.attrs.title())
+}
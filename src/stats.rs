@@ -0,0 +1,151 @@
+use crate::utils::Unix;
+use indicatif::HumanBytes;
+use indicatif::HumanDuration;
+use std::fs;
+use std::io::Write;
+use std::path::Path;
+use std::path::PathBuf;
+use std::time::Duration;
+
+/// A downloaded episode file.
+#[derive(Debug)]
+pub struct SyncedFile {
+    pub path: PathBuf,
+    pub published: Unix,
+}
+
+/// Order of the paths printed with `--print`.
+#[derive(Clone, Copy, Debug, Default, clap::ValueEnum)]
+pub enum PrintOrder {
+    /// By publish date of the episodes.
+    Published,
+    /// By podcast name, then publish date.
+    #[default]
+    Name,
+    /// By path.
+    Path,
+}
+
+/// What was downloaded for a single podcast during a sync.
+#[derive(Debug)]
+pub struct PodcastStats {
+    pub name: String,
+    pub files: Vec<SyncedFile>,
+    /// Bytes transferred, excluding previously downloaded parts of resumed episodes.
+    pub bytes: u64,
+    pub elapsed: Duration,
+    /// Downloads stopped because of the `min_free_space` setting.
+    pub low_disk_space: bool,
+    /// The feed couldn't be fetched or parsed.
+    pub fetch_failed: bool,
+}
+
+/// What was downloaded during a sync, across all podcasts.
+#[derive(Debug, Default)]
+pub struct RunStats {
+    pub podcasts: Vec<PodcastStats>,
+    pub elapsed: Duration,
+}
+
+impl RunStats {
+    /// The downloaded paths in the given order, which is the same across runs regardless of
+    /// which podcast finished first.
+    pub fn paths(&self, order: PrintOrder) -> Vec<&Path> {
+        let mut files: Vec<(&str, &SyncedFile)> = self
+            .podcasts
+            .iter()
+            .flat_map(|podcast| {
+                podcast
+                    .files
+                    .iter()
+                    .map(|file| (podcast.name.as_str(), file))
+            })
+            .collect();
+
+        match order {
+            PrintOrder::Published => files.sort_by_key(|(_, file)| (file.published, &file.path)),
+            PrintOrder::Name => files.sort_by_key(|(name, file)| (*name, file.published)),
+            PrintOrder::Path => files.sort_by_key(|(_, file)| &file.path),
+        }
+
+        files
+            .into_iter()
+            .map(|(_, file)| file.path.as_path())
+            .collect()
+    }
+
+    pub fn episode_qty(&self) -> usize {
+        self.podcasts
+            .iter()
+            .map(|podcast| podcast.files.len())
+            .sum()
+    }
+
+    pub fn low_disk_space(&self) -> bool {
+        self.podcasts.iter().any(|podcast| podcast.low_disk_space)
+    }
+
+    pub fn total_bytes(&self) -> u64 {
+        self.podcasts.iter().map(|podcast| podcast.bytes).sum()
+    }
+
+    fn throughput(bytes: u64, elapsed: Duration) -> String {
+        let secs = elapsed.as_secs_f64();
+        if secs == 0. {
+            return "-".to_string();
+        }
+
+        format!("{}/s", HumanBytes((bytes as f64 / secs) as u64))
+    }
+
+    /// Prints the bandwidth and time of the sync, in total and per podcast.
+    pub fn print(&self) {
+        eprintln!(
+            "downloaded {} in {} ({})",
+            HumanBytes(self.total_bytes()),
+            HumanDuration(self.elapsed),
+            Self::throughput(self.total_bytes(), self.elapsed)
+        );
+
+        let mut podcasts: Vec<&PodcastStats> = self
+            .podcasts
+            .iter()
+            .filter(|podcast| !podcast.files.is_empty())
+            .collect();
+        podcasts.sort_by_key(|podcast| std::cmp::Reverse(podcast.bytes));
+
+        for podcast in podcasts {
+            eprintln!(
+                "  {}: {} episodes, {} in {} ({})",
+                &podcast.name,
+                podcast.files.len(),
+                HumanBytes(podcast.bytes),
+                HumanDuration(podcast.elapsed),
+                Self::throughput(podcast.bytes, podcast.elapsed)
+            );
+        }
+    }
+
+    /// Appends a line of `unix_timestamp elapsed_secs episodes bytes` to the file.
+    pub fn append_to_log(&self, path: &Path) -> Result<(), String> {
+        if let Some(parent) = path.parent() {
+            crate::utils::create_dir(parent);
+        }
+
+        let mut file = fs::OpenOptions::new()
+            .append(true)
+            .create(true)
+            .open(path)
+            .map_err(|_| "failed to open stats log".to_string())?;
+
+        writeln!(
+            file,
+            "{} {:.1} {} {}",
+            crate::utils::current_unix().as_secs(),
+            self.elapsed.as_secs_f64(),
+            self.episode_qty(),
+            self.total_bytes()
+        )
+        .map_err(|_| "failed to write to stats log".to_string())
+    }
+}
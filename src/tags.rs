@@ -1,107 +1,474 @@
+use crate::config::Config;
+use crate::config::EvalData;
+use crate::config::TagPreset;
 use crate::display::DownloadBar;
-use crate::episode;
-use crate::podcast::RawPodcast;
+use crate::utils;
 use chrono::Datelike;
-use id3::TagLike;
+use lofty::config::ParseOptions;
+use lofty::config::WriteOptions;
+use lofty::file::AudioFile;
+use lofty::file::TaggedFile;
+use lofty::file::TaggedFileExt;
+use lofty::probe::Probe;
+use lofty::tag::ItemKey;
+use lofty::tag::ItemValue;
+use lofty::tag::Tag;
+use lofty::tag::TagItem;
+use lofty::tag::TagType;
+use std::collections::HashMap;
+use std::path::Path;
+
+/// Tags of an episode, which aren't tied to a tag format.
+///
+/// They're converted to the format of each file when written, so that MP3, M4A, FLAC, Ogg and
+/// Opus files are tagged alike.
+#[derive(Debug, Clone, Default)]
+pub struct Tags {
+    items: Vec<TagItem>,
+}
+
+impl Tags {
+    /// The `tags` setting, or another map of field names to values.
+    pub fn from_fields(fields: &HashMap<String, String>) -> Self {
+        let mut tags = Self::default();
+        for (field, value) in fields {
+            tags.set(item_key(field), value);
+        }
+        tags
+    }
+
+    /// The `id3_tags` setting, keyed by ID3v2 frame IDs.
+    pub fn from_id3_frames(frames: &HashMap<String, String>) -> Self {
+        let mut tags = Self::default();
+        for (id, value) in frames {
+            tags.set(ItemKey::from_key(TagType::Id3v2, id), value);
+        }
+        tags
+    }
+
+    /// All values, in the order they're written.
+    pub fn items(&self) -> &[TagItem] {
+        &self.items
+    }
+
+    /// The first value of the field.
+    pub fn get(&self, key: &ItemKey) -> Option<&str> {
+        self.items
+            .iter()
+            .filter(|item| item.key() == key)
+            .find_map(|item| match item.value() {
+                ItemValue::Text(text) | ItemValue::Locator(text) => Some(text.as_str()),
+                ItemValue::Binary(_) => None,
+            })
+    }
+
+    /// Sets the field, replacing its values.
+    fn set(&mut self, key: ItemKey, value: impl Into<String>) {
+        self.items.retain(|item| item.key() != &key);
+        self.add(key, value);
+    }
+
+    /// Adds a value to the field, for fields with several values like the categories.
+    fn add(&mut self, key: ItemKey, value: impl Into<String>) {
+        self.items
+            .push(TagItem::new(key, ItemValue::Text(value.into())));
+    }
+}
 
 pub async fn extract_tags_from_raw(
-    podcast: &RawPodcast,
-    episode: &episode::Attributes,
+    data: EvalData<'_>,
+    config: &Config,
+    index: usize,
     ui: &DownloadBar,
-) -> Option<id3::Tag> {
-    let mut tags = id3::Tag::new();
+) -> Option<Tags> {
+    let podcast = data.podcast;
+    let episode = data.episode;
+    let mut tags = Tags::default();
 
-    tags.set_title(episode.title());
+    tags.set(ItemKey::TrackTitle, episode.title());
 
     if let Ok(author) = episode.author() {
         ui.log_trace("extracting author tag");
-        tags.set_artist(author);
+        tags.set(ItemKey::TrackArtist, author);
     }
 
-    tags.set_album(podcast.title());
+    tags.set(ItemKey::AlbumTitle, podcast.title());
 
-    tags.set_genre("podcast");
+    tags.set(ItemKey::Genre, "podcast");
 
     if let Ok(episode) = episode.itunes_episode() {
         if let Ok(episode) = episode.parse::<u32>() {
             ui.log_trace("extracting itunes track number");
-            tags.set_track(episode);
+            tags.set(ItemKey::TrackNumber, episode.to_string());
         }
     }
 
-    let year = chrono::DateTime::from_timestamp(episode.published().as_secs() as i64, 0)
-        .unwrap()
-        .year();
-    tags.set_year(year);
-
     if let Some(copyright) = podcast.copyright() {
         ui.log_trace("extracting copyright tag");
-        tags.set_text(Id3Tag::COPYRIGHT, copyright);
+        tags.set(ItemKey::CopyrightMessage, copyright);
     }
 
     if let Ok(desc) = episode.description() {
         ui.log_trace("extracting description tag");
-        tags.set_text(Id3Tag::DESCRIPTION, desc);
+        tags.set(ItemKey::PodcastDescription, desc);
     }
 
-    let mut strs = vec![];
-    for cat in podcast.categories() {
-        strs.push(cat);
+    if config.write_description {
+        if let Some(comment) = description_comment(data) {
+            ui.log_trace("extracting description comment");
+            tags.items.push(comment);
+        }
     }
 
-    if !strs.is_empty() {
+    let categories = podcast.categories();
+    if !categories.is_empty() {
         ui.log_trace("extracting podcast categories tag");
-        tags.set_text_values(Id3Tag::PODCASTCATEGORY, strs);
-    }
-
-    use chrono::TimeZone;
-    use chrono::Timelike;
-    let datetime = chrono::Utc
-        .timestamp_opt(episode.published().as_secs() as i64, 0)
-        .unwrap();
-
-    let ts = id3::frame::Timestamp {
-        year: datetime.year(),
-        month: Some(datetime.month() as u8),
-        day: Some(datetime.day() as u8),
-        hour: Some(datetime.hour() as u8),
-        minute: Some(datetime.minute() as u8),
-        second: Some(datetime.second() as u8),
-    };
+        for category in categories {
+            tags.add(ItemKey::PodcastSeriesCategory, category);
+        }
+    }
 
-    tags.set_date_released(ts);
+    // The recording date holds the year for formats without a year field.
+    let datetime = episode.published_at();
+    tags.set(
+        ItemKey::RecordingDate,
+        format!(
+            "{:04}-{:02}-{:02}",
+            datetime.year(),
+            datetime.month(),
+            datetime.day()
+        ),
+    );
+    tags.set(
+        ItemKey::ReleaseDate,
+        datetime.format("%Y-%m-%dT%H:%M:%S").to_string(),
+    );
 
     if let Some(language) = podcast.language() {
         ui.log_trace("extracting language tag");
-        tags.set_text(Id3Tag::LANGUAGE, language);
+        tags.set(ItemKey::Language, language);
     }
 
-    if let Ok(dur) = episode.itunes_duration() {
-        if let Ok(secs) = dur.parse::<u32>() {
-            ui.log_trace("extracting itunes duration tag");
-            let millis = secs * 1000;
-            tags.set_text(Id3Tag::DURATION, millis.to_string());
-        }
+    // Accepts `HH:MM:SS` durations, and falls back to the duration of `media:content`.
+    if let Some(duration) = episode.duration() {
+        ui.log_trace("extracting itunes duration tag");
+        tags.set(ItemKey::Length, duration.as_millis().to_string());
     }
 
     if let Some(author) = podcast.author() {
         ui.log_trace("extracting publisher tag");
-        tags.set_text(Id3Tag::PUBLISHER, author);
+        tags.set(ItemKey::Publisher, author);
     }
 
-    tags.set_text(Id3Tag::PODCAST_ID, episode.guid());
+    tags.set(ItemKey::PodcastGlobalUniqueId, episode.guid());
+
+    if let Some(explicit) = episode.explicit {
+        ui.log_trace("extracting content rating");
+        // The values iTunes uses, 1 for explicit and 2 for clean.
+        let rating = if explicit { "1" } else { "2" };
+        tags.set(ItemKey::ParentalAdvisory, rating);
+    }
+
+    if let Some(link) = episode.link() {
+        ui.log_trace("extracting episode link");
+        let value = ItemValue::Locator(link.to_string());
+        tags.items.push(TagItem::new(ItemKey::AudioFileUrl, value));
+    }
+
+    if let Some(preset) = config.tag_preset {
+        apply_preset(&mut tags, preset, data, index, ui);
+    }
 
     Some(tags)
 }
 
-struct Id3Tag;
+/// Maximum length of the description comment, in characters.
+const MAX_COMMENT_LEN: usize = 4000;
+
+/// Comment with the plain text show notes, falling back to the podcast's description.
+fn description_comment(data: EvalData<'_>) -> Option<TagItem> {
+    let html = data
+        .episode
+        .description()
+        .or_else(|_| data.episode.content_encoded())
+        .ok()
+        .or_else(|| data.podcast.get_str("description"))?;
+
+    let mut text = utils::strip_html(html);
+    if text.is_empty() {
+        return None;
+    }
+
+    if text.chars().count() > MAX_COMMENT_LEN {
+        text = text.chars().take(MAX_COMMENT_LEN - 3).collect::<String>() + "...";
+    }
+
+    let mut comment = TagItem::new(ItemKey::Comment, ItemValue::Text(text));
+    comment.set_lang(*b"eng");
+    Some(comment)
+}
+
+fn apply_preset(
+    tags: &mut Tags,
+    preset: TagPreset,
+    data: EvalData<'_>,
+    index: usize,
+    ui: &DownloadBar,
+) {
+    match preset {
+        TagPreset::Audiobook => {
+            ui.log_trace("applying audiobook tag preset");
+            tags.set(ItemKey::AlbumTitle, data.pod_name);
+            tags.set(
+                ItemKey::AlbumArtist,
+                data.podcast.author().unwrap_or(data.pod_name),
+            );
+
+            let track = data
+                .episode
+                .itunes_episode()
+                .ok()
+                .and_then(|episode| episode.parse::<u32>().ok())
+                .unwrap_or(index as u32 + 1);
+            tags.set(ItemKey::TrackNumber, track.to_string());
+
+            if let Some(season) = data
+                .episode
+                .itunes_season()
+                .ok()
+                .and_then(|season| season.parse::<u32>().ok())
+            {
+                tags.set(ItemKey::DiscNumber, season.to_string());
+            }
+        }
+    }
+}
+
+/// Format-agnostic tag fields, with their key in lofty.
+const FIELDS: &[(&str, ItemKey)] = &[
+    ("title", ItemKey::TrackTitle),
+    ("artist", ItemKey::TrackArtist),
+    ("album", ItemKey::AlbumTitle),
+    ("album_artist", ItemKey::AlbumArtist),
+    ("composer", ItemKey::Composer),
+    ("genre", ItemKey::Genre),
+    ("date", ItemKey::ReleaseDate),
+    ("year", ItemKey::RecordingDate),
+    ("track", ItemKey::TrackNumber),
+    ("disc", ItemKey::DiscNumber),
+    ("description", ItemKey::PodcastDescription),
+    ("copyright", ItemKey::CopyrightMessage),
+    ("language", ItemKey::Language),
+    ("publisher", ItemKey::Publisher),
+];
+
+/// The key of a tag field: a generic field name, or the key of a format like the ID3v2 frame
+/// `TIT3` or the Vorbis comment `ORGANIZATION`.
+///
+/// Other keys are written as they are, to the formats that allow them.
+pub fn item_key(field: &str) -> ItemKey {
+    if let Some((_, key)) = FIELDS
+        .iter()
+        .find(|(name, _)| field.eq_ignore_ascii_case(name))
+    {
+        return key.clone();
+    }
+
+    [TagType::Id3v2, TagType::VorbisComments, TagType::Mp4Ilst]
+        .into_iter()
+        .map(|tag_type| ItemKey::from_key(tag_type, field))
+        .find(|key| !matches!(key, ItemKey::Unknown(_)))
+        .unwrap_or_else(|| ItemKey::Unknown(field.to_string()))
+}
+
+/// The value the episode is tagged with for `field`, for the `{tag::<field>}` pattern.
+///
+/// The settings take precedence over the tags from the feed, like when the file is tagged.
+pub fn lookup(tags: Option<&Tags>, config: &Config, field: &str) -> Option<String> {
+    let key = item_key(field);
+    let setting = |fields: &HashMap<String, String>| {
+        fields
+            .iter()
+            .find(|(name, _)| item_key(name) == key)
+            .map(|(_, value)| value.clone())
+    };
+
+    setting(&config.tag_overrides)
+        .or_else(|| setting(&config.id3_tags))
+        .or_else(|| setting(&config.tags))
+        .or_else(|| tags?.get(&key).map(ToString::to_string))
+}
+
+/// Reads the tags of an audio file, or `None` if lofty doesn't support its format.
+pub fn read_file(path: &Path) -> Result<Option<TaggedFile>, String> {
+    let probe = Probe::open(path)
+        .and_then(|probe| Ok(probe.guess_file_type()?))
+        .map_err(|e| e.to_string())?
+        .options(ParseOptions::new().read_properties(false));
+    if probe.file_type().is_none() {
+        return Ok(None);
+    }
+
+    probe.read().map(Some).map_err(|e| e.to_string())
+}
 
-impl Id3Tag {
-    const COPYRIGHT: &'static str = "TCOP";
-    const DESCRIPTION: &'static str = "TDES";
-    const PODCASTCATEGORY: &'static str = "TCAT";
-    const LANGUAGE: &'static str = "TLAN";
-    const DURATION: &'static str = "TLEN";
-    const PUBLISHER: &'static str = "TPUB";
-    const PODCAST_ID: &'static str = "TGID";
+/// The main tag of the file, in the format of its container. Created if the file has none.
+pub fn primary_tag(file: &mut TaggedFile) -> &mut Tag {
+    let tag_type = file.primary_tag_type();
+    if file.tag(tag_type).is_none() {
+        file.insert_tag(Tag::new(tag_type));
+    }
+
+    file.tag_mut(tag_type).unwrap()
+}
+
+/// Writes the tags to the tag of a file. Fields that the file already has values for are
+/// kept, unless `overwrite` is true.
+pub fn write_to(tag: &mut Tag, tags: &Tags, overwrite: bool) {
+    let mut written: Vec<ItemKey> = vec![];
+    for item in tags.items() {
+        let Some(item) = for_format(item, tag.tag_type()) else {
+            continue;
+        };
+
+        let key = item.key().clone();
+        if !written.contains(&key) {
+            if !overwrite && tag.get(&key).is_some() {
+                continue;
+            }
+            tag.remove_key(&key);
+            written.push(key);
+        }
+
+        tag.push_unchecked(item);
+    }
+}
+
+/// The item with the key of the tag format, or `None` if the format has no key for it.
+///
+/// Vorbis comments and APE tags allow any key, so fields without a standard key are written
+/// with the one players commonly read.
+fn for_format(item: &TagItem, tag_type: TagType) -> Option<TagItem> {
+    let key = item.key();
+    if matches!(key, ItemKey::Unknown(_)) || key.map_key(tag_type, false).is_some() {
+        return Some(item.clone());
+    }
+
+    if !matches!(tag_type, TagType::VorbisComments | TagType::Ape) {
+        return None;
+    }
+
+    let key = match key {
+        ItemKey::PodcastDescription => "DESCRIPTION",
+        ItemKey::ParentalAdvisory => "ITUNESADVISORY",
+        ItemKey::AudioFileUrl => "WEBSITE",
+        _ => return None,
+    };
+    let value = match item.value() {
+        ItemValue::Locator(text) => ItemValue::Text(text.clone()),
+        value => value.clone(),
+    };
+
+    Some(TagItem::new(ItemKey::Unknown(key.to_string()), value))
+}
+
+/// Saves the tags of the file in place, leaving the audio as it is.
+pub fn save_file(file: &TaggedFile, path: &Path) -> Result<(), String> {
+    file.save_to_path(path, WriteOptions::default())
+        .map_err(|e| e.to_string())
+}
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+    use crate::patterns;
+    use crate::testing;
+
+    fn fields(pairs: &[(&str, &str)]) -> HashMap<String, String> {
+        pairs
+            .iter()
+            .map(|(field, value)| (field.to_string(), value.to_string()))
+            .collect()
+    }
+
+    fn title_of(path: &Path) -> Option<String> {
+        let mut file = read_file(path).unwrap().unwrap();
+        primary_tag(&mut file)
+            .get_string(&ItemKey::TrackTitle)
+            .map(ToString::to_string)
+    }
+
+    fn tag_file(path: &Path, tags: &Tags, overwrite: bool) {
+        let mut file = read_file(path).unwrap().unwrap();
+        write_to(primary_tag(&mut file), tags, overwrite);
+        save_file(&file, path).unwrap();
+    }
+
+    #[test]
+    fn existing_values_are_kept_unless_overwritten() {
+        let path = testing::temp_dir("tags-overwrite").join("episode.mp3");
+        std::fs::write(&path, testing::SILENT_MP3).unwrap();
+
+        tag_file(
+            &path,
+            &Tags::from_fields(&fields(&[("title", "First")])),
+            false,
+        );
+        assert_eq!(title_of(&path).as_deref(), Some("First"));
+
+        let other = Tags::from_id3_frames(&fields(&[("TIT2", "Second")]));
+        tag_file(&path, &other, false);
+        assert_eq!(title_of(&path).as_deref(), Some("First"));
+
+        tag_file(&path, &other, true);
+        assert_eq!(title_of(&path).as_deref(), Some("Second"));
+        assert!(std::fs::read(&path)
+            .unwrap()
+            .ends_with(&testing::SILENT_MP3));
+    }
+
+    #[test]
+    fn generic_and_format_keys_are_the_same_field() {
+        assert_eq!(item_key("title"), ItemKey::TrackTitle);
+        assert_eq!(item_key("TIT2"), ItemKey::TrackTitle);
+        assert_eq!(item_key("TITLE"), ItemKey::TrackTitle);
+        assert_eq!(item_key("TIT3"), ItemKey::TrackSubtitle);
+        assert_eq!(item_key("MOOD"), ItemKey::Mood);
+        assert_eq!(item_key("FOO"), ItemKey::Unknown("FOO".to_string()));
+    }
+
+    #[test]
+    fn podcast_fields_get_a_vorbis_comment() {
+        let mut tags = Tags::default();
+        tags.set(ItemKey::PodcastDescription, "About the episode");
+        tags.set(ItemKey::ParentalAdvisory, "1");
+
+        let mut tag = Tag::new(TagType::VorbisComments);
+        write_to(&mut tag, &tags, true);
+
+        let get = |key: &str| tag.get_string(&ItemKey::Unknown(key.to_string()));
+        assert_eq!(get("DESCRIPTION"), Some("About the episode"));
+        assert_eq!(get("ITUNESADVISORY"), Some("1"));
+    }
+
+    #[test]
+    fn tag_pattern_uses_settings_before_feed_tags() {
+        let mut tags = Tags::default();
+        tags.set(ItemKey::TrackTitle, "From the feed");
+        tags.set(ItemKey::AlbumTitle, "The Podcast");
+
+        let config = Config {
+            tags: fields(&[("album", "From the settings")]),
+            ..Default::default()
+        };
+        let eval =
+            |pattern| patterns::eval_tags(pattern, |field| lookup(Some(&tags), &config, field));
+
+        assert_eq!(
+            eval("{tag::title} - {tag::album}"),
+            "From the feed - From the settings"
+        );
+        assert_eq!(eval("{id3::TIT2}"), "From the feed");
+        assert_eq!(eval("{tag::composer}"), "<value not found>");
+    }
 }
@@ -0,0 +1,196 @@
+//! Helpers for tests that need a server or files on disk.
+
+use crate::observer::SyncObserver;
+use std::collections::HashMap;
+use std::io::BufRead;
+use std::io::BufReader;
+use std::io::Write;
+use std::net::TcpListener;
+use std::net::TcpStream;
+use std::path::PathBuf;
+use std::sync::atomic::AtomicUsize;
+use std::sync::atomic::Ordering;
+use std::sync::Arc;
+use std::sync::Mutex;
+use std::sync::Once;
+
+/// A request received by a [`TestServer`].
+#[derive(Debug, Clone)]
+pub struct Request {
+    pub method: String,
+    pub path: String,
+    /// Header names are lowercased.
+    pub headers: HashMap<String, String>,
+}
+
+impl Request {
+    pub fn header(&self, name: &str) -> Option<&str> {
+        self.headers.get(&name.to_lowercase()).map(String::as_str)
+    }
+}
+
+/// A response sent by a [`TestServer`].
+pub struct Response {
+    status: u16,
+    headers: Vec<(String, String)>,
+    body: Vec<u8>,
+}
+
+impl Response {
+    pub fn new(status: u16, body: impl Into<Vec<u8>>) -> Self {
+        Self {
+            status,
+            headers: vec![],
+            body: body.into(),
+        }
+    }
+
+    pub fn header(mut self, name: &str, value: &str) -> Self {
+        self.headers.push((name.to_string(), value.to_string()));
+        self
+    }
+
+    fn write_to(&self, stream: &mut TcpStream) -> std::io::Result<()> {
+        let mut head = format!("HTTP/1.1 {} Test\r\n", self.status);
+        for (name, value) in &self.headers {
+            head.push_str(&format!("{}: {}\r\n", name, value));
+        }
+        head.push_str(&format!("Content-Length: {}\r\n", self.body.len()));
+        head.push_str("Connection: close\r\n\r\n");
+
+        stream.write_all(head.as_bytes())?;
+        stream.write_all(&self.body)?;
+        stream.flush()
+    }
+}
+
+/// HTTP server on a local port that answers every request with the response of a handler.
+///
+/// Runs until the test process exits.
+pub struct TestServer {
+    port: u16,
+    requests: Arc<Mutex<Vec<Request>>>,
+}
+
+impl TestServer {
+    pub fn start<F>(handler: F) -> Self
+    where
+        F: Fn(&Request) -> Response + Send + 'static,
+    {
+        let listener = TcpListener::bind("127.0.0.1:0").unwrap();
+        let port = listener.local_addr().unwrap().port();
+        let requests = Arc::new(Mutex::new(vec![]));
+
+        let received = Arc::clone(&requests);
+        std::thread::spawn(move || {
+            for stream in listener.incoming() {
+                let Ok(mut stream) = stream else {
+                    continue;
+                };
+
+                let Some(request) = read_request(&stream) else {
+                    continue;
+                };
+
+                let response = handler(&request);
+                received.lock().unwrap().push(request);
+                let _ = response.write_to(&mut stream);
+            }
+        });
+
+        Self { port, requests }
+    }
+
+    /// Full url of `path` on the server.
+    pub fn url(&self, path: &str) -> String {
+        format!("http://127.0.0.1:{}{}", self.port, path)
+    }
+
+    /// The requests received so far, in order.
+    pub fn requests(&self) -> Vec<Request> {
+        self.requests.lock().unwrap().clone()
+    }
+}
+
+/// Reads the request line and headers. The body isn't needed by any test.
+fn read_request(stream: &TcpStream) -> Option<Request> {
+    let mut reader = BufReader::new(stream);
+    let mut line = String::new();
+    reader.read_line(&mut line).ok()?;
+
+    let mut parts = line.split_whitespace();
+    let method = parts.next()?.to_string();
+    let path = parts.next()?.to_string();
+
+    let mut headers = HashMap::new();
+    loop {
+        let mut line = String::new();
+        reader.read_line(&mut line).ok()?;
+        let line = line.trim_end();
+        if line.is_empty() {
+            break;
+        }
+
+        if let Some((name, value)) = line.split_once(':') {
+            headers.insert(name.trim().to_lowercase(), value.trim().to_string());
+        }
+    }
+
+    Some(Request {
+        method,
+        path,
+        headers,
+    })
+}
+
+/// Three frames of silence, the smallest file that's read as an mp3 when tagging.
+pub const SILENT_MP3: [u8; 3 * 417] = silent_mp3();
+
+const fn silent_mp3() -> [u8; 3 * 417] {
+    let mut data = [0; 3 * 417];
+    let mut frame = 0;
+    while frame < 3 {
+        // MPEG-1 layer 3, 128 kbps, 44.1 kHz.
+        data[frame * 417] = 0xFF;
+        data[frame * 417 + 1] = 0xFB;
+        data[frame * 417 + 2] = 0x90;
+        frame += 1;
+    }
+    data
+}
+
+/// Observer for tests that don't check the progress.
+pub struct NoProgress;
+
+impl SyncObserver for NoProgress {}
+
+/// Client for tests, which doesn't route the local server through a proxy from the environment.
+pub fn client() -> reqwest::Client {
+    reqwest::Client::builder().no_proxy().build().unwrap()
+}
+
+/// Points the state, cache and config directories to a temporary directory, so that tests
+/// which sync podcasts don't touch the ones of the user.
+pub fn isolate_app_dirs() {
+    static ISOLATED: Once = Once::new();
+    ISOLATED.call_once(|| {
+        let dir = temp_dir("app-dirs");
+        std::env::set_var("XDG_STATE_HOME", dir.join("state"));
+        std::env::set_var("XDG_CACHE_HOME", dir.join("cache"));
+        std::env::set_var("XDG_CONFIG_HOME", dir.join("config"));
+    });
+}
+
+/// A new empty directory for the test `name`.
+pub fn temp_dir(name: &str) -> PathBuf {
+    static COUNTER: AtomicUsize = AtomicUsize::new(0);
+    let dir = std::env::temp_dir().join(format!(
+        "talecast-test-{}-{}-{}",
+        name,
+        std::process::id(),
+        COUNTER.fetch_add(1, Ordering::Relaxed)
+    ));
+    let _ = std::fs::remove_dir_all(&dir);
+    std::fs::create_dir_all(&dir).unwrap();
+    dir
+}
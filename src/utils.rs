@@ -11,13 +11,16 @@ use std::io::{BufRead, BufReader};
 use std::path::Path;
 use std::path::PathBuf;
 use std::process;
+use std::sync::Mutex;
 use std::time;
 
 pub type Unix = std::time::Duration;
 
 #[allow(dead_code)]
 pub fn log<S: AsRef<str>>(message: S) {
-    let log_file_path = default_download_path().join("logfile");
+    let Some(log_file_path) = default_download_path().map(|path| path.join("logfile")) else {
+        return;
+    };
     let mut file = std::fs::OpenOptions::new()
         .create(true)
         .append(true)
@@ -26,12 +29,39 @@ pub fn log<S: AsRef<str>>(message: S) {
     writeln!(file, "{}", message.as_ref()).unwrap();
 }
 
+/// The home directory, which can be overridden with the `TALECAST_HOME` environment variable.
+pub fn home_dir() -> Option<PathBuf> {
+    match std::env::var_os("TALECAST_HOME") {
+        Some(path) => Some(PathBuf::from(path)),
+        None => dirs::home_dir(),
+    }
+}
+
 pub fn config_dir() -> PathBuf {
     let path = match std::env::var("XDG_CONFIG_HOME") {
         Ok(path) => PathBuf::from(path),
-        Err(_) => dirs::home_dir()
-            .expect("unable to locate home directory. Try setting 'XDG_CONFIG_HOME' manually")
-            .join(".config"),
+        Err(_) => {
+            // Earlier versions used `~/.config` on every platform, which is kept if it's in use.
+            let legacy = home_dir().map(|home| home.join(".config"));
+            let use_legacy = std::env::var_os("TALECAST_HOME").is_some()
+                || legacy
+                    .as_ref()
+                    .is_some_and(|legacy| legacy.join(crate::APPNAME).exists());
+
+            let dir = match use_legacy {
+                true => legacy,
+                false => dirs::config_dir().or(legacy),
+            };
+
+            match dir {
+                Some(dir) => dir,
+                None => {
+                    eprintln!("unable to locate home directory.");
+                    eprintln!("set 'TALECAST_HOME' or 'XDG_CONFIG_HOME' to where the config files should be stored.");
+                    process::exit(1);
+                }
+            }
+        }
     }
     .join(crate::APPNAME);
 
@@ -40,11 +70,58 @@ pub fn config_dir() -> PathBuf {
     path
 }
 
+/// Config files that were found in both formats, and have been warned about.
+static WARNED_DUPLICATES: Mutex<Vec<String>> = Mutex::new(Vec::new());
+
+/// The config file with the given name, written in either TOML or YAML.
+///
+/// If both exist, the TOML file is used. If neither exist, it's the path of the TOML file.
+pub fn config_file(name: &str) -> PathBuf {
+    let dir = config_dir();
+    let toml = dir.join(format!("{}.toml", name));
+    let yaml = ["yaml", "yml"]
+        .iter()
+        .map(|ext| dir.join(format!("{}.{}", name, ext)))
+        .find(|path| path.exists());
+
+    match yaml {
+        Some(yaml) if toml.exists() => {
+            let mut warned = WARNED_DUPLICATES.lock().unwrap();
+            if !warned.iter().any(|warned| warned == name) {
+                eprintln!(
+                    "warning: both {:?} and {:?} exist, using {:?}",
+                    &toml, &yaml, &toml
+                );
+                warned.push(name.to_owned());
+            }
+            toml
+        }
+        Some(yaml) => yaml,
+        None => toml,
+    }
+}
+
 pub fn cache_dir() -> PathBuf {
     let path = match std::env::var("XDG_CACHE_HOME") {
         Ok(path) => PathBuf::from(path),
         Err(_) => dirs::cache_dir()
-            .expect("unable to locate cache directory. Try setting 'XDG_CACHE_HOME' manually"),
+            .or_else(|| Some(home_dir()?.join(".cache")))
+            .unwrap_or_else(std::env::temp_dir),
+    }
+    .join(crate::APPNAME);
+
+    utils::create_dir(&path);
+
+    path
+}
+
+/// Directory for data that should persist between runs, but isn't configuration.
+pub fn state_dir() -> PathBuf {
+    let path = match std::env::var("XDG_STATE_HOME") {
+        Ok(path) => PathBuf::from(path),
+        Err(_) => dirs::state_dir()
+            .or_else(|| Some(home_dir()?.join(".local").join("state")))
+            .unwrap_or_else(std::env::temp_dir),
     }
     .join(crate::APPNAME);
 
@@ -58,12 +135,73 @@ pub fn current_unix() -> Unix {
     Unix::from_secs(secs)
 }
 
-pub fn default_download_path() -> PathBuf {
-    let path = dirs::home_dir()
-        .expect("unable to load home directory. Try hardcoding the download path in settings.")
-        .join(crate::APPNAME);
-    utils::create_dir(&path);
-    path
+/// Whether the system clock is before this release could have been built, which
+/// happens on devices without a real-time clock that haven't synced the time yet.
+pub fn clock_seems_unset() -> bool {
+    // 2024-01-01
+    current_unix() < Unix::from_secs(1_704_067_200)
+}
+
+/// The `talecast` folder in the home directory, if there is one and it can be created.
+pub fn default_download_path() -> Option<PathBuf> {
+    let path = home_dir()?.join(crate::APPNAME);
+    try_create_dir(&path).ok()?;
+    Some(path)
+}
+
+/// Converts HTML to plain text, keeping line breaks between paragraphs.
+pub fn strip_html(html: &str) -> String {
+    let breaks = Regex::new(r"(?i)<br\s*/?>|</p>|</li>|</h\d>").unwrap();
+    let tags = Regex::new(r"<[^>]*>").unwrap();
+    let spaces = Regex::new(r"[ \t]+").unwrap();
+    let blank_lines = Regex::new(r"\n\s*\n\s*").unwrap();
+
+    let text = breaks.replace_all(html, "\n");
+    let text = tags.replace_all(&text, "");
+    let text = text
+        .replace("&nbsp;", " ")
+        .replace("&lt;", "<")
+        .replace("&gt;", ">")
+        .replace("&quot;", "\"")
+        .replace("&#39;", "'")
+        .replace("&apos;", "'")
+        .replace("&amp;", "&");
+    let text = spaces.replace_all(&text, " ");
+    let text = blank_lines.replace_all(&text, "\n\n");
+
+    text.trim().to_string()
+}
+
+/// Joins the name and extension, truncating the name to fit both the file name limit and the
+/// path limit in the directory. Lengths are in bytes, like filesystem limits.
+pub fn fit_file_name(
+    name: &str,
+    extension: Option<&str>,
+    dir: &Path,
+    max_file_len: usize,
+    max_path_len: usize,
+) -> String {
+    let ext_len = extension.map_or(0, |ext| ext.len() + 1); // + 1 for the dot.
+    let dir_len = dir.as_os_str().len() + 1; // + 1 for the separator.
+    let budget = max_file_len
+        .min(max_path_len.saturating_sub(dir_len))
+        .saturating_sub(ext_len);
+
+    let mut end = name.len().min(budget);
+    while !name.is_char_boundary(end) {
+        end -= 1;
+    }
+
+    // An empty name would be a hidden file or no file at all.
+    let name = match name[..end].trim_end() {
+        "" => name.chars().next().map(String::from).unwrap_or_default(),
+        truncated => truncated.to_string(),
+    };
+
+    match extension {
+        Some(ext) => format!("{}.{}", name, ext),
+        None => name,
+    }
 }
 
 pub fn truncate_string(s: &str, max_width: usize, append_dots: bool) -> String {
@@ -94,6 +232,23 @@ pub fn truncate_string(s: &str, max_width: usize, append_dots: bool) -> String {
     truncated
 }
 
+/// The Levenshtein distance between `a` and `b`, in characters.
+pub fn edit_distance(a: &str, b: &str) -> usize {
+    let b: Vec<char> = b.chars().collect();
+    let mut prev: Vec<usize> = (0..=b.len()).collect();
+
+    for (i, ca) in a.chars().enumerate() {
+        let mut row = vec![i + 1];
+        for (j, cb) in b.iter().enumerate() {
+            let substitution = prev[j] + usize::from(ca != *cb);
+            row.push(substitution.min(prev[j + 1] + 1).min(row[j] + 1));
+        }
+        prev = row;
+    }
+
+    prev[b.len()]
+}
+
 pub fn short_handle_response(
     response: Result<reqwest::Response, reqwest::Error>,
 ) -> Result<reqwest::Response, String> {
@@ -144,18 +299,28 @@ use futures_util::StreamExt;
 pub async fn download_text(
     client: &reqwest::Client,
     url: &str,
+    request: &config::RequestSettings,
     ui: &DownloadBar,
-) -> Option<String> {
+) -> Result<String, String> {
     ui.log_info("downloading podcast xml");
-    let response = match client.get(url).send().await {
+    let response = match request.apply(client.get(url), url).send().await {
         Ok(res) => res,
         Err(e) => {
             ui.log_error(&format!("connection failure: {:?}", e));
-            return None;
+            return Err("failed to connect to the feed".to_string());
         }
     };
 
+    if !response.status().is_success() {
+        return Err(feed_status_error(response.status()));
+    }
+
     let total_size = response.content_length().unwrap_or(0);
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|val| val.to_str().ok())
+        .map(ToString::to_string);
 
     let mut downloaded = 0;
     let mut stream = response.bytes_stream();
@@ -163,27 +328,99 @@ pub async fn download_text(
     ui.init_download_bar(downloaded, total_size);
     let mut buffer: Vec<u8> = vec![];
     while let Some(item) = stream.next().await {
-        let chunk = item.ok()?;
+        let chunk = item.map_err(|_| "feed download was interrupted".to_string())?;
         buffer.extend(&chunk);
         downloaded = std::cmp::min(downloaded + (chunk.len() as u64), total_size);
         ui.set_progress(downloaded);
     }
 
-    match String::from_utf8(buffer) {
-        Ok(s) => Some(s),
-        Err(e) => {
-            ui.log_error(&format!("failed to decode xml: {:?}", e));
-            None
+    Ok(decode_xml(&buffer, content_type.as_deref(), ui))
+}
+
+/// Describes an unsuccessful feed response, with hints for feeds blocked in some regions.
+fn feed_status_error(status: reqwest::StatusCode) -> String {
+    let reason = match status.as_u16() {
+        451 => "the feed is unavailable for legal reasons, likely in your region",
+        403 => "access was denied, the feed may need credentials or be blocked in your region",
+        _ => return format!("failed to download feed: HTTP {}", status),
+    };
+
+    format!(
+        "failed to download feed: HTTP {}, {}. To sync it through a proxy in another region, set the HTTPS_PROXY environment variable",
+        status, reason
+    )
+}
+
+/// Decodes an XML document with the encoding it declares.
+///
+/// The byte order mark comes first, then the XML declaration, then the charset of the
+/// `Content-Type` header, since servers often send a default charset regardless of the file.
+/// A declared encoding is skipped if the bytes aren't valid in it, falling back to UTF-8.
+fn decode_xml(bytes: &[u8], content_type: Option<&str>, ui: &DownloadBar) -> String {
+    use encoding_rs::Encoding;
+
+    if let Some((encoding, bom_len)) = Encoding::for_bom(bytes) {
+        return encoding
+            .decode_without_bom_handling(&bytes[bom_len..])
+            .0
+            .into_owned();
+    }
+
+    let declared = xml_declared_encoding(bytes);
+    let header = content_type.and_then(|content_type| {
+        content_type
+            .split(';')
+            .filter_map(|param| param.trim().split_once('='))
+            .find(|(key, _)| key.trim().eq_ignore_ascii_case("charset"))
+            .map(|(_, charset)| charset.trim().trim_matches('"').to_string())
+    });
+
+    for label in declared.iter().chain(header.iter()) {
+        let Some(encoding) = Encoding::for_label(label.as_bytes()) else {
+            ui.log_warn(format!("unknown feed encoding: {:?}", label));
+            continue;
+        };
+
+        match encoding.decode_without_bom_handling_and_without_replacement(bytes) {
+            Some(s) => {
+                if encoding != encoding_rs::UTF_8 {
+                    ui.log_info(format!("decoding feed as {}", encoding.name()));
+                }
+                return s.into_owned();
+            }
+            None => ui.log_warn(format!("feed isn't valid {}", encoding.name())),
         }
     }
+
+    let (s, had_errors) = encoding_rs::UTF_8.decode_without_bom_handling(bytes);
+    if had_errors {
+        ui.log_warn("feed isn't valid UTF-8, replacing invalid characters");
+    }
+    s.into_owned()
+}
+
+/// The `encoding` of the `<?xml ... ?>` declaration at the start of the document.
+fn xml_declared_encoding(bytes: &[u8]) -> Option<String> {
+    let head = &bytes[..bytes.len().min(256)];
+    let head = String::from_utf8_lossy(head);
+    let declaration = &head[head.find("<?xml")?..];
+    let declaration = &declaration[..declaration.find("?>")?];
+    let rest = &declaration[declaration.find("encoding")? + "encoding".len()..];
+    let rest = rest.trim_start().strip_prefix('=')?.trim_start();
+    let quote = rest.chars().next().filter(|c| *c == '"' || *c == '\'')?;
+    let rest = &rest[1..];
+    Some(rest[..rest.find(quote)?].to_string())
 }
 
-pub fn edit_file(path: &Path) {
+/// Opens a copy of the file in `$EDITOR` (or `$VISUAL`), and saves it over the file once
+/// `validate` accepts it. If it doesn't, the error is shown and the editor can be re-opened.
+pub fn edit_file(path: &Path, validate: fn(&Path) -> Result<(), String>) {
     if !path.exists() {
         eprintln!("error: path does not exist: {:?}", path);
+        std::process::exit(1);
     }
 
-    let editor = match std::env::var("EDITOR") {
+    let editor = match std::env::var("EDITOR").or_else(|_| std::env::var("VISUAL")) {
         Ok(editor) => editor,
         Err(_) => {
             eprintln!("Unable to edit {:?}", path);
@@ -192,10 +429,42 @@ pub fn edit_file(path: &Path) {
         }
     };
 
-    std::process::Command::new(editor)
-        .arg(path.to_str().unwrap())
-        .status()
-        .unwrap();
+    // Keeps the extension, so the copy is validated in the same format.
+    let file_name = path.file_name().unwrap().to_string_lossy();
+    let copy = path.with_file_name(format!(".edit.{}", file_name));
+    if let Err(e) = fs::copy(path, &copy) {
+        eprintln!("failed to copy {:?} to {:?}: {}", path, &copy, e);
+        std::process::exit(1);
+    }
+
+    let discard = |msg: &str| -> ! {
+        let _ = fs::remove_file(&copy);
+        eprintln!("{}, {:?} was left unchanged", msg, path);
+        std::process::exit(1);
+    };
+
+    loop {
+        match std::process::Command::new(&editor).arg(&copy).status() {
+            Ok(status) if status.success() => {}
+            Ok(status) => discard(&format!("{} exited with {}", &editor, status)),
+            Err(e) => discard(&format!("failed to run {}: {}", &editor, e)),
+        }
+
+        match validate(&copy) {
+            Ok(()) => break,
+            Err(e) => {
+                eprintln!("invalid {:?}:\n{}", path, e);
+                let answer = get_input(Some("re-open the editor? [Y/n]: "));
+                if answer.is_some_and(|answer| !answer.eq_ignore_ascii_case("y")) {
+                    discard("changes discarded");
+                }
+            }
+        }
+    }
+
+    if let Err(e) = fs::rename(&copy, path) {
+        discard(&format!("failed to save {:?}: {}", path, e));
+    }
 }
 
 pub fn replacer(val: Value, input: &str) -> String {
@@ -255,7 +524,7 @@ pub fn get_input(prompt: Option<&str>) -> Option<String> {
 }
 
 pub async fn search_podcasts(config: &config::GlobalConfig, query: String, catch_up: bool) {
-    let response = search(&query).await;
+    let response = search(&config.reqwest_client(), &query).await;
     let mut results = vec![];
 
     let mut idx = 0;
@@ -349,51 +618,123 @@ pub fn date_str_to_unix(date: &str) -> Result<time::Duration, String> {
     Ok(time::Duration::from_secs(secs as u64))
 }
 
-pub fn get_extension_from_response(response: &reqwest::Response, episode: &Episode) -> String {
-    let url = &episode.attrs.url();
-    let ext = match PathBuf::from(url)
-        .extension()
-        .and_then(|ext| ext.to_str().map(String::from))
-    {
-        Some(ext) => ext.to_string(),
-        None => {
-            let content_type = response
-                .headers()
-                .get(reqwest::header::CONTENT_TYPE)
-                .and_then(|ct| ct.to_str().ok())
-                .unwrap_or("application/octet-stream");
-
-            let extensions = mime_guess::get_mime_extensions_str(&content_type).unwrap();
-
-            match extensions.contains(&"mp3") {
-                true => "mp3".to_owned(),
-                false => extensions
-                    .first()
-                    .expect("extension not found.")
-                    .to_string(),
-            }
+/// The UTC offset of a date, if it's in a format that has one (RFC 2822 or RFC 3339).
+pub fn date_str_offset(date: &str) -> Option<chrono::FixedOffset> {
+    let date = date.trim();
+    chrono::DateTime::parse_from_rfc2822(date)
+        .or_else(|_| chrono::DateTime::parse_from_rfc3339(date))
+        .ok()
+        .map(|date| *date.offset())
+}
+
+/// Parses an `itunes:explicit` value. Feeds use several spellings, and `None` means it's unknown.
+pub fn parse_explicit(s: &str) -> Option<bool> {
+    match s.trim().to_lowercase().as_str() {
+        "yes" | "true" | "explicit" => Some(true),
+        "no" | "false" | "clean" => Some(false),
+        _ => None,
+    }
+}
+
+/// Resolves a relative url against the base url.
+///
+/// Returns `None` if the url is already absolute, or if it can't be resolved.
+pub fn resolve_relative_url(base: &str, url: &str) -> Option<String> {
+    match url::Url::parse(url) {
+        Err(url::ParseError::RelativeUrlWithoutBase) => {
+            let resolved = url::Url::parse(base).ok()?.join(url).ok()?;
+            Some(resolved.to_string())
         }
-    };
+        _ => None,
+    }
+}
+
+/// The extension of the downloaded episode, from the first of its `extension_source` that
+/// has one, and where it was taken from.
+///
+/// Falls back to `bin` if none of them have an extension.
+pub fn get_extension_from_response(
+    response: &reqwest::Response,
+    episode: &Episode,
+) -> (String, Option<config::ExtensionSource>) {
+    let preferred = &episode.config.preferred_extensions;
+    let content_type = response
+        .headers()
+        .get(reqwest::header::CONTENT_TYPE)
+        .and_then(|ct| ct.to_str().ok());
+
+    episode
+        .config
+        .extension_source
+        .iter()
+        .find_map(|source| {
+            let extension = match source {
+                config::ExtensionSource::ContentType => {
+                    content_type.and_then(|mime| extension_from_mime(mime, preferred))
+                }
+                _ => extension_from_feed(*source, episode),
+            };
+            Some((extension?, Some(*source)))
+        })
+        .unwrap_or_else(|| ("bin".to_string(), None))
+}
 
-    // Some urls have these arguments after the extension.
-    // feels a bit hacky.
-    // todo: find a cleaner way to extract extensions.
-    let ext = ext
-        .split_once("?")
-        .map(|(l, _)| l.to_string())
-        .unwrap_or(ext);
-    ext
+/// The extensions the feed suggests for the episode, in the order of `extension_source`, and
+/// the `bin` fallback.
+///
+/// Unlike [`get_extension_from_response`], this doesn't need a request.
+pub fn get_extensions_from_feed(episode: &Episode) -> Vec<String> {
+    episode
+        .config
+        .extension_source
+        .iter()
+        .filter_map(|source| extension_from_feed(*source, episode))
+        .chain(["bin".to_string()])
+        .collect()
+}
+
+/// The extension from the url or the enclosure type. `None` for the content type.
+fn extension_from_feed(source: config::ExtensionSource, episode: &Episode) -> Option<String> {
+    let preferred = &episode.config.preferred_extensions;
+    match source {
+        config::ExtensionSource::Url => extension_from_url(episode.attrs.url()),
+        config::ExtensionSource::ContentType => None,
+        config::ExtensionSource::EnclosureType => episode
+            .attrs
+            .mime
+            .as_deref()
+            .and_then(|mime| extension_from_mime(mime, preferred)),
+    }
+}
+
+/// The extension at the end of the path of `url`, ignoring its query.
+fn extension_from_url(url: &str) -> Option<String> {
+    let url = url::Url::parse(url).ok()?;
+    let extension = Path::new(url.path()).extension()?.to_str()?;
+    (!extension.is_empty()).then(|| extension.to_string())
+}
+
+/// The extension of the mime type, picking the `preferred` ones first if it has several.
+fn extension_from_mime(mime: &str, preferred: &[String]) -> Option<String> {
+    let mime = mime.split(';').next().unwrap_or(mime).trim();
+    let extensions = mime_guess::get_mime_extensions_str(mime)?;
+
+    preferred
+        .iter()
+        .find(|ext| extensions.contains(&ext.as_str()))
+        .cloned()
+        .or_else(|| extensions.first().map(ToString::to_string))
 }
 
 use percent_encoding::{utf8_percent_encode, NON_ALPHANUMERIC};
 
-pub async fn search(terms: &str) -> Vec<Value> {
+pub async fn search(client: &reqwest::Client, terms: &str) -> Vec<Value> {
     let encoded: String = utf8_percent_encode(terms, NON_ALPHANUMERIC).to_string();
     let url = format!(
         "https://itunes.apple.com/search?media=podcast&entity=podcast&term={}",
         encoded
     );
-    let resp = reqwest::get(&url).await.unwrap().text().await.unwrap();
+    let resp = client.get(&url).send().await.unwrap().text().await.unwrap();
 
     serde_json::from_str::<serde_json::Value>(&resp)
         .unwrap()
@@ -417,6 +758,115 @@ pub fn val_to_str<'a>(val: &'a serde_json::Value) -> Option<&'a str> {
     obj.get("#text")?.as_str()
 }
 
+/// The text of `val`, which can also be a number or boolean. The first element with text is
+/// used if there are several.
+pub fn val_to_text(val: &serde_json::Value) -> Option<String> {
+    match val {
+        Value::String(s) => Some(s.clone()),
+        Value::Number(num) => Some(num.to_string()),
+        Value::Bool(b) => Some(b.to_string()),
+        Value::Array(vals) => vals.iter().find_map(val_to_text),
+        Value::Object(obj) => val_to_text(obj.get("@text").or_else(|| obj.get("#text"))?),
+        Value::Null => None,
+    }
+}
+
+/// The text of the XML element `key` in `map`, like `itunes:duration`, or of its attribute
+/// with `tag@attribute`, like `enclosure@url`.
+pub fn xml_lookup(map: &serde_json::Map<String, Value>, key: &str) -> Option<String> {
+    let (tag, attribute) = match key.split_once('@') {
+        Some((tag, attribute)) => (tag, Some(attribute)),
+        None => (key, None),
+    };
+
+    let val = map.get(tag)?;
+    let Some(attribute) = attribute else {
+        return val_to_text(val);
+    };
+
+    let attribute = format!("@{}", attribute);
+    match val {
+        Value::Array(vals) => vals
+            .iter()
+            .find_map(|val| val_to_text(val.get(&attribute)?)),
+        val => val_to_text(val.get(&attribute)?),
+    }
+}
+
+pub fn val_to_u64(val: &serde_json::Value) -> Option<u64> {
+    if let Some(num) = val.as_u64() {
+        return Some(num);
+    }
+
+    if let Some(num) = val.as_f64() {
+        return Some(num as u64);
+    }
+
+    val_to_str(val)?.trim().parse().ok()
+}
+
+/// Parses a duration given in seconds, or in the `HH:MM:SS` or `MM:SS` formats.
+pub fn parse_duration_secs(s: &str) -> Option<u64> {
+    let parts: Vec<&str> = s.trim().split(':').collect();
+    if parts.len() > 3 {
+        return None;
+    }
+
+    let mut secs = 0;
+    for part in parts {
+        let part = part.trim().parse::<f64>().ok().filter(|part| *part >= 0.)?;
+        secs = secs * 60 + part as u64;
+    }
+
+    Some(secs)
+}
+
+/// Parses a duration like `30m`, `12h`, `2d` or `1w`. Plain numbers are days.
+pub fn parse_interval(s: &str) -> Option<Unix> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num = num.parse::<f64>().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "s" => 1,
+        "m" => 60,
+        "h" => 3600,
+        "" | "d" => 86400,
+        "w" => 7 * 86400,
+        _ => return None,
+    };
+
+    Some(Unix::from_secs((num * multiplier as f64) as u64))
+}
+
+/// Parses a file size like `500MB` or `2GiB` into bytes. Plain numbers are bytes.
+pub fn parse_size(s: &str) -> Option<u64> {
+    let s = s.trim();
+    let split = s
+        .find(|c: char| !(c.is_ascii_digit() || c == '.'))
+        .unwrap_or(s.len());
+    let (num, unit) = s.split_at(split);
+    let num = num.parse::<f64>().ok()?;
+
+    let multiplier: u64 = match unit.trim().to_lowercase().as_str() {
+        "" | "b" => 1,
+        "k" | "kb" => 1000,
+        "m" | "mb" => 1000u64.pow(2),
+        "g" | "gb" => 1000u64.pow(3),
+        "t" | "tb" => 1000u64.pow(4),
+        "kib" => 1024,
+        "mib" => 1024u64.pow(2),
+        "gib" => 1024u64.pow(3),
+        "tib" => 1024u64.pow(4),
+        _ => return None,
+    };
+
+    Some((num * multiplier as f64) as u64)
+}
+
 pub fn val_to_url<'a>(val: &'a serde_json::Value) -> Option<&'a str> {
     if let Some(val) = val.as_str() {
         return Some(val);
@@ -432,6 +882,10 @@ pub fn val_to_url<'a>(val: &'a serde_json::Value) -> Option<&'a str> {
         return url.as_str();
     }
 
+    if let Some(url) = obj.get("@url") {
+        return url.as_str();
+    }
+
     if let Some(url) = obj.get("src") {
         return url.as_str();
     }
@@ -477,6 +931,32 @@ pub fn append_to_config(file_path: &Path, key: &str, value: &str) -> io::Result<
     Ok(())
 }
 
+/// The raw bytes of `path`, which can be any bytes but NUL on unix.
+#[cfg(unix)]
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+    use std::os::unix::ffi::OsStrExt;
+    path.as_os_str().as_bytes().to_vec()
+}
+
+#[cfg(not(unix))]
+pub fn path_bytes(path: &Path) -> Vec<u8> {
+    path.to_string_lossy().into_owned().into_bytes()
+}
+
+/// Like [`create_dir`], but returns the error instead of exiting.
+pub fn try_create_dir(path: &Path) -> Result<(), String> {
+    fs::create_dir_all(path).map_err(|e| format!("failed to create directory {:?}: {}", path, e))
+}
+
+/// Fails if files can't be created in the directory, like on a read-only mount.
+pub fn check_writable(dir: &Path) -> Result<(), String> {
+    let probe = dir.join(format!(".{}-write-test", crate::APPNAME));
+    fs::File::create(&probe)
+        .map_err(|e| format!("download directory {:?} is not writable: {}", dir, e))?;
+    let _ = fs::remove_file(&probe);
+    Ok(())
+}
+
 pub fn create_dir(path: &Path) {
     if let Err(e) = fs::create_dir_all(path) {
         eprintln!("failed to create following directory: {:?}", path);
@@ -496,3 +976,120 @@ pub fn _log_warn<E: std::fmt::Debug>(ui: &DownloadBar, msg: &str, error: E) -> E
     ui.log_warn(&msg);
     error
 }
+
+#[cfg(test)]
+mod tests {
+    use super::*;
+
+    #[test]
+    fn fit_file_name_keeps_short_names() {
+        let dir = Path::new("/podcasts/show");
+        assert_eq!(
+            fit_file_name("Episode 1", Some("mp3"), dir, 255, 4096),
+            "Episode 1.mp3"
+        );
+        assert_eq!(
+            fit_file_name("Episode 1", None, dir, 255, 4096),
+            "Episode 1"
+        );
+    }
+
+    #[test]
+    fn fit_file_name_truncates_long_titles() {
+        let dir = Path::new("/podcasts/show");
+        let name = fit_file_name(&"a".repeat(300), Some("mp3"), dir, 255, 4096);
+        assert_eq!(name.len(), 255);
+        assert!(name.ends_with("a.mp3"));
+
+        // "/podcasts/show/" takes 15 of the 40 bytes of the path.
+        let name = fit_file_name(&"a".repeat(300), Some("mp3"), dir, 255, 40);
+        assert_eq!(name, format!("{}.mp3", "a".repeat(21)));
+
+        // Spaces left at the end of the cut are dropped.
+        let name = fit_file_name("Episode one", Some("mp3"), dir, 12, 4096);
+        assert_eq!(name, "Episode.mp3");
+    }
+
+    #[test]
+    fn fit_file_name_cuts_multibyte_titles_at_char_boundaries() {
+        let dir = Path::new("/podcasts/show");
+        let name = fit_file_name(&"é".repeat(200), Some("mp3"), dir, 255, 4096);
+        assert_eq!(name, format!("{}.mp3", "é".repeat(125)));
+
+        // The first character is kept even if it doesn't fit.
+        let name = fit_file_name("日本語", Some("mp3"), dir, 6, 4096);
+        assert_eq!(name, "日.mp3");
+    }
+
+    #[test]
+    fn decode_xml_uses_header_charset() {
+        let ui = DownloadBar::hidden("test".to_string());
+        let bytes = b"<rss><title>Caf\xe9</title></rss>";
+        let content_type = Some("application/rss+xml; charset=\"ISO-8859-1\"");
+        assert_eq!(
+            decode_xml(bytes, content_type, &ui),
+            "<rss><title>Café</title></rss>"
+        );
+    }
+
+    #[test]
+    fn decode_xml_prefers_declared_encoding() {
+        let ui = DownloadBar::hidden("test".to_string());
+        let bytes = b"<?xml version=\"1.0\" encoding=\"windows-1252\"?><title>\x93Hi\x94</title>";
+        let decoded = decode_xml(bytes, Some("text/xml; charset=utf-8"), &ui);
+        assert!(decoded.ends_with("<title>“Hi”</title>"));
+
+        // A UTF-16 byte order mark overrides both.
+        let bytes = [0xff, 0xfe, b'<', 0, b'a', 0, b'/', 0, b'>', 0];
+        assert_eq!(
+            decode_xml(&bytes, Some("text/xml; charset=utf-8"), &ui),
+            "<a/>"
+        );
+    }
+
+    #[test]
+    fn decode_xml_falls_back_to_utf8() {
+        let ui = DownloadBar::hidden("test".to_string());
+        let bytes = "<?xml version=\"1.0\" encoding=\"bogus\"?><title>Café</title>";
+        assert!(decode_xml(bytes.as_bytes(), None, &ui).ends_with("<title>Café</title>"));
+
+        // Bytes that aren't valid in the declared encoding skip it.
+        let bytes = "<?xml version=\"1.0\" encoding=\"euc-jp\"?><title>日本</title>";
+        let decoded = decode_xml(bytes.as_bytes(), None, &ui);
+        assert!(decoded.ends_with("<title>日本</title>"));
+
+        let decoded = decode_xml(b"<title>\xff</title>", None, &ui);
+        assert_eq!(decoded, "<title>\u{fffd}</title>");
+    }
+
+    #[test]
+    fn try_create_dir_fails_under_a_file() {
+        let dir = crate::testing::temp_dir("create-dir");
+        let file = dir.join("file");
+        fs::write(&file, "").unwrap();
+
+        assert!(try_create_dir(&dir.join("a").join("b")).is_ok());
+        assert!(try_create_dir(&file.join("sub")).is_err());
+        assert!(check_writable(&file.join("sub")).is_err());
+    }
+
+    #[cfg(unix)]
+    #[test]
+    fn check_writable_fails_for_read_only_dir() {
+        use std::os::unix::fs::PermissionsExt;
+
+        let dir = crate::testing::temp_dir("read-only");
+        assert!(check_writable(&dir).is_ok());
+
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o555)).unwrap();
+        // Permissions don't stop root from writing.
+        let writable = fs::File::create(dir.join("probe")).is_ok();
+        let result = check_writable(&dir);
+        fs::set_permissions(&dir, fs::Permissions::from_mode(0o755)).unwrap();
+
+        if !writable {
+            let e = result.unwrap_err();
+            assert!(e.contains("is not writable"), "{}", e);
+        }
+    }
+}